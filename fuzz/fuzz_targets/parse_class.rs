@@ -0,0 +1,27 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use rubust::class::attribute::AttributeValue;
+use rubust::class::class::Class;
+use rubust::class::op::parse_code;
+use rubust::class::view::ClassView;
+use rubust::io::Readable;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(class) = Class::read(&mut Cursor::new(data)) {
+        for method in &class.methods {
+            for attribute in &method.attributes {
+                if let AttributeValue::Code(code) = &attribute.value {
+                    let _ = parse_code(&code.code);
+                }
+            }
+        }
+    }
+    if let Ok(view) = ClassView::parse(data) {
+        let _ = view.to_class();
+    }
+    // The raw input is also treated as bytecode directly
+    let _ = parse_code(data);
+});
@@ -1,55 +1,70 @@
-#![allow(dead_code)]
-
+//! Access flags of classes, members, inner classes, parameters and
+//! modules. The same bit means different things depending on what the
+//! flags belong to (e.g. 0x0040 is ACC_VOLATILE on a field but ACC_BRIDGE
+//! on a method) so each kind of structure has its own set of flags
 use std::fmt::{Debug, Formatter};
-use std::io::Read;
+use std::io::{Read, Write};
+
+use crate::io::{Readable, ReadResult, Writable, WriteResult};
+
+/// A single flag from one of the sets of access flags
+pub trait AccessFlag: Copy + Debug + 'static {
+    // Every flag in the set in the order javap lists them
+    const ALL: &'static [Self];
 
-use crate::io::{Readable, ReadResult};
+    fn value(&self) -> u16;
+
+    /// The name of the flag in the JVMS (e.g. ACC_PUBLIC)
+    fn name(&self) -> &'static str;
+
+    /// The modifier keyword for the flag in a declaration. None for flags
+    /// which can't be written in source (e.g. ACC_SYNTHETIC)
+    fn keyword(&self) -> Option<&'static str>;
+}
 
 macro_rules! access_flags {
+    (@keyword $Keyword:literal) => { Some($Keyword) };
+    (@keyword) => { None };
     (
         $(
-            $FieldName:ident = $FieldValue:literal
-        ),* $(,)?
+            $(#[$Meta:meta])*
+            enum $Name:ident {
+                $(
+                    $FieldName:ident = $FieldValue:literal $JvmName:literal $(=> $Keyword:literal)?
+                ),* $(,)?
+            }
+        )*
     ) => {
-        #[derive(Copy, Clone)]
-        pub enum AccessFlag {
-            $($FieldName,)*
-        }
-
-        impl Debug for AccessFlag {
-            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-                f.write_str(self.name())
+        $(
+            $(#[$Meta])*
+            #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+            #[repr(u16)]
+            pub enum $Name {
+                $($FieldName = $FieldValue,)*
             }
-        }
 
-        impl AccessFlag {
-            fn name(&self) -> &'static str {
-                match self {
-                    $(AccessFlag::$FieldName => stringify!($FieldName),)*
-                }
-            }
+            impl AccessFlag for $Name {
+                const ALL: &'static [Self] = &[$($Name::$FieldName,)*];
+
+                fn value(&self) -> u16 { *self as u16 }
 
-            fn value(&self) -> u16 {
-                match self {
-                    $(AccessFlag::$FieldName => $FieldValue,)*
+                fn name(&self) -> &'static str {
+                    match self {
+                        $($Name::$FieldName => $JvmName,)*
+                    }
                 }
-            }
 
-            fn get_values(value: &AccessFlags) -> Vec<Self> where Self: Sized {
-                let v = value.0;
-                let mut out = Vec::new();
-                $(
-                    if v & $FieldValue == $FieldValue {
-                        out.push(AccessFlag::$FieldName);
+                fn keyword(&self) -> Option<&'static str> {
+                    match self {
+                        $($Name::$FieldName => access_flags!(@keyword $($Keyword)?),)*
                     }
-                )*
-                out
+                }
             }
-        }
+        )*
     };
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub struct AccessFlags(pub u16);
 
 impl Readable for AccessFlags {
@@ -58,46 +73,153 @@ impl Readable for AccessFlags {
     }
 }
 
+impl Writable for AccessFlags {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        self.0.write(o)
+    }
+}
+
+// The names of the flags depend on what they belong to so only the raw
+// value is shown
 impl Debug for AccessFlags {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let values: Vec<&'static str> = AccessFlag::get_values(self).iter()
-            .map(|x| x.name()).collect();
-        f.write_str(format!("AccessFlags ({:#06x}, [{}])", self.0, values.join(", ").as_str())
-            .as_str())
+        write!(f, "AccessFlags({:#06x})", self.0)
+    }
+}
+
+impl Default for AccessFlags {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl AccessFlags {
     pub fn new() -> AccessFlags { AccessFlags(0) }
 
-    pub fn set(&mut self, value: AccessFlag) {
-        let sv = value.value();
-        self.0 = self.0 | sv
+    pub fn set<F: AccessFlag>(&mut self, flag: F) {
+        self.0 |= flag.value()
+    }
+
+    pub fn unset<F: AccessFlag>(&mut self, flag: F) {
+        self.0 &= !flag.value()
+    }
+
+    pub fn is_set<F: AccessFlag>(&self, flag: F) -> bool {
+        let value = flag.value();
+        self.0 & value == value
+    }
+
+    /// A copy of the flags without the flag
+    pub fn without<F: AccessFlag>(&self, flag: F) -> AccessFlags {
+        AccessFlags(self.0 & !flag.value())
+    }
+
+    /// The flags of the set which are present
+    pub fn flags<F: AccessFlag>(&self) -> Vec<F> {
+        F::ALL.iter()
+            .filter(|flag| self.is_set(**flag))
+            .copied()
+            .collect()
     }
 
-    pub fn is_set(&self, value: AccessFlag) -> bool {
-        let sv = value.value();
-        self.0 & sv == sv
+    /// The modifier keywords of the present flags each followed by a space
+    /// (e.g. "public static ")
+    pub fn modifiers<F: AccessFlag>(&self) -> String {
+        self.flags::<F>().iter()
+            .filter_map(|flag| flag.keyword())
+            .map(|keyword| format!("{} ", keyword))
+            .collect()
     }
 }
 
 access_flags! {
-    Public = 0x0001,
-    Private = 0x0002,
-    Protected = 0x0004,
-    Static = 0x0008,
-    Final = 0x0010,
-    Super = 0x0020,
-    Synchronized = 0x0020,
-    Interface = 0x0200,
-    Volatile = 0x0040,
-    Bridge = 0x0040,
-    Transient = 0x0080,
-    Varargs = 0x0080,
-    Native = 0x0100,
-    Abstract = 0x0400,
-    Strict = 0x0800,
-    Synthetic = 0x1000,
-    Annotation = 0x2000,
-    Enum = 0x4000,
-}
\ No newline at end of file
+    /// Flags of a ClassFile (JVMS 4.1)
+    enum ClassAccessFlag {
+        Public = 0x0001 "ACC_PUBLIC" => "public",
+        Final = 0x0010 "ACC_FINAL" => "final",
+        Super = 0x0020 "ACC_SUPER",
+        Interface = 0x0200 "ACC_INTERFACE",
+        Abstract = 0x0400 "ACC_ABSTRACT" => "abstract",
+        Synthetic = 0x1000 "ACC_SYNTHETIC",
+        Annotation = 0x2000 "ACC_ANNOTATION",
+        Enum = 0x4000 "ACC_ENUM",
+        Module = 0x8000 "ACC_MODULE",
+    }
+
+    /// Flags of a field_info (JVMS 4.5)
+    enum FieldAccessFlag {
+        Public = 0x0001 "ACC_PUBLIC" => "public",
+        Private = 0x0002 "ACC_PRIVATE" => "private",
+        Protected = 0x0004 "ACC_PROTECTED" => "protected",
+        Static = 0x0008 "ACC_STATIC" => "static",
+        Final = 0x0010 "ACC_FINAL" => "final",
+        Volatile = 0x0040 "ACC_VOLATILE" => "volatile",
+        Transient = 0x0080 "ACC_TRANSIENT" => "transient",
+        Synthetic = 0x1000 "ACC_SYNTHETIC",
+        Enum = 0x4000 "ACC_ENUM",
+    }
+
+    /// Flags of a method_info (JVMS 4.6)
+    enum MethodAccessFlag {
+        Public = 0x0001 "ACC_PUBLIC" => "public",
+        Private = 0x0002 "ACC_PRIVATE" => "private",
+        Protected = 0x0004 "ACC_PROTECTED" => "protected",
+        Static = 0x0008 "ACC_STATIC" => "static",
+        Final = 0x0010 "ACC_FINAL" => "final",
+        Synchronized = 0x0020 "ACC_SYNCHRONIZED" => "synchronized",
+        Bridge = 0x0040 "ACC_BRIDGE",
+        Varargs = 0x0080 "ACC_VARARGS",
+        Native = 0x0100 "ACC_NATIVE" => "native",
+        Abstract = 0x0400 "ACC_ABSTRACT" => "abstract",
+        Strict = 0x0800 "ACC_STRICT" => "strictfp",
+        Synthetic = 0x1000 "ACC_SYNTHETIC",
+    }
+
+    /// Flags of an entry of the InnerClasses attribute (JVMS 4.7.6). These
+    /// are the modifiers the class was declared with in source
+    enum InnerClassAccessFlag {
+        Public = 0x0001 "ACC_PUBLIC" => "public",
+        Private = 0x0002 "ACC_PRIVATE" => "private",
+        Protected = 0x0004 "ACC_PROTECTED" => "protected",
+        Static = 0x0008 "ACC_STATIC" => "static",
+        Final = 0x0010 "ACC_FINAL" => "final",
+        Interface = 0x0200 "ACC_INTERFACE",
+        Abstract = 0x0400 "ACC_ABSTRACT" => "abstract",
+        Synthetic = 0x1000 "ACC_SYNTHETIC",
+        Annotation = 0x2000 "ACC_ANNOTATION",
+        Enum = 0x4000 "ACC_ENUM",
+    }
+
+    /// Flags of an entry of the MethodParameters attribute (JVMS 4.7.24)
+    enum ParameterAccessFlag {
+        Final = 0x0010 "ACC_FINAL" => "final",
+        Synthetic = 0x1000 "ACC_SYNTHETIC",
+        // Implicitly declared by the language (e.g. the name parameter of
+        // Enum.valueOf)
+        Mandated = 0x8000 "ACC_MANDATED",
+    }
+
+    /// Flags of the Module attribute itself (JVMS 4.7.25). Listed in the
+    /// same order as javap rather than by value
+    enum ModuleAccessFlag {
+        Open = 0x0020 "ACC_OPEN" => "open",
+        Mandated = 0x8000 "ACC_MANDATED",
+        Synthetic = 0x1000 "ACC_SYNTHETIC",
+    }
+
+    /// Flags of a requires directive of a module
+    enum RequiresAccessFlag {
+        Transitive = 0x0020 "ACC_TRANSITIVE" => "transitive",
+        // requires static
+        StaticPhase = 0x0040 "ACC_STATIC_PHASE" => "static",
+        Synthetic = 0x1000 "ACC_SYNTHETIC",
+        Mandated = 0x8000 "ACC_MANDATED",
+    }
+
+    /// Flags of an exports or opens directive of a module. Listed in the
+    /// same order as javap rather than by value
+    enum PackageAccessFlag {
+        Mandated = 0x8000 "ACC_MANDATED",
+        Synthetic = 0x1000 "ACC_SYNTHETIC",
+    }
+}
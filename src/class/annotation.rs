@@ -0,0 +1,436 @@
+//! Annotations stored in the RuntimeVisible/RuntimeInvisible annotation
+//! attributes along with the element values they (and AnnotationDefault)
+//! are made up of. Type annotations (JSR 308) additionally record which
+//! use of a type within the declaration or code they apply to
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+use crate::class::attribute::{Attribute, AttributeValue};
+use crate::class::constant::{ConstantPool, PoolIndex};
+use crate::class::descriptor::Descriptor;
+use crate::error::{ConstantError, ReadError};
+use crate::io::{ParseLimits, Readable, ReadResult, VecReadableFn, VecReadableSize, VecWritableFn, VecWritableSize, Writable, WriteResult};
+use crate::readable_struct;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Annotation {
+    // Index of the field descriptor of the annotation interface (e.g. Ljava/lang/Deprecated;)
+    pub type_index: PoolIndex,
+    // The element_name_index of each element paired with its value
+    pub elements: Vec<(PoolIndex, ElementValue)>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ElementValue {
+    // Primitive or string constant. The tag is one of B, C, D, F, I, J, S, Z or s
+    // (String) and determines the type of constant at the index
+    Const { tag: u8, const_value_index: PoolIndex },
+    // type_name_index is the field descriptor of the enum class
+    Enum { type_name_index: PoolIndex, const_name_index: PoolIndex },
+    // class_info_index is a return descriptor so can be V for void.class
+    Class(PoolIndex),
+    Annotation(Annotation),
+    Array(Vec<ElementValue>),
+}
+
+impl Annotation {
+    /// Reads an annotation. Nested annotations and arrays are limited
+    /// by the nesting depth of the provided limits
+    pub fn read<R: Read>(i: &mut R, limits: &ParseLimits) -> ReadResult<Annotation> {
+        let type_index = PoolIndex::read(i)?;
+        let elements = u16::read_vec_closure(i, |r| -> ReadResult<(PoolIndex, ElementValue)> {
+            let name_index = PoolIndex::read(r)?;
+            let value = ElementValue::read(r, limits)
+                .map_err(|err| err.in_field("value"))?;
+            Ok((name_index, value))
+        }).map_err(|err| err.in_field("element_value_pairs"))?;
+        Ok(Annotation { type_index, elements })
+    }
+
+    /// The type of the annotation interface
+    pub fn type_descriptor(&self, constant_pool: &ConstantPool) -> Result<Descriptor, ConstantError> {
+        Ok(Descriptor::parse(constant_pool.get_utf8(&self.type_index)?))
+    }
+
+    /// Finds the value of the element with the provided name. Elements
+    /// using their default value aren't present in the annotation
+    pub fn element(&self, constant_pool: &ConstantPool, name: &str) -> Option<&ElementValue> {
+        self.elements.iter()
+            .find(|(name_index, _)| constant_pool.get_utf8(name_index)
+                .is_ok_and(|value| value == name))
+            .map(|(_, value)| value)
+    }
+}
+
+impl Writable for Annotation {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        self.type_index.write(o)?;
+        u16::write_vec_closure(o, &self.elements, |o, (name_index, value)| {
+            name_index.write(o)?;
+            value.write(o)
+        })
+    }
+}
+
+impl ElementValue {
+    pub fn read<R: Read>(i: &mut R, limits: &ParseLimits) -> ReadResult<ElementValue> {
+        let tag = u8::read(i)?;
+        Ok(match tag {
+            b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' | b's' => ElementValue::Const {
+                tag,
+                const_value_index: PoolIndex::read(i)?,
+            },
+            b'e' => ElementValue::Enum {
+                type_name_index: PoolIndex::read(i)?,
+                const_name_index: PoolIndex::read(i)?,
+            },
+            b'c' => ElementValue::Class(PoolIndex::read(i)?),
+            b'@' => ElementValue::Annotation(Annotation::read(i, &limits.nested()?)
+                .map_err(|err| err.in_field("annotation_value"))?),
+            b'[' => {
+                let nested_limits = limits.nested()?;
+                ElementValue::Array(u16::read_vec_closure(i, |r| ElementValue::read(r, &nested_limits))
+                    .map_err(|err| err.in_field("array_value"))?)
+            }
+            tag => Err(ReadError::UnknownElementValueTag(tag))?
+        })
+    }
+
+    /// The tag character identifying the kind of value
+    pub fn tag(&self) -> u8 {
+        match self {
+            ElementValue::Const { tag, .. } => *tag,
+            ElementValue::Enum { .. } => b'e',
+            ElementValue::Class(_) => b'c',
+            ElementValue::Annotation(_) => b'@',
+            ElementValue::Array(_) => b'[',
+        }
+    }
+}
+
+impl Writable for ElementValue {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        self.tag().write(o)?;
+        match self {
+            ElementValue::Const { const_value_index, .. } => const_value_index.write(o),
+            ElementValue::Enum { type_name_index, const_name_index } => {
+                type_name_index.write(o)?;
+                const_name_index.write(o)
+            }
+            ElementValue::Class(index) => index.write(o),
+            ElementValue::Annotation(value) => value.write(o),
+            ElementValue::Array(values) => u16::write_vec(o, values),
+        }
+    }
+}
+
+/// Reads the contents of a RuntimeVisibleAnnotations or RuntimeInvisibleAnnotations attribute
+pub fn read_annotations<R: Read>(i: &mut R, limits: &ParseLimits) -> ReadResult<Vec<Annotation>> {
+    u16::read_vec_closure(i, |r| Annotation::read(r, limits))
+        .map_err(|err| err.in_field("annotations"))
+}
+
+/// Reads the contents of a RuntimeVisibleParameterAnnotations or
+/// RuntimeInvisibleParameterAnnotations attribute. There is one list
+/// of annotations for each parameter
+pub fn read_parameter_annotations<R: Read>(i: &mut R, limits: &ParseLimits) -> ReadResult<Vec<Vec<Annotation>>> {
+    u8::read_vec_closure(i, |r| read_annotations(r, limits))
+        .map_err(|err| err.in_field("parameter_annotations"))
+}
+
+/// The annotations in both the visible and invisible annotation
+/// attributes of a class, field or method
+pub fn annotations(attributes: &[Attribute]) -> impl Iterator<Item=&Annotation> {
+    attributes.iter()
+        .filter_map(|attribute| match &attribute.value {
+            AttributeValue::RuntimeVisibleAnnotations(values) |
+            AttributeValue::RuntimeInvisibleAnnotations(values) => Some(values),
+            _ => None
+        })
+        .flatten()
+}
+
+/// The visible and invisible annotations of the method parameter at the
+/// provided index of the descriptor's `parameter_count` parameters. The
+/// attributes can cover fewer parameters than the descriptor as compilers
+/// may leave out synthetic ones (e.g. the outer instance passed to inner
+/// class constructors) so they are aligned to the last parameter
+pub fn parameter_annotations(
+    attributes: &[Attribute],
+    parameter: usize,
+    parameter_count: usize,
+) -> impl Iterator<Item=&Annotation> {
+    attributes.iter()
+        .filter_map(move |attribute| match &attribute.value {
+            AttributeValue::RuntimeVisibleParameterAnnotations(values) |
+            AttributeValue::RuntimeInvisibleParameterAnnotations(values) => {
+                let skipped = parameter_count.checked_sub(values.len())?;
+                values.get(parameter.checked_sub(skipped)?)
+            }
+            _ => None
+        })
+        .flatten()
+}
+
+/// The kind of type use a type annotation applies to (JVMS 4.7.20)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum TargetType {
+    ClassTypeParameter = 0x00,
+    MethodTypeParameter = 0x01,
+    // Superclass or interface in the extends or implements clause
+    ClassExtends = 0x10,
+    ClassTypeParameterBound = 0x11,
+    MethodTypeParameterBound = 0x12,
+    Field = 0x13,
+    // Return type of a method or the type of a newly constructed object
+    MethodReturn = 0x14,
+    MethodReceiver = 0x15,
+    MethodFormalParameter = 0x16,
+    Throws = 0x17,
+    LocalVariable = 0x40,
+    ResourceVariable = 0x41,
+    ExceptionParameter = 0x42,
+    InstanceOf = 0x43,
+    New = 0x44,
+    // Method references using ::new
+    ConstructorReference = 0x45,
+    // Method references using ::Identifier
+    MethodReference = 0x46,
+    Cast = 0x47,
+    ConstructorInvocationTypeArgument = 0x48,
+    MethodInvocationTypeArgument = 0x49,
+    ConstructorReferenceTypeArgument = 0x4A,
+    MethodReferenceTypeArgument = 0x4B,
+}
+
+impl TargetType {
+    /// The name used by javap (e.g. METHOD_FORMAL_PARAMETER)
+    pub fn name(&self) -> &'static str {
+        match self {
+            TargetType::ClassTypeParameter => "CLASS_TYPE_PARAMETER",
+            TargetType::MethodTypeParameter => "METHOD_TYPE_PARAMETER",
+            TargetType::ClassExtends => "CLASS_EXTENDS",
+            TargetType::ClassTypeParameterBound => "CLASS_TYPE_PARAMETER_BOUND",
+            TargetType::MethodTypeParameterBound => "METHOD_TYPE_PARAMETER_BOUND",
+            TargetType::Field => "FIELD",
+            TargetType::MethodReturn => "METHOD_RETURN",
+            TargetType::MethodReceiver => "METHOD_RECEIVER",
+            TargetType::MethodFormalParameter => "METHOD_FORMAL_PARAMETER",
+            TargetType::Throws => "THROWS",
+            TargetType::LocalVariable => "LOCAL_VARIABLE",
+            TargetType::ResourceVariable => "RESOURCE_VARIABLE",
+            TargetType::ExceptionParameter => "EXCEPTION_PARAMETER",
+            TargetType::InstanceOf => "INSTANCEOF",
+            TargetType::New => "NEW",
+            TargetType::ConstructorReference => "CONSTRUCTOR_REFERENCE",
+            TargetType::MethodReference => "METHOD_REFERENCE",
+            TargetType::Cast => "CAST",
+            TargetType::ConstructorInvocationTypeArgument => "CONSTRUCTOR_INVOCATION_TYPE_ARGUMENT",
+            TargetType::MethodInvocationTypeArgument => "METHOD_INVOCATION_TYPE_ARGUMENT",
+            TargetType::ConstructorReferenceTypeArgument => "CONSTRUCTOR_REFERENCE_TYPE_ARGUMENT",
+            TargetType::MethodReferenceTypeArgument => "METHOD_REFERENCE_TYPE_ARGUMENT",
+        }
+    }
+}
+
+readable_struct! {
+    // The range of code a local variable holds a value in
+    struct LocalVariableTarget {
+        start_pc: u16,
+        length: u16,
+        index: u16,
+    }
+}
+
+/// Identifies which type in a declaration or expression is annotated.
+/// The variant used is determined by the target type
+#[derive(Debug, Clone)]
+pub enum TargetInfo {
+    TypeParameter { type_parameter_index: u8 },
+    // Index into the interfaces of the class or 65535 for the superclass
+    Supertype { supertype_index: u16 },
+    TypeParameterBound { type_parameter_index: u8, bound_index: u8 },
+    // Field, method return and receiver targets need no extra information
+    Empty,
+    FormalParameter { formal_parameter_index: u8 },
+    // Index into the Exceptions attribute of the method
+    Throws { throws_type_index: u16 },
+    // The ranges a (possibly split) local variable is live for
+    LocalVariable(Vec<LocalVariableTarget>),
+    // Index into the exception table of the Code attribute
+    Catch { exception_table_index: u16 },
+    // Offset of the instruction the annotated expression compiled to
+    Offset { offset: u16 },
+    TypeArgument { offset: u16, type_argument_index: u8 },
+}
+
+/// The supertype_index of annotations on the superclass
+pub const SUPERCLASS_INDEX: u16 = 65535;
+
+impl TargetInfo {
+    pub fn read<R: Read>(i: &mut R, target_type: TargetType) -> ReadResult<TargetInfo> {
+        Ok(match target_type {
+            TargetType::ClassTypeParameter |
+            TargetType::MethodTypeParameter => TargetInfo::TypeParameter { type_parameter_index: u8::read(i)? },
+            TargetType::ClassExtends => TargetInfo::Supertype { supertype_index: u16::read(i)? },
+            TargetType::ClassTypeParameterBound |
+            TargetType::MethodTypeParameterBound => TargetInfo::TypeParameterBound {
+                type_parameter_index: u8::read(i)?,
+                bound_index: u8::read(i)?,
+            },
+            TargetType::Field |
+            TargetType::MethodReturn |
+            TargetType::MethodReceiver => TargetInfo::Empty,
+            TargetType::MethodFormalParameter => TargetInfo::FormalParameter { formal_parameter_index: u8::read(i)? },
+            TargetType::Throws => TargetInfo::Throws { throws_type_index: u16::read(i)? },
+            TargetType::LocalVariable |
+            TargetType::ResourceVariable => TargetInfo::LocalVariable(u16::read_vec(i)?),
+            TargetType::ExceptionParameter => TargetInfo::Catch { exception_table_index: u16::read(i)? },
+            TargetType::InstanceOf |
+            TargetType::New |
+            TargetType::ConstructorReference |
+            TargetType::MethodReference => TargetInfo::Offset { offset: u16::read(i)? },
+            TargetType::Cast |
+            TargetType::ConstructorInvocationTypeArgument |
+            TargetType::MethodInvocationTypeArgument |
+            TargetType::ConstructorReferenceTypeArgument |
+            TargetType::MethodReferenceTypeArgument => TargetInfo::TypeArgument {
+                offset: u16::read(i)?,
+                type_argument_index: u8::read(i)?,
+            },
+        })
+    }
+}
+
+impl Writable for TargetInfo {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        match self {
+            TargetInfo::TypeParameter { type_parameter_index } => type_parameter_index.write(o),
+            TargetInfo::Supertype { supertype_index } => supertype_index.write(o),
+            TargetInfo::TypeParameterBound { type_parameter_index, bound_index } => {
+                type_parameter_index.write(o)?;
+                bound_index.write(o)
+            }
+            TargetInfo::Empty => Ok(()),
+            TargetInfo::FormalParameter { formal_parameter_index } => formal_parameter_index.write(o),
+            TargetInfo::Throws { throws_type_index } => throws_type_index.write(o),
+            TargetInfo::LocalVariable(values) => u16::write_vec(o, values),
+            TargetInfo::Catch { exception_table_index } => exception_table_index.write(o),
+            TargetInfo::Offset { offset } => offset.write(o),
+            TargetInfo::TypeArgument { offset, type_argument_index } => {
+                offset.write(o)?;
+                type_argument_index.write(o)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum TypePathKind {
+    // Deeper in an array type
+    Array = 0,
+    // Deeper in a nested type
+    InnerType = 1,
+    // On the bound of a wildcard type argument
+    Wildcard = 2,
+    // On a type argument of a parameterized type
+    TypeArgument = 3,
+}
+
+/// A single step from the outermost type towards the annotated part of it
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TypePathEntry {
+    pub kind: TypePathKind,
+    // Which type argument is annotated. Zero for every other kind
+    pub type_argument_index: u8,
+}
+
+impl Readable for TypePathEntry {
+    fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized {
+        let kind = u8::read(i)?;
+        let kind = TypePathKind::try_from(kind)
+            .map_err(|_| ReadError::UnknownTypePathKind(kind))?;
+        Ok(TypePathEntry { kind, type_argument_index: u8::read(i)? })
+    }
+}
+
+impl Writable for TypePathEntry {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        u8::from(self.kind).write(o)?;
+        self.type_argument_index.write(o)
+    }
+}
+
+impl Display for TypePathEntry {
+    /// Formats the entry the same way as javap (e.g. TYPE_ARGUMENT(0))
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            TypePathKind::Array => f.write_str("ARRAY"),
+            TypePathKind::InnerType => f.write_str("INNER_TYPE"),
+            TypePathKind::Wildcard => f.write_str("WILDCARD"),
+            TypePathKind::TypeArgument => write!(f, "TYPE_ARGUMENT({})", self.type_argument_index),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeAnnotation {
+    pub target_type: TargetType,
+    pub target_info: TargetInfo,
+    // Empty when the annotation applies to the whole type
+    pub type_path: Vec<TypePathEntry>,
+    pub annotation: Annotation,
+}
+
+impl TypeAnnotation {
+    pub fn read<R: Read>(i: &mut R, limits: &ParseLimits) -> ReadResult<TypeAnnotation> {
+        let target_type = u8::read(i)?;
+        let target_type = TargetType::try_from(target_type)
+            .map_err(|_| ReadError::UnknownTargetType(target_type).in_field("target_type"))?;
+        let target_info = TargetInfo::read(i, target_type)
+            .map_err(|err| err.in_field("target_info"))?;
+        let type_path = u8::read_vec(i)
+            .map_err(|err| err.in_field("target_path"))?;
+        let annotation = Annotation::read(i, limits)?;
+        Ok(TypeAnnotation { target_type, target_info, type_path, annotation })
+    }
+
+    /// Whether this annotates an array type at the provided depth, zero
+    /// being the outermost array. Annotations on the element type of an
+    /// array with `dimensions` dimensions have a depth of `dimensions`
+    pub fn is_array_depth(&self, depth: usize) -> bool {
+        self.type_path.len() == depth
+            && self.type_path.iter().all(|entry| entry.kind == TypePathKind::Array)
+    }
+}
+
+impl Writable for TypeAnnotation {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        u8::from(self.target_type).write(o)?;
+        self.target_info.write(o)?;
+        u8::write_vec(o, &self.type_path)?;
+        self.annotation.write(o)
+    }
+}
+
+/// Reads the contents of a RuntimeVisibleTypeAnnotations or RuntimeInvisibleTypeAnnotations attribute
+pub fn read_type_annotations<R: Read>(i: &mut R, limits: &ParseLimits) -> ReadResult<Vec<TypeAnnotation>> {
+    u16::read_vec_closure(i, |r| TypeAnnotation::read(r, limits))
+        .map_err(|err| err.in_field("annotations"))
+}
+
+/// The type annotations in both the visible and invisible type annotation
+/// attributes of a class, field, method or Code attribute
+pub fn type_annotations(attributes: &[Attribute]) -> impl Iterator<Item=&TypeAnnotation> {
+    attributes.iter()
+        .filter_map(|attribute| match &attribute.value {
+            AttributeValue::RuntimeVisibleTypeAnnotations(values) |
+            AttributeValue::RuntimeInvisibleTypeAnnotations(values) => Some(values),
+            _ => None
+        })
+        .flatten()
+}
@@ -1,9 +1,14 @@
-use std::io::{Cursor, Read};
+use std::io::{Cursor, Read, Write};
 
 use crate::class::access::AccessFlags;
+use crate::class::annotation::{self, Annotation, ElementValue, read_annotations, read_parameter_annotations, read_type_annotations, TypeAnnotation};
 use crate::class::constant::{ConstantPool, PoolIndex};
-use crate::error::ReadError;
-use crate::io::{Readable, ReadResult, VecReadableBytesSize, VecReadableFn, VecReadableSize};
+use crate::class::descriptor::Descriptor;
+use crate::class::frame::StackMapFrame;
+use crate::class::module::Module;
+use crate::class::signature::{self, TypeSignature};
+use crate::error::{ConstantError, ReadError, SignatureError, WriteError};
+use crate::io::{ParseLimits, Readable, read_bytes_exact, ReadResult, VecReadableFn, VecReadableSize, VecWritableBytesSize, VecWritableFn, VecWritableSize, Writable, WriteResult};
 use crate::readable_struct;
 
 readable_struct! {
@@ -53,8 +58,31 @@ readable_struct! {
     }
 }
 
+impl LocalVariable {
+    pub fn name<'a>(&self, constant_pool: &'a ConstantPool) -> Result<&'a String, ConstantError> {
+        constant_pool.get_utf8(&self.name_index)
+    }
+
+    pub fn descriptor(&self, constant_pool: &ConstantPool) -> Result<Descriptor, ConstantError> {
+        constant_pool.get_utf8(&self.descriptor_index).map(|value| Descriptor::parse(value))
+    }
+}
+
+impl LocalVariableType {
+    pub fn name<'a>(&self, constant_pool: &'a ConstantPool) -> Result<&'a String, ConstantError> {
+        constant_pool.get_utf8(&self.name_index)
+    }
+
+    /// The generic type of the variable (e.g. Ljava/util/List<TT;>;)
+    pub fn signature(&self, constant_pool: &ConstantPool) -> ReadResult<TypeSignature> {
+        let value = constant_pool.get_utf8(&self.signature_index)?;
+        Ok(TypeSignature::parse(value)?)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Attribute {
+    pub name_index: PoolIndex,
     pub name: String,
     pub value: AttributeValue,
 }
@@ -71,18 +99,70 @@ impl Attribute {
     pub fn read<B: Read>(
         i: &mut B,
         constant_pool: &ConstantPool,
+        limits: &ParseLimits,
     ) -> Result<Self, ReadError> where Self: Sized {
-        let name = constant_pool.read_utf8(i)?;
-        let data = u32::read_bytes(i)?;
+        let (name_index, name) = constant_pool.read_utf8_indexed(i)
+            .map_err(|err| err.in_field("attribute_name_index"))?;
+        let data = Attribute::read_data(i, limits)
+            .map_err(|err| err.in_key(name.as_str()))?;
+        let value = AttributeValue::from_name(
+            name.as_str(),
+            data.as_slice(),
+            constant_pool,
+            limits,
+        ).map_err(|err| err.in_key(name.as_str()))?;
         Ok(Attribute {
+            name_index,
             name: name.clone(),
-            value: AttributeValue::from_name(
-                name.as_str(),
-                data.as_slice(),
-                constant_pool,
-            )?,
+            value,
         })
     }
+
+    /// Reads the length prefixed contents of an attribute
+    fn read_data<B: Read>(i: &mut B, limits: &ParseLimits) -> ReadResult<Vec<u8>> {
+        let length = u32::read(i)?;
+        ParseLimits::check("attribute length", length as u64, limits.max_attribute_length as u64)?;
+        read_bytes_exact(i, length as usize)
+    }
+
+    /// Reads an attribute without decoding its value. The value is left as
+    /// `AttributeValue::Undecoded` until `Attribute::decode` is called
+    pub fn read_lazy<B: Read>(
+        i: &mut B,
+        constant_pool: &ConstantPool,
+        limits: &ParseLimits,
+    ) -> Result<Self, ReadError> where Self: Sized {
+        let (name_index, name) = constant_pool.read_utf8_indexed(i)
+            .map_err(|err| err.in_field("attribute_name_index"))?;
+        let data = Attribute::read_data(i, limits)
+            .map_err(|err| err.in_key(name.as_str()))?;
+        Ok(Attribute {
+            name_index,
+            name: name.clone(),
+            value: AttributeValue::Undecoded(data),
+        })
+    }
+
+    /// Decodes the value of this attribute if it was read lazily. Attributes
+    /// that are already decoded are left unchanged
+    pub fn decode(&mut self, constant_pool: &ConstantPool, limits: &ParseLimits) -> ReadResult<&AttributeValue> {
+        if let AttributeValue::Undecoded(data) = &self.value {
+            self.value = AttributeValue::from_name(&self.name, data, constant_pool, limits)
+                .map_err(|err| err.in_key(self.name.as_str()))?;
+        }
+        Ok(&self.value)
+    }
+
+    pub fn write<W: Write>(
+        &self,
+        o: &mut W,
+        constant_pool: &ConstantPool,
+    ) -> WriteResult {
+        constant_pool.utf8_index(self.name_index, &self.name)?.write(o)?;
+        let mut data = Vec::new();
+        self.value.write(&mut data, constant_pool)?;
+        u32::write_bytes(o, &data)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -94,6 +174,53 @@ pub struct CodeAttr {
     pub attributes: Vec<Attribute>,
 }
 
+/// A component of a record class. Like fields and methods each
+/// component has its own attributes (e.g. Signature and annotations)
+#[derive(Debug, Clone)]
+pub struct RecordComponent {
+    pub name_index: PoolIndex,
+    pub name: String,
+    pub descriptor_index: PoolIndex,
+    pub descriptor: Descriptor,
+    pub attributes: Vec<Attribute>,
+}
+
+impl RecordComponent {
+    pub fn read<R: Read>(
+        i: &mut R,
+        constant_pool: &ConstantPool,
+        limits: &ParseLimits,
+    ) -> ReadResult<RecordComponent> {
+        let (name_index, name) = constant_pool.read_utf8_indexed(i)
+            .map_err(|err| err.in_field("name_index"))?;
+        let name = name.clone();
+        let (descriptor_index, descriptor) = constant_pool.read_utf8_indexed(i)
+            .map_err(|err| err.in_field("descriptor_index"))?;
+        let descriptor = Descriptor::parse(descriptor);
+        let attributes = u16::read_vec_closure(i, |r| Attribute::read(r, constant_pool, limits))
+            .map_err(|err| err.in_field("attributes"))?;
+        Ok(RecordComponent { name_index, name, descriptor_index, descriptor, attributes })
+    }
+
+    pub fn write<W: Write>(&self, o: &mut W, constant_pool: &ConstantPool) -> WriteResult {
+        constant_pool.utf8_index(self.name_index, &self.name)?.write(o)?;
+        constant_pool.utf8_index(self.descriptor_index, &self.descriptor.to_internal_java())?.write(o)?;
+        u16::write_vec_closure(o, &self.attributes, |o, attribute| attribute.write(o, constant_pool))
+    }
+
+    /// The generic type of this component. None when the type isn't generic
+    pub fn signature(&self) -> Result<Option<TypeSignature>, SignatureError> {
+        signature::signature(&self.attributes)
+            .map(|value| TypeSignature::parse(value))
+            .transpose()
+    }
+
+    /// The visible and invisible annotations of this component
+    pub fn annotations(&self) -> impl Iterator<Item=&Annotation> {
+        annotation::annotations(&self.attributes)
+    }
+}
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub enum AttributeValue {
@@ -109,21 +236,37 @@ pub enum AttributeValue {
     Synthetic,
     Depreciated,
     EnclosingMethod(EnclosingMethod),
-    // signature_index
-    Signature(String),
+    // signature_index and the signature it refers to
+    Signature(PoolIndex, String),
     SourceDebugExtension(Vec<u8>),
     LocalVariableTypeTable(Vec<LocalVariableType>),
-    RuntimeVisibleAnnotations,
-    RuntimeInvisibleAnnotations,
-    RuntimeVisibleParameterAnnotations,
-    RuntimeInvisibleParameterAnnotations,
-    AnnotationDefault(Vec<u8>),
-    StackMapTable,
+    RuntimeVisibleAnnotations(Vec<Annotation>),
+    RuntimeInvisibleAnnotations(Vec<Annotation>),
+    // The annotations of each parameter
+    RuntimeVisibleParameterAnnotations(Vec<Vec<Annotation>>),
+    RuntimeInvisibleParameterAnnotations(Vec<Vec<Annotation>>),
+    // default_value of an annotation interface element
+    AnnotationDefault(ElementValue),
+    StackMapTable(Vec<StackMapFrame>),
     BootstrapMethods(Vec<BootstrapMethod>),
-    RuntimeVisibleTypeAnnotations,
-    RuntimeInvisibleTypeAnnotations,
+    RuntimeVisibleTypeAnnotations(Vec<TypeAnnotation>),
+    RuntimeInvisibleTypeAnnotations(Vec<TypeAnnotation>),
     MethodParameters(Vec<MethodParameter>),
+    // host_class_index
+    NestHost(PoolIndex),
+    // Class indexes of the classes in the nest
+    NestMembers(Vec<PoolIndex>),
+    // Class indexes of the classes allowed to extend a sealed class
+    PermittedSubclasses(Vec<PoolIndex>),
+    Record(Vec<RecordComponent>),
+    Module(Module),
+    // package_index of each package in the module
+    ModulePackages(Vec<PoolIndex>),
+    // main_class_index
+    ModuleMainClass(PoolIndex),
     Unknown(Vec<u8>),
+    // Raw contents of an attribute that was read lazily and has not been decoded yet
+    Undecoded(Vec<u8>),
 }
 
 impl AttributeValue {
@@ -131,41 +274,177 @@ impl AttributeValue {
         name: &str,
         data: &[u8],
         constant_pool: &ConstantPool,
+        limits: &ParseLimits,
     ) -> Result<AttributeValue, ReadError> {
         let c = &mut Cursor::new(data);
+        // Errors are rewound by the unread portion of the data so that their
+        // offset can be resolved by the reader the data was taken from
+        AttributeValue::read_value(name, c, constant_pool, limits)
+            .and_then(|value| {
+                // The whole attribute must be used so that it is written back
+                // with the same length
+                let remaining = (data.len() as u64).saturating_sub(c.position());
+                if remaining > 0 {
+                    return Err(ReadError::TrailingBytes(remaining));
+                }
+                Ok(value)
+            })
+            .map_err(|err| err.rewind((data.len() as u64).saturating_sub(c.position())))
+    }
+
+    fn read_value(
+        name: &str,
+        c: &mut Cursor<&[u8]>,
+        constant_pool: &ConstantPool,
+        limits: &ParseLimits,
+    ) -> Result<AttributeValue, ReadError> {
+        let data: &[u8] = c.get_ref();
+        // Attributes kept as raw bytes use the whole of the data
+        let rest = |c: &mut Cursor<&[u8]>| {
+            c.set_position(data.len() as u64);
+            data.to_vec()
+        };
         Ok(match name {
-            "Code" => AttributeValue::Code(CodeAttr {
-                max_stack: u16::read(c)?,
-                max_locals: u16::read(c)?,
-                code: u32::read_vec(c)?,
-                exception_table: u16::read_vec(c)?,
-                attributes: u16::read_vec_closure(
-                    c,
-                    |r| Attribute::read(r, &constant_pool),
-                )?,
-            }),
+            "Code" => {
+                let max_stack = u16::read(c)?;
+                let max_locals = u16::read(c)?;
+                let code_length = u32::read(c)?;
+                ParseLimits::check("code length", code_length as u64, limits.max_code_length as u64)
+                    .map_err(|err| err.in_field("code_length"))?;
+                let code = read_bytes_exact(c, code_length as usize)
+                    .map_err(|err| err.in_field("code"))?;
+                let nested_limits = limits.nested()?;
+                AttributeValue::Code(CodeAttr {
+                    max_stack,
+                    max_locals,
+                    code,
+                    exception_table: u16::read_vec(c)
+                        .map_err(|err| err.in_field("exception_table"))?,
+                    attributes: u16::read_vec_closure(
+                        c,
+                        |r| Attribute::read(r, constant_pool, &nested_limits),
+                    ).map_err(|err| err.in_field("attributes"))?,
+                })
+            }
             "ConstantValue" => AttributeValue::ConstantValue(PoolIndex::read(c)?),
             "Deprecated" => AttributeValue::Depreciated,
             "Exceptions" => AttributeValue::Exceptions(u16::read_vec(c)?),
             "InnerClasses" => AttributeValue::InnerClasses(u16::read_vec(c)?),
             "Signature" => {
-                let id = u16::read(c)?;
-                AttributeValue::Signature(constant_pool.get_utf8(&id)?.clone())
+                let index = PoolIndex::read(c)?;
+                AttributeValue::Signature(index, constant_pool.get_utf8(&index)?.clone())
             }
-            "SourceDebugExtension" => AttributeValue::SourceDebugExtension(data.to_vec()),
+            "SourceDebugExtension" => AttributeValue::SourceDebugExtension(rest(c)),
             "LineNumberTable" => AttributeValue::LineNumberTable(u16::read_vec(c)?),
             "LocalVariableTable" => AttributeValue::LocalVariableTable(u16::read_vec(c)?),
             "SourceFile" => AttributeValue::SourceFile(PoolIndex::read(c)?),
             "Synthetic" => AttributeValue::Synthetic,
-            "AnnotationDefault" => AttributeValue::AnnotationDefault(data.to_vec()),
+            "AnnotationDefault" => AttributeValue::AnnotationDefault(ElementValue::read(c, limits)
+                .map_err(|err| err.in_field("default_value"))?),
+            "RuntimeVisibleAnnotations" => AttributeValue::RuntimeVisibleAnnotations(read_annotations(c, limits)?),
+            "RuntimeInvisibleAnnotations" => AttributeValue::RuntimeInvisibleAnnotations(read_annotations(c, limits)?),
+            "RuntimeVisibleParameterAnnotations" => AttributeValue::RuntimeVisibleParameterAnnotations(
+                read_parameter_annotations(c, limits)?
+            ),
+            "RuntimeInvisibleParameterAnnotations" => AttributeValue::RuntimeInvisibleParameterAnnotations(
+                read_parameter_annotations(c, limits)?
+            ),
+            "RuntimeVisibleTypeAnnotations" => AttributeValue::RuntimeVisibleTypeAnnotations(read_type_annotations(c, limits)?),
+            "RuntimeInvisibleTypeAnnotations" => AttributeValue::RuntimeInvisibleTypeAnnotations(read_type_annotations(c, limits)?),
+            "StackMapTable" => AttributeValue::StackMapTable(u16::read_vec_closure(
+                c,
+                |r| StackMapFrame::read(r, constant_pool),
+            )?),
+            "NestHost" => AttributeValue::NestHost(PoolIndex::read(c)?),
+            "NestMembers" => AttributeValue::NestMembers(u16::read_vec(c)?),
+            "PermittedSubclasses" => AttributeValue::PermittedSubclasses(u16::read_vec(c)?),
+            "Record" => {
+                let nested_limits = limits.nested()?;
+                AttributeValue::Record(u16::read_vec_closure(
+                    c,
+                    |r| RecordComponent::read(r, constant_pool, &nested_limits),
+                )?)
+            }
+            "Module" => AttributeValue::Module(Module::read(c)?),
+            "ModulePackages" => AttributeValue::ModulePackages(u16::read_vec(c)?),
+            "ModuleMainClass" => AttributeValue::ModuleMainClass(PoolIndex::read(c)?),
             "EnclosingMethod" => AttributeValue::EnclosingMethod(EnclosingMethod::read(c)?),
-            "LocalVariableTypeTable" => AttributeValue::LocalVariableTable(u16::read_vec(c)?),
+            "LocalVariableTypeTable" => AttributeValue::LocalVariableTypeTable(u16::read_vec(c)?),
+            "MethodParameters" => AttributeValue::MethodParameters(u8::read_vec(c)?),
             "BootstrapMethods" => AttributeValue::BootstrapMethods(u16::read_vec_closure(c, |r| -> ReadResult<BootstrapMethod> {
                 let method_ref = PoolIndex::read(r)?;
                 let arguments = u16::read_vec(r)?;
                 Ok(BootstrapMethod { method_ref, arguments })
             })?),
-            _ => AttributeValue::Unknown(data.to_vec())
+            _ => AttributeValue::Unknown(rest(c))
         })
     }
+
+    /// Writes the body of this attribute value (excluding the name
+    /// and length)
+    pub fn write<W: Write>(
+        &self,
+        o: &mut W,
+        constant_pool: &ConstantPool,
+    ) -> WriteResult {
+        match self {
+            AttributeValue::Code(code) => {
+                code.max_stack.write(o)?;
+                code.max_locals.write(o)?;
+                u32::write_bytes(o, &code.code)?;
+                u16::write_vec(o, &code.exception_table)?;
+                u16::write_vec_closure(
+                    o,
+                    &code.attributes,
+                    |o, attribute| attribute.write(o, constant_pool),
+                )?;
+            }
+            AttributeValue::ConstantValue(index) |
+            AttributeValue::SourceFile(index) |
+            AttributeValue::ModuleMainClass(index) |
+            AttributeValue::NestHost(index) => index.write(o)?,
+            AttributeValue::NestMembers(values) |
+            AttributeValue::PermittedSubclasses(values) => u16::write_vec(o, values)?,
+            AttributeValue::Record(components) => u16::write_vec_closure(
+                o,
+                components,
+                |o, component| component.write(o, constant_pool),
+            )?,
+            AttributeValue::Synthetic | AttributeValue::Depreciated => {}
+            AttributeValue::Exceptions(values) => u16::write_vec(o, values)?,
+            AttributeValue::InnerClasses(values) => u16::write_vec(o, values)?,
+            AttributeValue::Signature(index, value) => constant_pool.utf8_index(*index, value)?.write(o)?,
+            AttributeValue::LineNumberTable(values) => u16::write_vec(o, values)?,
+            AttributeValue::LocalVariableTable(values) => u16::write_vec(o, values)?,
+            AttributeValue::LocalVariableTypeTable(values) => u16::write_vec(o, values)?,
+            AttributeValue::EnclosingMethod(value) => value.write(o)?,
+            AttributeValue::Module(value) => value.write(o)?,
+            AttributeValue::ModulePackages(values) => u16::write_vec(o, values)?,
+            AttributeValue::MethodParameters(values) => u8::write_vec(o, values)?,
+            AttributeValue::BootstrapMethods(values) => u16::write_vec_closure(o, values, |o, value| {
+                value.method_ref.write(o)?;
+                u16::write_vec(o, &value.arguments)
+            })?,
+            AttributeValue::RuntimeVisibleAnnotations(values) |
+            AttributeValue::RuntimeInvisibleAnnotations(values) => u16::write_vec(o, values)?,
+            AttributeValue::RuntimeVisibleParameterAnnotations(values) |
+            AttributeValue::RuntimeInvisibleParameterAnnotations(values) => u8::write_vec_closure(
+                o,
+                values,
+                |o, values| u16::write_vec(o, values),
+            )?,
+            AttributeValue::RuntimeVisibleTypeAnnotations(values) |
+            AttributeValue::RuntimeInvisibleTypeAnnotations(values) => u16::write_vec(o, values)?,
+            AttributeValue::AnnotationDefault(value) => value.write(o)?,
+            AttributeValue::StackMapTable(frames) => u16::write_vec_closure(
+                o,
+                frames,
+                |o, frame| frame.write(o, constant_pool),
+            )?,
+            AttributeValue::SourceDebugExtension(data) |
+            AttributeValue::Unknown(data) |
+            AttributeValue::Undecoded(data) => o.write_all(data).map_err(WriteError::from)?,
+        }
+        Ok(())
+    }
 }
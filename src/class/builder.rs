@@ -0,0 +1,282 @@
+//! Builder for adding constants to a constant pool. Constants are interned
+//! so adding a constant that is already present returns the existing index
+use std::collections::{HashMap, HashSet};
+
+use crate::class::class::ClassPath;
+use crate::class::constant::{Constant, ConstantPool, DynamicConstant, MemberReferenceU, MethodHandle, NameAndTypeIndex, PoolIndex, ReferenceKind};
+use crate::error::WriteError;
+use crate::io::Writable;
+
+/// The largest value of constant_pool_count. Constants must fit
+/// below this index including the second slot of Long and Double
+const MAX_POOL_SIZE: u32 = 65535;
+
+pub struct ConstantPoolBuilder {
+    pool: ConstantPool,
+    // Constants keyed by their encoded form so that floating point
+    // values are compared by their bits
+    lookup: HashMap<Vec<u8>, PoolIndex>,
+    // The index the next constant will be placed at
+    next: u32,
+}
+
+impl Default for ConstantPoolBuilder {
+    fn default() -> Self {
+        ConstantPoolBuilder::new()
+    }
+}
+
+impl From<ConstantPool> for ConstantPoolBuilder {
+    fn from(value: ConstantPool) -> Self {
+        ConstantPoolBuilder::from_pool(value)
+    }
+}
+
+/// Encodes the constant for the lookup. Encoding only fails on lengths
+/// which can't be written at all so such constants are never added
+fn key(constant: &Constant) -> Result<Vec<u8>, WriteError> {
+    let mut out = Vec::new();
+    constant.write(&mut out)?;
+    Ok(out)
+}
+
+/// The index of a constant when it is already present in the pool along
+/// with the number of slots needed by it and the constants it refers to
+type Plan = (Option<PoolIndex>, u32);
+
+/// The encoded constants a planned insertion will add
+type Pending = HashSet<Vec<u8>>;
+
+impl ConstantPoolBuilder {
+    pub fn new() -> Self {
+        ConstantPoolBuilder { pool: ConstantPool { inner: HashMap::new() }, lookup: HashMap::new(), next: 1 }
+    }
+
+    /// Creates a builder that adds to an existing pool. Existing constants
+    /// keep their indexes and new constants are placed after them
+    pub fn from_pool(pool: ConstantPool) -> Self {
+        let mut indexes: Vec<PoolIndex> = pool.inner.keys().copied().collect();
+        indexes.sort();
+        let mut lookup = HashMap::with_capacity(indexes.len());
+        let mut next = 1;
+        for index in indexes {
+            let constant = &pool.inner[&index];
+            // Duplicates resolve to the smallest index like the pool lookups
+            if let Ok(key) = key(constant) {
+                lookup.entry(key).or_insert(index);
+            }
+            next = index as u32 + constant.width() as u32;
+        }
+        ConstantPoolBuilder { pool, lookup, next }
+    }
+
+    /// The constant_pool_count of the pool being built
+    pub fn size(&self) -> u32 { self.next }
+
+    pub fn pool(&self) -> &ConstantPool { &self.pool }
+
+    pub fn build(self) -> ConstantPool { self.pool }
+
+    /// Adds the constant to the pool returning its index. If an equal
+    /// constant is already present its index is returned instead. Fails
+    /// with `WriteError::LengthOverflow` if the constant can't be encoded
+    pub fn insert(&mut self, constant: Constant) -> Result<PoolIndex, WriteError> {
+        let key = key(&constant)?;
+        if let Some(index) = self.lookup.get(&key) {
+            return Ok(*index);
+        }
+        self.reserve(constant.width() as u32)?;
+        let index = self.next as PoolIndex;
+        self.next += constant.width() as u32;
+        self.pool.inner.insert(index, constant);
+        self.lookup.insert(key, index);
+        Ok(index)
+    }
+
+    /// Checks that the provided number of slots fit in the pool
+    fn reserve(&self, slots: u32) -> Result<(), WriteError> {
+        if self.next + slots > MAX_POOL_SIZE {
+            return Err(WriteError::PoolFull);
+        }
+        Ok(())
+    }
+
+    /// Plans the insertion of a constant whose dependencies needed the
+    /// provided slots. None is used for constants that depend on missing
+    /// constants, which must be missing themselves. Constants already
+    /// planned for the group are only counted once
+    fn plan(&self, constant: Option<Constant>, slots: u32, pending: &mut Pending) -> Result<Plan, WriteError> {
+        let Some(constant) = constant else {
+            // Every constant that refers to other constants takes one slot
+            return Ok((None, slots + 1));
+        };
+        let key = key(&constant)?;
+        match self.lookup.get(&key) {
+            Some(index) => Ok((Some(*index), slots)),
+            None if pending.insert(key) => Ok((None, slots + constant.width() as u32)),
+            None => Ok((None, slots)),
+        }
+    }
+
+    fn plan_utf8(&self, value: &str, pending: &mut Pending) -> Result<Plan, WriteError> {
+        self.plan(Some(Constant::Utf8(value.to_string())), 0, pending)
+    }
+
+    /// Plans a constant that refers to a single utf8 constant
+    fn plan_named(&self, value: &str, constant: fn(PoolIndex) -> Constant, pending: &mut Pending) -> Result<Plan, WriteError> {
+        let (index, slots) = self.plan_utf8(value, pending)?;
+        self.plan(index.map(constant), slots, pending)
+    }
+
+    fn plan_name_and_type(&self, name: &str, descriptor: &str, pending: &mut Pending) -> Result<Plan, WriteError> {
+        let (name_index, name_slots) = self.plan_utf8(name, pending)?;
+        let (descriptor_index, descriptor_slots) = self.plan_utf8(descriptor, pending)?;
+        let constant = name_index.zip(descriptor_index)
+            .map(|(name_index, descriptor_index)| Constant::NameAndType(NameAndTypeIndex { name_index, descriptor_index }));
+        self.plan(constant, name_slots + descriptor_slots, pending)
+    }
+
+    fn plan_member(
+        &self,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+        constant: fn(MemberReferenceU) -> Constant,
+        pending: &mut Pending,
+    ) -> Result<Plan, WriteError> {
+        let (class_index, class_slots) = self.plan_named(class, Constant::Class, pending)?;
+        let (name_and_type_info, name_and_type_slots) = self.plan_name_and_type(name, descriptor, pending)?;
+        let value = class_index.zip(name_and_type_info)
+            .map(|(class_index, name_and_type_info)| constant(MemberReferenceU { class_index, name_and_type_info }));
+        self.plan(value, class_slots + name_and_type_slots, pending)
+    }
+
+    fn plan_dynamic(
+        &self,
+        name: &str,
+        descriptor: &str,
+        constant: impl Fn(PoolIndex) -> Constant,
+        pending: &mut Pending,
+    ) -> Result<Plan, WriteError> {
+        let (name_and_type_index, slots) = self.plan_name_and_type(name, descriptor, pending)?;
+        self.plan(name_and_type_index.map(constant), slots, pending)
+    }
+
+    /// Checks that the constants of a planned insertion all fit so that a
+    /// full pool doesn't leave some of them behind
+    fn reserve_plan(&self, plan: impl FnOnce(&mut Pending) -> Result<Plan, WriteError>) -> Result<(), WriteError> {
+        let (_, slots) = plan(&mut Pending::new())?;
+        self.reserve(slots)
+    }
+
+    pub fn utf8(&mut self, value: &str) -> Result<PoolIndex, WriteError> {
+        self.insert(Constant::Utf8(value.to_string()))
+    }
+
+    pub fn integer(&mut self, value: i32) -> Result<PoolIndex, WriteError> {
+        self.insert(Constant::Integer(value))
+    }
+
+    pub fn float(&mut self, value: f32) -> Result<PoolIndex, WriteError> {
+        self.insert(Constant::Float(value))
+    }
+
+    pub fn long(&mut self, value: i64) -> Result<PoolIndex, WriteError> {
+        self.insert(Constant::Long(value))
+    }
+
+    pub fn double(&mut self, value: f64) -> Result<PoolIndex, WriteError> {
+        self.insert(Constant::Double(value))
+    }
+
+    /// Adds a class constant using the internal name of the
+    /// class (e.g. java/lang/Object or [I for array classes)
+    pub fn class(&mut self, name: &str) -> Result<PoolIndex, WriteError> {
+        self.reserve_plan(|pending| self.plan_named(name, Constant::Class, pending))?;
+        let name_index = self.utf8(name)?;
+        self.insert(Constant::Class(name_index))
+    }
+
+    pub fn class_path(&mut self, path: &ClassPath) -> Result<PoolIndex, WriteError> {
+        self.class(&path.internal_path())
+    }
+
+    pub fn string(&mut self, value: &str) -> Result<PoolIndex, WriteError> {
+        self.reserve_plan(|pending| self.plan_named(value, Constant::String, pending))?;
+        let string_index = self.utf8(value)?;
+        self.insert(Constant::String(string_index))
+    }
+
+    pub fn name_and_type(&mut self, name: &str, descriptor: &str) -> Result<PoolIndex, WriteError> {
+        self.reserve_plan(|pending| self.plan_name_and_type(name, descriptor, pending))?;
+        let name_index = self.utf8(name)?;
+        let descriptor_index = self.utf8(descriptor)?;
+        self.insert(Constant::NameAndType(NameAndTypeIndex { name_index, descriptor_index }))
+    }
+
+    fn member(&mut self, class: &str, name: &str, descriptor: &str) -> Result<MemberReferenceU, WriteError> {
+        let class_index = self.class(class)?;
+        let name_and_type_info = self.name_and_type(name, descriptor)?;
+        Ok(MemberReferenceU { class_index, name_and_type_info })
+    }
+
+    pub fn field_ref(&mut self, class: &str, name: &str, descriptor: &str) -> Result<PoolIndex, WriteError> {
+        self.reserve_plan(|pending| self.plan_member(class, name, descriptor, Constant::FieldRef, pending))?;
+        let value = self.member(class, name, descriptor)?;
+        self.insert(Constant::FieldRef(value))
+    }
+
+    pub fn method_ref(&mut self, class: &str, name: &str, descriptor: &str) -> Result<PoolIndex, WriteError> {
+        self.reserve_plan(|pending| self.plan_member(class, name, descriptor, Constant::MethodRef, pending))?;
+        let value = self.member(class, name, descriptor)?;
+        self.insert(Constant::MethodRef(value))
+    }
+
+    pub fn interface_method_ref(&mut self, class: &str, name: &str, descriptor: &str) -> Result<PoolIndex, WriteError> {
+        self.reserve_plan(|pending| self.plan_member(class, name, descriptor, Constant::InterfaceMethodRef, pending))?;
+        let value = self.member(class, name, descriptor)?;
+        self.insert(Constant::InterfaceMethodRef(value))
+    }
+
+    /// Adds a method handle referencing an existing field or method
+    /// reference (e.g. one returned by `method_ref`)
+    pub fn method_handle(&mut self, kind: ReferenceKind, reference_index: PoolIndex) -> Result<PoolIndex, WriteError> {
+        self.insert(Constant::MethodHandle(MethodHandle { reference_kind: kind.into(), reference_index }))
+    }
+
+    pub fn method_type(&mut self, descriptor: &str) -> Result<PoolIndex, WriteError> {
+        self.reserve_plan(|pending| self.plan_named(descriptor, Constant::MethodType, pending))?;
+        let descriptor_index = self.utf8(descriptor)?;
+        self.insert(Constant::MethodType(descriptor_index))
+    }
+
+    /// Adds a dynamically-computed constant. The bootstrap method index
+    /// is an index into the BootstrapMethods attribute of the class
+    pub fn dynamic(&mut self, bootstrap_method_attr_index: PoolIndex, name: &str, descriptor: &str) -> Result<PoolIndex, WriteError> {
+        self.reserve_plan(|pending| self.plan_dynamic(name, descriptor, |name_and_type_index| {
+            Constant::Dynamic(DynamicConstant { bootstrap_method_attr_index, name_and_type_index })
+        }, pending))?;
+        let name_and_type_index = self.name_and_type(name, descriptor)?;
+        self.insert(Constant::Dynamic(DynamicConstant { bootstrap_method_attr_index, name_and_type_index }))
+    }
+
+    pub fn invoke_dynamic(&mut self, bootstrap_method_attr_index: PoolIndex, name: &str, descriptor: &str) -> Result<PoolIndex, WriteError> {
+        self.reserve_plan(|pending| self.plan_dynamic(name, descriptor, |name_and_type_index| {
+            Constant::InvokeDynamic(DynamicConstant { bootstrap_method_attr_index, name_and_type_index })
+        }, pending))?;
+        let name_and_type_index = self.name_and_type(name, descriptor)?;
+        self.insert(Constant::InvokeDynamic(DynamicConstant { bootstrap_method_attr_index, name_and_type_index }))
+    }
+
+    pub fn module(&mut self, name: &str) -> Result<PoolIndex, WriteError> {
+        self.reserve_plan(|pending| self.plan_named(name, Constant::Module, pending))?;
+        let name_index = self.utf8(name)?;
+        self.insert(Constant::Module(name_index))
+    }
+
+    pub fn package(&mut self, name: &str) -> Result<PoolIndex, WriteError> {
+        self.reserve_plan(|pending| self.plan_named(name, Constant::Package, pending))?;
+        let name_index = self.utf8(name)?;
+        self.insert(Constant::Package(name_index))
+    }
+}
@@ -1,30 +1,69 @@
+use std::collections::HashSet;
 use std::fmt::{Debug, Display, Formatter};
-use std::io::Read;
+use std::io::{Read, Write};
 
-use num_enum::{IntoPrimitive, TryFromPrimitive};
+use num_enum::{FromPrimitive, IntoPrimitive};
 
-use crate::class::access::AccessFlags;
-use crate::class::attribute::Attribute;
+use crate::class::access::{AccessFlags, ClassAccessFlag};
+use crate::class::annotation::{self, Annotation, ElementValue, type_annotations};
+use crate::class::attribute::{Attribute, AttributeValue, BootstrapMethod, RecordComponent};
 use crate::class::constant::{ConstantPool, PoolIndex};
 use crate::class::descriptor::Descriptor;
 use crate::class::member::Member;
-use crate::error::ReadError;
-use crate::io::{Readable, ReadResult, VecReadableFn};
+use crate::class::module::Module;
+use crate::class::nesting::ClassNesting;
+use crate::class::signature::{self, ClassSignature};
+use crate::error::{ConstantError, ReadError, SignatureError, WriteError};
+use crate::io::{ParseLimits, PositionReader, Readable, ReadResult, VecReadableFn, VecReadableSize, VecWritableFn, Writable, WriteResult};
 
+#[derive(Copy, Clone)]
 pub struct SourceVersion {
     minor: u16,
     major: MajorVersion,
 }
 
+// The minor version of classes compiled with --enable-preview
+const PREVIEW_MINOR: u16 = 0xFFFF;
+
 impl Debug for SourceVersion {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         MajorVersion::fmt(&self.major, f)?;
-        f.write_str(format!(", {}", self.minor).as_str())
+        if self.is_preview() {
+            f.write_str(" (preview)")
+        } else {
+            f.write_str(format!(", {}", self.minor).as_str())
+        }
+    }
+}
+
+impl Readable for SourceVersion {
+    fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized {
+        let minor = u16::read(i)?;
+        let major = MajorVersion::from(u16::read(i)?);
+        Ok(SourceVersion { minor, major })
+    }
+}
+
+impl SourceVersion {
+    pub fn new(major: MajorVersion, minor: u16) -> SourceVersion { SourceVersion { minor, major } }
+    pub fn minor(&self) -> u16 { self.minor }
+    pub fn major(&self) -> MajorVersion { self.major }
+
+    /// Whether the class uses the preview features of its Java release and
+    /// can only be loaded by that release with --enable-preview
+    pub fn is_preview(&self) -> bool { self.minor == PREVIEW_MINOR }
+
+    /// Whether classes of this version can use the feature. Preview classes
+    /// can also use features which were still in preview in their release
+    pub fn supports(&self, feature: Feature) -> bool {
+        let major = u16::from(self.major);
+        major >= feature.major()
+            || self.is_preview() && feature.preview_major().is_some_and(|preview| major >= preview)
     }
 }
 
 #[allow(dead_code)]
-#[derive(Copy, Clone, Eq, PartialEq, IntoPrimitive, TryFromPrimitive)]
+#[derive(Copy, Clone, Eq, PartialEq, IntoPrimitive, FromPrimitive)]
 #[repr(u16)]
 pub enum MajorVersion {
     JavaLE4 = 48,
@@ -41,30 +80,85 @@ pub enum MajorVersion {
     Java15 = 59,
     Java16 = 60,
     Java17 = 61,
-    #[num_enum(default)]
-    Unknown,
+    Java18 = 62,
+    Java19 = 63,
+    Java20 = 64,
+    Java21 = 65,
+    Java22 = 66,
+    Java23 = 67,
+    Java24 = 68,
+    Java25 = 69,
+    // Versions before Java 1.4 and releases newer than this crate. The
+    // value is kept so the class can still be written back out
+    #[num_enum(catch_all)]
+    Unknown(u16),
+}
+
+impl MajorVersion {
+    /// The Java release the version belongs to (e.g. 21 for 65). Releases
+    /// before Java 5 are numbered 1.x so Java 1.4 is 4
+    pub fn feature_level(&self) -> u16 {
+        u16::from(*self).saturating_sub(44)
+    }
 }
 
 impl Debug for MajorVersion {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.write_str(match self {
-            MajorVersion::JavaLE4 => "Java LE 4",
-            MajorVersion::Java5 => "Java 5",
-            MajorVersion::Java6 => "Java 6",
-            MajorVersion::Java7 => "Java 7",
-            MajorVersion::Java8 => "Java 8",
-            MajorVersion::Java9 => "Java 9",
-            MajorVersion::Java10 => "Java 10",
-            MajorVersion::Java11 => "Java 11",
-            MajorVersion::Java12 => "Java 12",
-            MajorVersion::Java13 => "Java 13",
-            MajorVersion::Java14 => "Java 14",
-            MajorVersion::Java15 => "Java 15",
-            MajorVersion::Java16 => "Java 16",
-            MajorVersion::Java17 => "Java 17",
-            MajorVersion::Unknown => "Unknown"
+        match self {
+            MajorVersion::JavaLE4 => f.write_str("Java LE 4"),
+            MajorVersion::Unknown(value) => write!(f, "Unknown ({})", value),
+            _ => write!(f, "Java {}", self.feature_level()),
+        }
+    }
+}
+
+/// Class file and language features which depend on the version of the class
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Feature {
+    // The Module, ModulePackages and ModuleMainClass attributes
+    Modules,
+    // The NestHost and NestMembers attributes
+    NestMates,
+    Records,
+    // The PermittedSubclasses attribute
+    SealedClasses,
+    // Switches on the type of a value using SwitchBootstraps
+    PatternSwitch,
+}
+
+impl Feature {
+    /// The major version the feature became a standard part of
+    pub fn major(&self) -> u16 {
+        u16::from(match self {
+            Feature::Modules => MajorVersion::Java9,
+            Feature::NestMates => MajorVersion::Java11,
+            Feature::Records => MajorVersion::Java16,
+            Feature::SealedClasses => MajorVersion::Java17,
+            Feature::PatternSwitch => MajorVersion::Java21,
         })
     }
+
+    /// The first major version the feature was available in as a preview
+    pub fn preview_major(&self) -> Option<u16> {
+        match self {
+            Feature::Records => Some(MajorVersion::Java14),
+            Feature::SealedClasses => Some(MajorVersion::Java15),
+            Feature::PatternSwitch => Some(MajorVersion::Java17),
+            _ => None,
+        }.map(u16::from)
+    }
+
+    /// The feature which introduced the attribute. None for the attributes
+    /// which aren't gated
+    pub fn of_attribute(name: &str) -> Option<Feature> {
+        match name {
+            "Module" | "ModulePackages" | "ModuleMainClass" => Some(Feature::Modules),
+            "NestHost" | "NestMembers" => Some(Feature::NestMates),
+            "Record" => Some(Feature::Records),
+            "PermittedSubclasses" => Some(Feature::SealedClasses),
+            _ => None,
+        }
+    }
 }
 
 pub const CLASS_SIGNATURE: u32 = 0xCAFEBABE;
@@ -74,55 +168,253 @@ pub struct Class {
     pub version: SourceVersion,
     pub constant_pool: ConstantPool,
     pub access_flags: AccessFlags,
+    // The this_class, super_class and interfaces indexes the paths were read from
+    pub this_class: PoolIndex,
     pub class_path: ClassPath,
+    pub super_class: PoolIndex,
     pub super_class_path: Option<ClassPath>,
+    pub interface_indexes: Vec<PoolIndex>,
     pub interfaces: Vec<ClassPath>,
     pub fields: Vec<Member>,
     pub methods: Vec<Member>,
     pub attributes: Vec<Attribute>,
+    // The nesting from the InnerClasses and EnclosingMethod attributes,
+    // collected once the attributes are decoded
+    pub nesting: ClassNesting,
 }
 
-impl Readable for Class {
+/// The portion of a class file up to and including the interfaces
+/// table. Reading only the header skips all the fields, methods and
+/// attributes which is useful when building an index of many classes
+#[derive(Debug)]
+pub struct ClassHeader {
+    pub version: SourceVersion,
+    pub constant_pool: ConstantPool,
+    pub access_flags: AccessFlags,
+    // The this_class, super_class and interfaces indexes the paths were read from
+    pub this_class: PoolIndex,
+    pub class_path: ClassPath,
+    pub super_class: PoolIndex,
+    pub super_class_path: Option<ClassPath>,
+    pub interface_indexes: Vec<PoolIndex>,
+    pub interfaces: Vec<ClassPath>,
+}
+
+impl Readable for ClassHeader {
     fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized {
+        ClassHeader::read_with_limits(i, &ParseLimits::default())
+    }
+}
+
+impl ClassHeader {
+    /// Reads the class header while enforcing the provided parse limits
+    pub fn read_with_limits<R: Read>(i: &mut R, limits: &ParseLimits) -> ReadResult<Self> {
+        let mut r = PositionReader::new(i);
+        ClassHeader::read_header(&mut r, limits)
+            .map_err(|err| err.at_offset(r.position()))
+    }
+
+    fn read_header<R: Read>(i: &mut R, limits: &ParseLimits) -> ReadResult<Self> {
         let magic_number = u32::read(i)?;
         if magic_number != CLASS_SIGNATURE {
             Err(ReadError::InvalidMagic(magic_number))?;
         }
 
-        let minor_version = u16::read(i)?;
-        let major_version = MajorVersion::try_from(u16::read(i)?)
-            .unwrap_or(MajorVersion::Unknown);
-
-        let version = SourceVersion { minor: minor_version, major: major_version };
-        let constant_pool = ConstantPool::read(i)?;
+        let version = SourceVersion::read(i)?;
+        let constant_pool = ConstantPool::read_with_limits(i, limits)
+            .map_err(|err| err.in_field("constant_pool"))?;
 
         let access_flags = AccessFlags::read(i)?;
 
-        let class_path = constant_pool.get_class_path(&u16::read(i)?)?
-            .ok_or(ReadError::NoClassName)?;
-        let super_class_path = constant_pool.get_class_path(&u16::read(i)?)?;
+        let this_class = PoolIndex::read(i)?;
+        let class_path = constant_pool.get_class_path(&this_class)
+            .map_err(ReadError::from)
+            .and_then(|value| value.ok_or(ReadError::NoClassName))
+            .map_err(|err| err.in_field("this_class"))?;
+        let super_class = PoolIndex::read(i)?;
+        let super_class_path = constant_pool.get_class_path(&super_class)
+            .map_err(|err| ReadError::from(err).in_field("super_class"))?;
 
-        let interfaces = u16::read_vec_closure(i, |r| -> ReadResult<ClassPath> {
-            let name_index = PoolIndex::read(r)?;
-            constant_pool.get_class_path_required(&name_index)
-                .map_err(ReadError::from)
-        })?;
+        let interface_indexes: Vec<PoolIndex> = u16::read_vec(i)
+            .map_err(|err| err.in_field("interfaces"))?;
+        let interfaces = interface_indexes.iter()
+            .enumerate()
+            .map(|(index, value)| constant_pool.get_class_path_required(value)
+                .map_err(|err| ReadError::from(err).in_element(index)))
+            .collect::<ReadResult<Vec<ClassPath>>>()
+            .map_err(|err| err.in_field("interfaces"))?;
 
-        let fields = u16::read_vec_closure(i, |r| Member::read(r, &constant_pool))?;
-        let methods = u16::read_vec_closure(i, |r| Member::read(r, &constant_pool))?;
-        let attributes = u16::read_vec_closure(i, |r| Attribute::read(r, &constant_pool))?;
-
-        Ok(Class {
+        Ok(ClassHeader {
             version,
             constant_pool,
             access_flags,
+            this_class,
             class_path,
+            super_class,
             super_class_path,
+            interface_indexes,
             interfaces,
+        })
+    }
+}
+
+impl Readable for Class {
+    fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized {
+        Class::read_with_limits(i, &ParseLimits::default())
+    }
+}
+
+impl Class {
+    /// Reads a class while enforcing the provided parse limits. Input
+    /// exceeding the limits fails with `ReadError::LimitExceeded`
+    pub fn read_with_limits<R: Read>(i: &mut R, limits: &ParseLimits) -> ReadResult<Self> {
+        Class::read_located(i, limits, false)
+    }
+
+    /// Reads a class without decoding any of its attributes. The attribute
+    /// values are left as `AttributeValue::Undecoded` until they are decoded
+    /// using `Attribute::decode`
+    pub fn read_lazy<R: Read>(i: &mut R, limits: &ParseLimits) -> ReadResult<Self> {
+        Class::read_located(i, limits, true)
+    }
+
+    /// Reads the class keeping track of the position so that any errors
+    /// include the offset they occurred at
+    fn read_located<R: Read>(i: &mut R, limits: &ParseLimits, lazy: bool) -> ReadResult<Self> {
+        let mut r = PositionReader::new(i);
+        ClassHeader::read_header(&mut r, limits)
+            .and_then(|header| Class::read_members(header, &mut r, limits, lazy))
+            .map_err(|err| err.at_offset(r.position()))
+    }
+
+    /// Reads the remainder of the class following the header
+    fn read_members<R: Read>(header: ClassHeader, i: &mut R, limits: &ParseLimits, lazy: bool) -> ReadResult<Self> {
+        let constant_pool = header.constant_pool;
+        let read_member = |r: &mut R| if lazy {
+            Member::read_lazy(r, &constant_pool, limits)
+        } else {
+            Member::read(r, &constant_pool, limits)
+        };
+        let fields = u16::read_vec_closure(i, read_member)
+            .map_err(|err| err.in_field("fields"))?;
+        let methods = u16::read_vec_closure(i, read_member)
+            .map_err(|err| err.in_field("methods"))?;
+        let attributes = u16::read_vec_closure(i, |r| if lazy {
+            Attribute::read_lazy(r, &constant_pool, limits)
+        } else {
+            Attribute::read(r, &constant_pool, limits)
+        }).map_err(|err| err.in_field("attributes"))?;
+
+        let mut class = Class {
+            version: header.version,
+            constant_pool,
+            access_flags: header.access_flags,
+            this_class: header.this_class,
+            class_path: header.class_path,
+            super_class: header.super_class,
+            super_class_path: header.super_class_path,
+            interface_indexes: header.interface_indexes,
+            interfaces: header.interfaces,
             fields,
             methods,
             attributes,
-        })
+            nesting: ClassNesting::default(),
+        };
+        class.finish();
+        Ok(class)
+    }
+
+    /// Decodes any attributes of the class and its members which were
+    /// left undecoded by `Class::read_lazy`. The offsets of any errors
+    /// aren't known as the class is no longer being read
+    pub fn decode_attributes(&mut self, limits: &ParseLimits) -> ReadResult<()> {
+        let constant_pool = &self.constant_pool;
+        for (name, members) in [("fields", &mut self.fields), ("methods", &mut self.methods)] {
+            for (index, member) in members.iter_mut().enumerate() {
+                for attribute in &mut member.attributes {
+                    attribute.decode(constant_pool, limits)
+                        .map_err(|err| err.in_field("attributes").in_element(index).in_field(name))?;
+                }
+            }
+        }
+        for attribute in &mut self.attributes {
+            attribute.decode(constant_pool, limits)
+                .map_err(|err| err.in_field("attributes"))?;
+        }
+        self.finish();
+        Ok(())
+    }
+
+    /// The JVM ignores attributes from versions newer than the class (JVMS
+    /// 4.7) so they are turned back into unknown attributes. Classes read
+    /// lazily are left as they were until their attributes are decoded
+    fn ignore_unsupported_attributes(&mut self) {
+        for attribute in &mut self.attributes {
+            let supported = Feature::of_attribute(&attribute.name)
+                .is_none_or(|feature| self.version.supports(feature));
+            if supported || matches!(attribute.value, AttributeValue::Unknown(_) | AttributeValue::Undecoded(_)) {
+                continue;
+            }
+            let mut data = Vec::new();
+            if attribute.value.write(&mut data, &self.constant_pool).is_ok() {
+                attribute.value = AttributeValue::Unknown(data);
+            }
+        }
+    }
+
+    /// Finishes a class built from its decoded parts. Every way of reading
+    /// a class ends with this so they all produce the same model
+    pub(crate) fn finish(&mut self) {
+        self.ignore_unsupported_attributes();
+        self.nesting = ClassNesting::new(&self.class_path.internal_path(), &self.attributes, &self.constant_pool);
+        self.resolve_class_paths();
+    }
+
+    /// The path of a class referenced by this class using the nesting from
+    /// its attributes rather than splitting the name on $
+    pub fn resolve_class_path(&self, internal_name: &str) -> ClassPath {
+        self.nesting.resolve(internal_name)
+    }
+
+    /// Replaces the paths read from the header and member descriptors, which
+    /// are split on $, with the paths resolved from the attributes. Classes
+    /// read lazily are left as they were until their attributes are decoded
+    fn resolve_class_paths(&mut self) {
+        let nesting = &self.nesting;
+        let resolve = |path: &mut ClassPath| *path = nesting.resolve(&path.internal_path());
+        resolve(&mut self.class_path);
+        self.super_class_path.iter_mut().for_each(resolve);
+        self.interfaces.iter_mut().for_each(resolve);
+        for member in self.fields.iter_mut().chain(self.methods.iter_mut()) {
+            nesting.resolve_descriptor(&mut member.descriptor);
+        }
+    }
+}
+
+impl Writable for Class {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        CLASS_SIGNATURE.write(o)?;
+        self.version.minor.write(o)?;
+        u16::from(self.version.major).write(o)?;
+        self.constant_pool.write(o)?;
+        self.access_flags.write(o)?;
+
+        let pool = &self.constant_pool;
+        pool.class_index(self.this_class, &self.class_path)?.write(o)?;
+        match &self.super_class_path {
+            Some(value) => pool.class_index(self.super_class, value)?.write(o)?,
+            None => 0u16.write(o)?,
+        }
+        u16::try_from(self.interfaces.len())
+            .map_err(|_| WriteError::LengthOverflow(self.interfaces.len()))?
+            .write(o)?;
+        for (index, value) in self.interfaces.iter().enumerate() {
+            let read_index = self.interface_indexes.get(index).copied().unwrap_or(0);
+            pool.class_index(read_index, value)?.write(o)?;
+        }
+        u16::write_vec_closure(o, &self.fields, |o, value| value.write(o, pool))?;
+        u16::write_vec_closure(o, &self.methods, |o, value| value.write(o, pool))?;
+        u16::write_vec_closure(o, &self.attributes, |o, value| value.write(o, pool))
     }
 }
 
@@ -133,41 +425,259 @@ pub struct Import {
 }
 
 impl Class {
-    fn collect_imports_for(descriptor: &Descriptor) -> Vec<&ClassPath> {
-        let mut out = Vec::new();
+    /// The entries of the BootstrapMethods attribute, empty when the
+    /// class has no such attribute
+    pub fn bootstrap_methods(&self) -> &[BootstrapMethod] {
+        self.attributes.iter()
+            .find_map(|attribute| match &attribute.value {
+                AttributeValue::BootstrapMethods(values) => Some(values.as_slice()),
+                _ => None
+            })
+            .unwrap_or(&[])
+    }
+
+    /// Whether this is a record class. Records extend java.lang.Record
+    /// and describe their components in the Record attribute
+    pub fn is_record(&self) -> bool {
+        self.super_class_path.as_ref().is_some_and(|value| value.is_java_lang() && value.name == "Record")
+            && self.record_components().is_some()
+    }
+
+    /// The components of a record class in declaration order
+    pub fn record_components(&self) -> Option<&[RecordComponent]> {
+        self.attributes.iter()
+            .find_map(|attribute| match &attribute.value {
+                AttributeValue::Record(components) => Some(components.as_slice()),
+                _ => None
+            })
+    }
+
+    /// Resolves the classes of the class indexes in the first attribute
+    /// matched by `select`. None when the class has no such attribute
+    fn attribute_classes(
+        &self,
+        select: impl Fn(&AttributeValue) -> Option<&Vec<PoolIndex>>,
+    ) -> Option<Result<Vec<ClassPath>, ConstantError>> {
+        self.attributes.iter()
+            .find_map(|attribute| select(&attribute.value))
+            .map(|values| values.iter()
+                .map(|index| self.constant_pool.get_class_name(index).map(|name| self.nesting.resolve(name)))
+                .collect())
+    }
+
+    /// Whether the class is sealed and only allows the classes in its
+    /// PermittedSubclasses attribute to extend it
+    pub fn is_sealed(&self) -> bool {
+        self.attributes.iter()
+            .any(|attribute| matches!(attribute.value, AttributeValue::PermittedSubclasses(_)))
+    }
+
+    /// The classes permitted to extend or implement a sealed class.
+    /// Empty when the class isn't sealed
+    pub fn permitted_subclasses(&self) -> Result<Vec<ClassPath>, ConstantError> {
+        self.attribute_classes(|value| match value {
+            AttributeValue::PermittedSubclasses(values) => Some(values),
+            _ => None
+        }).unwrap_or(Ok(Vec::new()))
+    }
+
+    /// The host of the nest this class is a member of. Hosts and classes
+    /// not in a nest have no NestHost attribute
+    pub fn nest_host(&self) -> Result<Option<ClassPath>, ConstantError> {
+        self.attributes.iter()
+            .find_map(|attribute| match &attribute.value {
+                AttributeValue::NestHost(index) => Some(self.constant_pool.get_class_path_required(index)),
+                _ => None
+            })
+            .transpose()
+    }
+
+    /// The members of the nest this class is the host of
+    pub fn nest_members(&self) -> Result<Vec<ClassPath>, ConstantError> {
+        self.attribute_classes(|value| match value {
+            AttributeValue::NestMembers(values) => Some(values),
+            _ => None
+        }).unwrap_or(Ok(Vec::new()))
+    }
+
+    /// Whether this is a module-info class which declares a module
+    /// rather than a class
+    pub fn is_module(&self) -> bool {
+        self.access_flags.is_set(ClassAccessFlag::Module)
+    }
+
+    /// The generic signature of the class. None when the class doesn't
+    /// declare type parameters or extend or implement a generic type
+    pub fn signature(&self) -> Result<Option<ClassSignature>, SignatureError> {
+        signature::signature(&self.attributes)
+            .map(|value| ClassSignature::parse(value))
+            .transpose()
+    }
+
+    /// The Module attribute of a module-info class
+    pub fn module(&self) -> Option<&Module> {
+        self.attributes.iter()
+            .find_map(|attribute| match &attribute.value {
+                AttributeValue::Module(value) => Some(value),
+                _ => None
+            })
+    }
+
+    /// The internal names of all the packages of the module including
+    /// those that aren't exported or opened
+    pub fn module_packages(&self) -> Result<Vec<&String>, ConstantError> {
+        self.attributes.iter()
+            .filter_map(|attribute| match &attribute.value {
+                AttributeValue::ModulePackages(values) => Some(values),
+                _ => None
+            })
+            .flatten()
+            .map(|index| self.constant_pool.get_package_name(index))
+            .collect()
+    }
+
+    /// The main class of the module if it has one
+    pub fn module_main_class(&self) -> Result<Option<ClassPath>, ConstantError> {
+        self.attributes.iter()
+            .find_map(|attribute| match &attribute.value {
+                AttributeValue::ModuleMainClass(index) => Some(self.constant_pool.get_class_path_required(index)),
+                _ => None
+            })
+            .transpose()
+    }
+
+    /// The visible and invisible annotations of the class
+    pub fn annotations(&self) -> impl Iterator<Item=&Annotation> {
+        annotation::annotations(&self.attributes)
+    }
+
+    fn collect_imports_for(descriptor: &Descriptor, out: &mut Vec<ClassPath>) {
         match descriptor {
-            Descriptor::Class(path) => {
-                if !path.is_java_lang() {
-                    out.push(path)
+            Descriptor::Class(path)
+                if !path.is_java_lang() => {
+                    out.push(path.clone())
                 }
-            }
             Descriptor::Array(arr) => {
-                out.append(&mut Class::collect_imports_for(&arr.descriptor))
+                Class::collect_imports_for(&arr.descriptor, out)
             }
             Descriptor::Method(method) => {
                 for x in &method.parameters {
-                    out.append(&mut Class::collect_imports_for(&x))
+                    Class::collect_imports_for(x, out)
                 }
-                out.append(&mut Class::collect_imports_for(&method.return_type))
+                Class::collect_imports_for(&method.return_type, out)
             }
             _ => {}
         }
-        out
     }
 
-    pub fn collect_imports(&self) -> Vec<&ClassPath> {
+    /// Collects the annotation type along with the types of any enum,
+    /// class or nested annotation values
+    fn collect_imports_for_annotation(&self, annotation: &Annotation, out: &mut Vec<ClassPath>) {
+        if let Ok(descriptor) = annotation.type_descriptor(&self.constant_pool) {
+            Class::collect_imports_for(&descriptor, out);
+        }
+        for (_, value) in &annotation.elements {
+            self.collect_imports_for_element(value, out);
+        }
+    }
+
+    fn collect_imports_for_element(&self, value: &ElementValue, out: &mut Vec<ClassPath>) {
+        match value {
+            ElementValue::Enum { type_name_index: index, .. } |
+            ElementValue::Class(index) => {
+                if let Ok(value) = self.constant_pool.get_utf8(index) {
+                    Class::collect_imports_for(&Descriptor::parse(value), out);
+                }
+            }
+            ElementValue::Annotation(annotation) => self.collect_imports_for_annotation(annotation, out),
+            ElementValue::Array(values) => {
+                for value in values {
+                    self.collect_imports_for_element(value, out);
+                }
+            }
+            ElementValue::Const { .. } => {}
+        }
+    }
+
+    /// The classes used by the declarations of the class and its members
+    /// (including their annotations and type annotations) which need importing. Each class is
+    /// only included once
+    pub fn collect_imports(&self) -> Vec<ClassPath> {
         let mut out = Vec::new();
+        let mut annotations: Vec<&Annotation> = self.annotations().collect();
+        annotations.extend(type_annotations(&self.attributes).map(|annotation| &annotation.annotation));
         for field in &self.fields {
-            out.append(&mut Class::collect_imports_for(&field.descriptor))
+            Class::collect_imports_for(&field.descriptor, &mut out);
+            annotations.extend(field.annotations());
+            annotations.extend(type_annotations(&field.attributes).map(|annotation| &annotation.annotation));
         }
         for method in &self.methods {
-            out.append(&mut Class::collect_imports_for(&method.descriptor))
+            Class::collect_imports_for(&method.descriptor, &mut out);
+            annotations.extend(method.annotations());
+            annotations.extend(type_annotations(&method.attributes).map(|annotation| &annotation.annotation));
+            if let Descriptor::Method(descriptor) = &method.descriptor {
+                for parameter in 0..descriptor.parameters.len() {
+                    annotations.extend(method.parameter_annotations(parameter));
+                }
+            }
         }
-        out
+        for annotation in annotations {
+            self.collect_imports_for_annotation(annotation, &mut out);
+        }
+        // Type arguments and bounds of generic types aren't in the descriptors
+        let mut generic = Vec::new();
+        if let Ok(Some(signature)) = self.signature() {
+            generic.extend(signature.class_paths());
+        }
+        for field in &self.fields {
+            if let Ok(Some(signature)) = field.field_signature() {
+                generic.extend(signature.class_paths());
+            }
+        }
+        for method in &self.methods {
+            if let Ok(Some(signature)) = method.method_signature() {
+                generic.extend(signature.class_paths());
+            }
+        }
+        for path in generic.into_iter().filter(|path| *path != self.class_path) {
+            Class::collect_imports_for(&Descriptor::Class(path), &mut out);
+        }
+        // Signatures and annotations name classes without their nesting
+        let mut seen = HashSet::new();
+        out.into_iter()
+            .map(|path| self.nesting.resolve(&path.internal_path()))
+            .filter(|path| seen.insert(path.full_path()))
+            .collect()
     }
 }
 
 
+/// How a class is declared relative to the class enclosing it
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NestingKind {
+    TopLevel,
+    // Declared as a member of its outer class
+    Member,
+    // Declared with a name inside a method or initializer
+    Local,
+    Anonymous,
+}
+
+impl NestingKind {
+    /// Guesses the kind of a class from the segment of its name following
+    /// a $. javac names anonymous classes with a number (Outer$1) and puts a
+    /// number before the name of local classes (Outer$1Local)
+    fn guess(segment: &str) -> NestingKind {
+        if segment.bytes().all(|c| c.is_ascii_digit()) {
+            NestingKind::Anonymous
+        } else if segment.starts_with(|c: char| c.is_ascii_digit()) {
+            NestingKind::Local
+        } else {
+            NestingKind::Member
+        }
+    }
+}
+
 /// Represents a path to a class includes outer classes,
 /// the packages list and the class name
 #[derive(Clone)]
@@ -175,23 +685,64 @@ pub struct ClassPath {
     pub name: String,
     pub package: Vec<String>,
     pub outer_classes: Vec<String>,
+    // The kind of each of the outer classes followed by the class itself.
+    // The names are segments of the binary name so local classes keep
+    // the number before their name (e.g. 1Local)
+    pub nesting: Vec<NestingKind>,
 }
 
 impl ClassPath {
+    /// Splits an internal name (e.g. java/util/Map$Entry) guessing the
+    /// outer classes from the $ separators. Top level classes with a $ in
+    /// their name are split incorrectly so `Class::resolve_class_path` is
+    /// preferred when the class referencing the name is available
     pub fn from(value: &str) -> Self {
+        let mut path = ClassPath::top_level(value);
+        // Names such as Scala's Foo$ can't be split into nested classes
+        if path.name.split('$').any(str::is_empty) {
+            return path;
+        }
+        let class = std::mem::take(&mut path.name);
+        let mut segments = class.split('$');
+        path.name = segments.next().unwrap_or_default().to_string();
+        for segment in segments {
+            path = path.nested(segment, NestingKind::guess(segment));
+        }
+        path
+    }
+
+    /// The path of a top level class keeping any $ in its name
+    pub fn top_level(value: &str) -> Self {
         // Package components are split using slashes
         let mut package: Vec<String> = value.split('/')
             .map(|s| s.to_string())
             .collect();
         // Class is the last value of the packages list
-        let class = package.remove(package.len() - 1);
-        // If this class is a child class the name will be divided
-        // using the $ symbol
-        let mut outer_classes: Vec<String> = class.split('$')
-            .map(|s| s.to_string())
-            .collect();
-        let name = outer_classes.remove(outer_classes.len() - 1);
-        ClassPath { package, outer_classes, name }
+        let name = package.remove(package.len() - 1);
+        ClassPath { name, package, outer_classes: Vec::new(), nesting: vec![NestingKind::TopLevel] }
+    }
+
+    /// The path of a class nested in this class where the name is the
+    /// segment of the binary name following the $
+    pub fn nested(mut self, name: &str, kind: NestingKind) -> Self {
+        self.outer_classes.push(std::mem::replace(&mut self.name, name.to_string()));
+        self.nesting.push(kind);
+        self
+    }
+
+    /// How the class itself is declared
+    pub fn kind(&self) -> NestingKind {
+        self.nesting.last().copied().unwrap_or(NestingKind::TopLevel)
+    }
+
+    /// The name the class is declared with in source. Empty for
+    /// anonymous classes
+    pub fn simple_name(&self) -> &str {
+        match self.kind() {
+            NestingKind::Anonymous => "",
+            NestingKind::Local => self.name.trim_start_matches(|c: char| c.is_ascii_digit()),
+            _ => &self.name,
+        }
     }
 
     pub fn is_object(&self) -> bool { self.is_java_lang() && self.outer_classes.is_empty() && self.name == "Object" }
@@ -203,12 +754,7 @@ impl ClassPath {
     }
     pub fn package_str(&self) -> String { self.package.join(".") }
     pub fn jar_path(&self) -> String {
-        let mut out = self.package_str();
-        if !self.outer_classes.is_empty() {
-            out += self.outer_classes.join("$").as_str();
-            out += "$";
-        }
-        out += self.name.as_str();
+        let mut out = self.internal_path();
         out += ".class";
         out
     }
@@ -225,7 +771,7 @@ impl ClassPath {
         out
     }
     pub fn internal_path(&self) -> String {
-        let mut out = self.package_str();
+        let mut out = self.package.join("/");
         if !out.is_empty() {
             out += "/";
         }
@@ -238,6 +784,16 @@ impl ClassPath {
     }
 }
 
+// Paths naming the same class are equal even when only one of them was
+// resolved from the InnerClasses attribute
+impl PartialEq for ClassPath {
+    fn eq(&self, other: &Self) -> bool {
+        self.internal_path() == other.internal_path()
+    }
+}
+
+impl Eq for ClassPath {}
+
 impl Debug for ClassPath {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.write_str(self.full_path().as_str())
@@ -1,14 +1,16 @@
 use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
-use std::io::Read;
+use std::io::{Read, Write};
 
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 use crate::class::class::ClassPath;
-use crate::class::descriptor::Descriptor;
-use crate::error::ConstantError;
+use crate::class::attribute::BootstrapMethod;
+use crate::class::descriptor::{Descriptor, MethodDescriptor};
+use crate::error::{ConstantError, WriteError};
 use crate::error::ReadError::UnknownConstantTag;
-use crate::io::{Readable, ReadResult};
+use crate::io::{ParseLimits, Readable, ReadResult, VecReadableBytesSize, VecWritableBytesSize, Writable, WriteResult};
+use crate::mutf8;
 use crate::readable_struct;
 
 /// Represents an index in the pool.
@@ -37,14 +39,13 @@ impl Debug for ConstantPool {
             f.write_str("ConstantPool { ")?;
             let mut keys: Vec<&u16> = self.inner.keys().collect();
             keys.sort(); // Obtain a sorted version of the keys
-            let last = keys.len() - 1;
             for (index, key) in keys.iter().enumerate() {
                 let v = self.inner.get(key)
                     .expect("expected constant pool to contain index");
-                f.write_str(format!("{}: {:?}", key, v).as_str())?;
-                if index != last {
+                if index != 0 {
                     f.write_str(", ")?;
                 }
+                f.write_str(format!("{}: {:?}", key, v).as_str())?;
             }
             f.write_str(" }")?;
         }
@@ -54,12 +55,23 @@ impl Debug for ConstantPool {
 
 impl Readable for ConstantPool {
     fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized {
+        ConstantPool::read_with_limits(i, &ParseLimits::default())
+    }
+}
+
+impl ConstantPool {
+    /// Reads the constant pool failing if its size exceeds the
+    /// maximum constant pool size of the provided limits
+    pub fn read_with_limits<R: Read>(i: &mut R, limits: &ParseLimits) -> ReadResult<Self> {
         let size = u16::read(i)?;
-        let mut pool = ConstantPool { inner: HashMap::with_capacity(size as usize) };
-        let mut index = 1;
-        while index < size {
-            let value = ConstantValue::read(i)?;
-            pool.inner.insert(index, value.value);
+        ParseLimits::check("constant pool size", size as u64, limits.max_constant_pool_size as u64)?;
+        let mut pool = ConstantPool { inner: HashMap::new() };
+        // Wider than a pool index so that a trailing Long or Double can't overflow
+        let mut index: u32 = 1;
+        while index < size as u32 {
+            let value = ConstantValue::read(i)
+                .map_err(|err| err.in_element(index as usize))?;
+            pool.inner.insert(index as PoolIndex, value.value);
             // Long and Double constants consume two indexes worth of data
             index += match value.tag {
                 ConstantTag::Long | ConstantTag::Double => 2,
@@ -70,7 +82,86 @@ impl Readable for ConstantPool {
     }
 }
 
+impl Writable for ConstantPool {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        // The pool size is one greater than the last index used by a constant
+        let size = match self.inner.iter().max_by_key(|(index, _)| **index) {
+            Some((index, value)) => (*index as usize) + value.width() as usize,
+            None => 1,
+        };
+        u16::try_from(size)
+            .map_err(|_| WriteError::LengthOverflow(size))?
+            .write(o)?;
+        let mut index = 1;
+        while (index as usize) < size {
+            let value = self.inner.get(&index)
+                .ok_or(WriteError::PoolGap(index))?;
+            value.write(o)?;
+            index += value.width();
+        }
+        Ok(())
+    }
+}
+
 impl ConstantPool {
+    /// Finds the index of the first utf8 constant with the provided value
+    pub fn find_utf8(&self, value: &str) -> Option<PoolIndex> {
+        self.inner.iter()
+            .filter(|(_, constant)| match constant {
+                Constant::Utf8(v) | Constant::Utf8Lossy(v, _) => v == value,
+                _ => false
+            })
+            .map(|(index, _)| *index)
+            .min()
+    }
+
+    /// Finds the index of the first class constant which refers to
+    /// the provided internal class name
+    pub fn find_class(&self, internal_name: &str) -> Option<PoolIndex> {
+        self.inner.iter()
+            .filter(|(_, constant)| match constant {
+                Constant::Class(name_index) => self.get_utf8(name_index)
+                    .map(|v| v == internal_name)
+                    .unwrap_or(false),
+                _ => false
+            })
+            .map(|(index, _)| *index)
+            .min()
+    }
+
+    /// Like `find_utf8` but fails with a write error when
+    /// the value is not present
+    pub fn require_utf8(&self, value: &str) -> Result<PoolIndex, WriteError> {
+        self.find_utf8(value)
+            .ok_or_else(|| WriteError::MissingConstant(value.to_string()))
+    }
+
+    /// Like `find_class` but fails with a write error when
+    /// the class is not present
+    pub fn require_class(&self, class: &ClassPath) -> Result<PoolIndex, WriteError> {
+        let internal_name = class.internal_path();
+        self.find_class(&internal_name)
+            .ok_or(WriteError::MissingConstant(internal_name))
+    }
+
+    /// The index of the utf8 constant to write for the value. The index the
+    /// value was read from is kept while it still holds the value so that
+    /// classes with duplicate constants are written back unchanged
+    pub fn utf8_index(&self, index: PoolIndex, value: &str) -> Result<PoolIndex, WriteError> {
+        match self.get_utf8(&index) {
+            Ok(current) if current == value => Ok(index),
+            _ => self.require_utf8(value)
+        }
+    }
+
+    /// Like `utf8_index` but for the class constant of the class
+    pub fn class_index(&self, index: PoolIndex, class: &ClassPath) -> Result<PoolIndex, WriteError> {
+        match self.get_class_name(&index) {
+            Ok(current) if *current == class.internal_path() => Ok(index),
+            _ => self.require_class(class)
+        }
+    }
+
     pub fn get_class_path(&self, index: &PoolIndex) -> Result<Option<ClassPath>, ConstantError> {
         if *index == 0 { return Ok(None); }
         match self.inner.get(index) {
@@ -86,22 +177,24 @@ impl ConstantPool {
     }
 
     pub fn get_class_path_required(&self, index: &PoolIndex) -> Result<ClassPath, ConstantError> {
+        self.get_class_name(index).map(|name| ClassPath::from(name))
+    }
+
+    /// The internal name of the class constant at the index
+    /// (e.g. java/util/Map$Entry)
+    pub fn get_class_name(&self, index: &PoolIndex) -> Result<&String, ConstantError> {
         match self.inner.get(index) {
-            Some(constant) => match constant {
-                Constant::Class(v) => Ok(ClassPath::from(
-                    self.get_utf8(v)
-                        .map_err(|_| ConstantError::InvalidClassReference(*index))?
-                )),
-                _ => Err(ConstantError::InvalidClassReference(*index))
-            }
-            None => Err(ConstantError::InvalidClassReference(*index))
+            Some(Constant::Class(v)) => self.get_utf8(v)
+                .map_err(|_| ConstantError::InvalidClassReference(*index)),
+            _ => Err(ConstantError::InvalidClassReference(*index))
         }
     }
 
     pub fn get_utf8(&self, index: &PoolIndex) -> Result<&String, ConstantError> {
         match self.inner.get(index) {
             Some(constant) => match constant {
-                Constant::Utf8(value) => Ok(value),
+                Constant::Utf8(value) |
+                Constant::Utf8Lossy(value, _) => Ok(value),
                 _ => Err(ConstantError::ExpectedUtf8(*index))
             }
             None => Err(ConstantError::NotFound(*index))
@@ -140,9 +233,132 @@ impl ConstantPool {
         }
     }
 
+    /// Obtains the class constant at the provided index as a descriptor.
+    /// Array classes are named using their descriptor (e.g. [I) so
+    /// they are parsed as such, all other names are class paths
+    pub fn get_class_descriptor(&self, index: &PoolIndex) -> Result<Descriptor, ConstantError> {
+        match self.inner.get(index) {
+            Some(Constant::Class(v)) => {
+                let name = self.get_utf8(v)
+                    .map_err(|_| ConstantError::InvalidClassReference(*index))?;
+                Ok(if name.starts_with('[') {
+                    Descriptor::parse(name)
+                } else {
+                    Descriptor::Class(ClassPath::from(name))
+                })
+            }
+            _ => Err(ConstantError::InvalidClassReference(*index))
+        }
+    }
+
+    pub fn get_method_handle(&self, index: &PoolIndex) -> Result<ResolvedMethodHandle, ConstantError> {
+        match self.inner.get(index) {
+            Some(Constant::MethodHandle(value)) => {
+                let kind = ReferenceKind::try_from(value.reference_kind)
+                    .map_err(|_| ConstantError::InvalidReferenceKind(value.reference_kind))?;
+                let reference = self.get_member_ref(&value.reference_index)?;
+                Ok(ResolvedMethodHandle { kind, reference })
+            }
+            Some(_) => Err(ConstantError::ExpectedMethodHandle(*index)),
+            None => Err(ConstantError::NotFound(*index))
+        }
+    }
+
+    pub fn get_method_type(&self, index: &PoolIndex) -> Result<MethodDescriptor, ConstantError> {
+        match self.inner.get(index) {
+            Some(Constant::MethodType(value)) => match Descriptor::parse(self.get_utf8(value)?) {
+                Descriptor::Method(descriptor) => Ok(descriptor),
+                _ => Err(ConstantError::ExpectedMethodType(*index))
+            }
+            Some(_) => Err(ConstantError::ExpectedMethodType(*index)),
+            None => Err(ConstantError::NotFound(*index))
+        }
+    }
+
+    /// Obtains the dynamically-computed constant (condy) at the provided index
+    pub fn get_dynamic(&self, index: &PoolIndex) -> Result<ResolvedDynamic, ConstantError> {
+        match self.inner.get(index) {
+            Some(Constant::Dynamic(value)) => self.resolve_dynamic(value),
+            Some(_) => Err(ConstantError::ExpectedDynamic(*index)),
+            None => Err(ConstantError::NotFound(*index))
+        }
+    }
+
+    pub fn get_invoke_dynamic(&self, index: &PoolIndex) -> Result<ResolvedDynamic, ConstantError> {
+        match self.inner.get(index) {
+            Some(Constant::InvokeDynamic(value)) => self.resolve_dynamic(value),
+            Some(_) => Err(ConstantError::ExpectedInvokeDynamic(*index)),
+            None => Err(ConstantError::NotFound(*index))
+        }
+    }
+
+    fn resolve_dynamic(&self, value: &DynamicConstant) -> Result<ResolvedDynamic, ConstantError> {
+        Ok(ResolvedDynamic {
+            bootstrap_method_attr_index: value.bootstrap_method_attr_index,
+            name_and_type: self.get_name_and_type(&value.name_and_type_index)?,
+        })
+    }
+
+    pub fn get_module_name(&self, index: &PoolIndex) -> Result<&String, ConstantError> {
+        match self.inner.get(index) {
+            Some(Constant::Module(value)) => self.get_utf8(value),
+            Some(_) => Err(ConstantError::ExpectedModule(*index)),
+            None => Err(ConstantError::NotFound(*index))
+        }
+    }
+
+    pub fn get_package_name(&self, index: &PoolIndex) -> Result<&String, ConstantError> {
+        match self.inner.get(index) {
+            Some(Constant::Package(value)) => self.get_utf8(value),
+            Some(_) => Err(ConstantError::ExpectedPackage(*index)),
+            None => Err(ConstantError::NotFound(*index))
+        }
+    }
+
+    /// Obtains a constant that can be loaded onto the stack by the ldc
+    /// instructions or used as a static argument of a bootstrap method
+    pub fn get_loadable(&self, index: &PoolIndex) -> Result<LoadableConstant, ConstantError> {
+        match self.inner.get(index) {
+            Some(constant) => Ok(match constant {
+                Constant::Integer(value) => LoadableConstant::Integer(*value),
+                Constant::Float(value) => LoadableConstant::Float(*value),
+                Constant::Long(value) => LoadableConstant::Long(*value),
+                Constant::Double(value) => LoadableConstant::Double(*value),
+                Constant::String(value) => LoadableConstant::String(self.get_utf8(value)?.clone()),
+                Constant::Class(_) => LoadableConstant::Class(self.get_class_descriptor(index)?),
+                Constant::MethodHandle(_) => LoadableConstant::MethodHandle(self.get_method_handle(index)?),
+                Constant::MethodType(_) => LoadableConstant::MethodType(self.get_method_type(index)?),
+                Constant::Dynamic(_) => LoadableConstant::Dynamic(self.get_dynamic(index)?),
+                _ => Err(ConstantError::NotLoadable(*index))?
+            }),
+            None => Err(ConstantError::NotFound(*index))
+        }
+    }
+
+    /// Resolves the bootstrap method at the provided index of the
+    /// BootstrapMethods attribute along with its static arguments
+    pub fn get_bootstrap_method(
+        &self,
+        bootstrap_methods: &[BootstrapMethod],
+        index: PoolIndex,
+    ) -> Result<ResolvedBootstrapMethod, ConstantError> {
+        let value = bootstrap_methods.get(index as usize)
+            .ok_or(ConstantError::BootstrapMethodNotFound(index))?;
+        let method_handle = self.get_method_handle(&value.method_ref)?;
+        let arguments = value.arguments.iter()
+            .map(|argument| self.get_loadable(argument))
+            .collect::<Result<Vec<LoadableConstant>, ConstantError>>()?;
+        Ok(ResolvedBootstrapMethod { method_handle, arguments })
+    }
+
     pub fn read_utf8<R: Read>(&self, i: &mut R) -> ReadResult<&String> {
+        self.read_utf8_indexed(i).map(|(_, value)| value)
+    }
+
+    /// Like `read_utf8` but also provides the index that was read
+    pub fn read_utf8_indexed<R: Read>(&self, i: &mut R) -> ReadResult<(PoolIndex, &String)> {
         let index = PoolIndex::read(i)?;
-        Ok(self.get_utf8(&index)?)
+        Ok((index, self.get_utf8(&index)?))
     }
 
     pub fn read_class_path<R: Read>(&self, i: &mut R) -> ReadResult<Option<ClassPath>> {
@@ -157,6 +373,80 @@ pub struct NameAndType {
     pub descriptor: Descriptor,
 }
 
+/// The kind of reference a method handle makes which
+/// determines how the handle behaves when invoked
+#[derive(Debug, Copy, Clone, Eq, PartialEq, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum ReferenceKind {
+    GetField = 1,
+    GetStatic = 2,
+    PutField = 3,
+    PutStatic = 4,
+    InvokeVirtual = 5,
+    InvokeStatic = 6,
+    InvokeSpecial = 7,
+    NewInvokeSpecial = 8,
+    InvokeInterface = 9,
+}
+
+impl ReferenceKind {
+    /// The name used for this kind by javap and the JVM specification
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReferenceKind::GetField => "REF_getField",
+            ReferenceKind::GetStatic => "REF_getStatic",
+            ReferenceKind::PutField => "REF_putField",
+            ReferenceKind::PutStatic => "REF_putStatic",
+            ReferenceKind::InvokeVirtual => "REF_invokeVirtual",
+            ReferenceKind::InvokeStatic => "REF_invokeStatic",
+            ReferenceKind::InvokeSpecial => "REF_invokeSpecial",
+            ReferenceKind::NewInvokeSpecial => "REF_newInvokeSpecial",
+            ReferenceKind::InvokeInterface => "REF_invokeInterface",
+        }
+    }
+
+    pub fn is_field(&self) -> bool {
+        matches!(self, ReferenceKind::GetField | ReferenceKind::GetStatic | ReferenceKind::PutField | ReferenceKind::PutStatic)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedMethodHandle {
+    pub kind: ReferenceKind,
+    pub reference: MemberReference,
+}
+
+/// A Dynamic or InvokeDynamic constant. The bootstrap method is an index
+/// into the BootstrapMethods attribute of the class and can be resolved
+/// using `ConstantPool::get_bootstrap_method`
+#[derive(Debug, Clone)]
+pub struct ResolvedDynamic {
+    pub bootstrap_method_attr_index: PoolIndex,
+    pub name_and_type: NameAndType,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedBootstrapMethod {
+    pub method_handle: ResolvedMethodHandle,
+    pub arguments: Vec<LoadableConstant>,
+}
+
+/// Constants which can be pushed onto the stack by ldc or passed
+/// as static arguments to bootstrap methods
+#[derive(Debug, Clone)]
+pub enum LoadableConstant {
+    Integer(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    String(String),
+    // Class constants are descriptors as they can also name array classes
+    Class(Descriptor),
+    MethodHandle(ResolvedMethodHandle),
+    MethodType(MethodDescriptor),
+    Dynamic(ResolvedDynamic),
+}
+
 #[derive(Debug, Clone)]
 pub struct MemberReference {
     pub class: ClassPath,
@@ -208,9 +498,13 @@ pub enum ConstantTag {
     Package = 20,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Constant {
     Utf8(String),
+    // Utf8 constant whose bytes were not valid modified utf-8 (e.g. unpaired surrogates).
+    // The lossy decoded value is kept alongside the original bytes so that the constant
+    // can still be used as a string and written back out unchanged.
+    Utf8Lossy(String, Vec<u8>),
     Integer(i32),
     Float(f32),
     Long(i64),
@@ -236,8 +530,15 @@ pub enum Constant {
 
 impl From<Constant> for ConstantTag {
     fn from(value: Constant) -> Self {
+        ConstantTag::from(&value)
+    }
+}
+
+impl From<&Constant> for ConstantTag {
+    fn from(value: &Constant) -> Self {
         match value {
-            Constant::Utf8 { .. } => ConstantTag::Utf8,
+            Constant::Utf8 { .. } |
+            Constant::Utf8Lossy { .. } => ConstantTag::Utf8,
             Constant::Integer { .. } => ConstantTag::Integer,
             Constant::Float { .. } => ConstantTag::Float,
             Constant::Long { .. } => ConstantTag::Long,
@@ -258,19 +559,11 @@ impl From<Constant> for ConstantTag {
     }
 }
 
-#[derive(Debug)]
-pub struct ConstantValue {
-    tag: ConstantTag,
-    value: Constant,
-}
-
-impl Readable for ConstantValue {
-    fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized {
-        let tag_raw = u8::read(i)?;
-        let tag = ConstantTag::try_from(tag_raw)
-            .map_err(|_| UnknownConstantTag(tag_raw))?;
-        let value = match tag {
-            ConstantTag::Utf8 => Constant::Utf8(String::read(i)?),
+impl Constant {
+    /// Reads the contents of a constant whose tag has already been read
+    pub fn read_with_tag<R: Read>(tag: ConstantTag, i: &mut R) -> ReadResult<Constant> {
+        Ok(match tag {
+            ConstantTag::Utf8 => Constant::from_utf8_bytes(u16::read_bytes(i)?),
             ConstantTag::Integer => Constant::Integer(i32::read(i)?),
             ConstantTag::Float => Constant::Float(f32::read(i)?),
             ConstantTag::Long => Constant::Long(i64::read(i)?),
@@ -287,7 +580,66 @@ impl Readable for ConstantValue {
             ConstantTag::InvokeDynamic => Constant::InvokeDynamic(DynamicConstant::read(i)?),
             ConstantTag::Module => Constant::Module(PoolIndex::read(i)?),
             ConstantTag::Package => Constant::Package(PoolIndex::read(i)?),
-        };
+        })
+    }
+
+    /// Creates a utf8 constant from the raw modified utf-8 bytes falling
+    /// back to a lossy constant when the bytes are not well-formed
+    pub fn from_utf8_bytes(bytes: Vec<u8>) -> Constant {
+        match mutf8::decode(&bytes) {
+            Ok(value) => Constant::Utf8(value),
+            Err(_) => Constant::Utf8Lossy(mutf8::decode_lossy(&bytes), bytes),
+        }
+    }
+
+    /// The number of pool indexes taken up by this constant. Long and
+    /// Double constants consume two indexes worth of data
+    pub fn width(&self) -> PoolIndex {
+        match self {
+            Constant::Long(_) | Constant::Double(_) => 2,
+            _ => 1
+        }
+    }
+}
+
+impl Writable for Constant {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        u8::from(ConstantTag::from(self)).write(o)?;
+        match self {
+            Constant::Utf8(value) => value.write(o),
+            Constant::Utf8Lossy(_, bytes) => u16::write_bytes(o, bytes),
+            Constant::Integer(value) => value.write(o),
+            Constant::Float(value) => value.write(o),
+            Constant::Long(value) => value.write(o),
+            Constant::Double(value) => value.write(o),
+            Constant::Class(index) |
+            Constant::String(index) |
+            Constant::MethodType(index) |
+            Constant::Module(index) |
+            Constant::Package(index) => index.write(o),
+            Constant::FieldRef(value) |
+            Constant::MethodRef(value) |
+            Constant::InterfaceMethodRef(value) => value.write(o),
+            Constant::NameAndType(value) => value.write(o),
+            Constant::MethodHandle(value) => value.write(o),
+            Constant::Dynamic(value) |
+            Constant::InvokeDynamic(value) => value.write(o),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConstantValue {
+    tag: ConstantTag,
+    value: Constant,
+}
+
+impl Readable for ConstantValue {
+    fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized {
+        let tag_raw = u8::read(i)?;
+        let tag = ConstantTag::try_from(tag_raw)
+            .map_err(|_| UnknownConstantTag(tag_raw))?;
+        let value = Constant::read_with_tag(tag, i)?;
         Ok(ConstantValue { tag, value })
     }
 }
@@ -1,8 +1,7 @@
 use std::fmt::{Debug, Formatter};
 
-use regex::Regex;
-
 use crate::class::class::ClassPath;
+use crate::error::DescriptorError;
 
 #[derive(Debug, Clone)]
 pub struct MethodDescriptor {
@@ -41,14 +40,6 @@ impl Debug for Descriptor {
 }
 
 impl Descriptor {
-
-    pub fn parse_all(value: &str) -> Vec<Descriptor> {
-        let regex = Regex::new(r"([BCDFIJSZV]|(L.*;)|(\[.*))")
-            .expect("invalid regex");
-        regex.find_iter(value)
-            .map(|v| Descriptor::parse(v.as_str())).collect()
-    }
-
     pub fn to_internal_java(&self) -> String {
         match self {
             Descriptor::Class(clazz) => format!("L{};", clazz.internal_path()),
@@ -60,11 +51,12 @@ impl Descriptor {
                 for x in &met.parameters {
                     out.push_str(x.to_internal_java().as_str());
                 }
-                out.push_str(")");
+                out.push(')');
                 out.push_str(met.return_type.to_internal_java().as_str());
                 out
             }
-            Descriptor::Unknown(v) => format!("/* unknown type */ {}", v.clone()),
+            // Unknown values are kept as is so they can be written back out
+            Descriptor::Unknown(v) => v.clone(),
             el => String::from(match el {
                 Descriptor::Byte => "B",
                 Descriptor::Char => "C",
@@ -91,7 +83,7 @@ impl Descriptor {
                 for x in &met.parameters {
                     out.push_str(x.to_internal_java().as_str());
                 }
-                out.push_str(")");
+                out.push(')');
                 out.push_str(met.return_type.to_internal_java().as_str());
                 out
             }
@@ -111,52 +103,201 @@ impl Descriptor {
         }
     }
 
+    /// The number of local variable or operand stack slots a value of this
+    /// type takes. Long and double take two slots and void takes none
+    pub fn size(&self) -> u16 {
+        match self {
+            Descriptor::Long | Descriptor::Double => 2,
+            Descriptor::Void => 0,
+            _ => 1,
+        }
+    }
+
+    /// Whether this is a category 2 type (long or double)
+    pub fn is_wide(&self) -> bool { self.size() == 2 }
+
+    /// Parses a field descriptor, a method descriptor or the void return
+    /// type. Malformed descriptors become `Descriptor::Unknown` so that
+    /// they are kept as is, use `try_parse` to find out why they failed
     pub fn parse(value: &str) -> Descriptor {
-        match value {
-            "B" => Descriptor::Byte,
-            "C" => Descriptor::Char,
-            "D" => Descriptor::Double,
-            "F" => Descriptor::Float,
-            "I" => Descriptor::Int,
-            "J" => Descriptor::Long,
-            "V" => Descriptor::Void,
-            "S" => Descriptor::Short,
-            "Z" => Descriptor::Boolean,
-            _ => {
-                if value.starts_with('L') {
-                    let mut name = value.split_at(value.len() - 1);
-                    name = name.0.split_at(1);
-                    Descriptor::Class(ClassPath::from(name.1))
-                } else if value.starts_with('[') {
-                    let name = value.trim_start_matches("[");
-                    let dimensions = (value.len() - name.len()) as u8;
-                    Descriptor::Array(ArrayDescriptor {
-                        dimensions,
-                        descriptor: Box::new(Descriptor::parse(name)),
-                    })
-                } else if value.starts_with('(') {
-                    if let Some(end) = value.rfind(')') {
-                        let parts = value.split_at(end);
-                        let parameters;
-                        if parts.0.len() != 1 {
-                            let raw_params = parts.0.split_at(1).1;
-                            parameters = Descriptor::parse_all(raw_params)
-                        } else {
-                            parameters = Vec::with_capacity(0);
-                        }
-                        let return_type_raw = parts.1.split_at(1).1;
-                        let return_type = Descriptor::parse(return_type_raw);
-                        Descriptor::Method(MethodDescriptor {
-                            parameters,
-                            return_type: Box::new(return_type),
-                        })
-                    } else {
-                        Descriptor::Unknown(value.to_string())
-                    }
-                } else {
-                    Descriptor::Unknown(value.to_string())
+        Descriptor::try_parse(value)
+            .unwrap_or_else(|_| Descriptor::Unknown(value.to_string()))
+    }
+
+    /// Strict form of `parse` which fails on malformed descriptors
+    pub fn try_parse(value: &str) -> Result<Descriptor, DescriptorError> {
+        if value.starts_with('(') {
+            return MethodDescriptor::parse(value).map(Descriptor::Method);
+        }
+        let mut reader = DescriptorReader { value: value.as_bytes(), position: 0 };
+        let descriptor = reader.field_type(true)?;
+        reader.finish()?;
+        Ok(descriptor)
+    }
+
+    /// Parses a field descriptor (JVMS 4.3.2) which can't be void
+    pub fn parse_field(value: &str) -> Result<Descriptor, DescriptorError> {
+        let mut reader = DescriptorReader { value: value.as_bytes(), position: 0 };
+        let descriptor = reader.field_type(false)?;
+        reader.finish()?;
+        Ok(descriptor)
+    }
+}
+
+impl MethodDescriptor {
+    /// Parses a method descriptor (JVMS 4.3.3)
+    pub fn parse(value: &str) -> Result<MethodDescriptor, DescriptorError> {
+        let mut reader = DescriptorReader { value: value.as_bytes(), position: 0 };
+        reader.expect(b'(')?;
+        let mut parameters = Vec::new();
+        while reader.peek() != Some(b')') {
+            parameters.push(reader.field_type(false)?);
+        }
+        reader.position += 1;
+        let return_type = reader.field_type(true)?;
+        reader.finish()?;
+        Ok(MethodDescriptor { parameters, return_type: Box::new(return_type) })
+    }
+
+    /// The number of slots taken by the arguments including the this
+    /// reference of instance methods. This is the size of the locals when
+    /// the method is invoked and the number of stack slots popped to call it
+    pub fn arguments_size(&self, is_static: bool) -> u16 {
+        let this_size = if is_static { 0 } else { 1 };
+        self.parameters.iter()
+            .fold(this_size, |size, parameter| size.saturating_add(parameter.size()))
+    }
+
+    /// The local variable index that the parameter at the position is stored in
+    pub fn parameter_slot(&self, parameter: usize, is_static: bool) -> Option<u16> {
+        if parameter >= self.parameters.len() {
+            return None;
+        }
+        let this_size = if is_static { 0 } else { 1 };
+        Some(self.parameters[..parameter].iter()
+            .fold(this_size, |size, parameter| size.saturating_add(parameter.size())))
+    }
+
+    /// The position of the parameter stored in the local variable index.
+    /// None for the this reference, the second slot of long and double
+    /// parameters and the locals after the parameters
+    pub fn parameter_at_slot(&self, slot: u16, is_static: bool) -> Option<usize> {
+        let mut next: u16 = if is_static { 0 } else { 1 };
+        for (index, parameter) in self.parameters.iter().enumerate() {
+            if next == slot {
+                return Some(index);
+            }
+            next = next.saturating_add(parameter.size());
+            if next > slot {
+                break;
+            }
+        }
+        None
+    }
+
+    /// The number of stack slots taken by the returned value. This is
+    /// the computational type category of the return type or 0 for void
+    pub fn return_size(&self) -> u16 { self.return_type.size() }
+
+    /// The change in the height of the operand stack after invoking the
+    /// method. The receiver is popped too unless the invoke is static
+    pub fn stack_delta(&self, is_static: bool) -> i32 {
+        self.return_size() as i32 - self.arguments_size(is_static) as i32
+    }
+}
+
+/// Reads descriptors from the bytes of the value. Every character with a
+/// meaning in a descriptor is ASCII so only class names need decoding
+struct DescriptorReader<'a> {
+    value: &'a [u8],
+    position: usize,
+}
+
+impl<'a> DescriptorReader<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.value.get(self.position).copied()
+    }
+
+    /// The character at the position for errors. Class names can contain
+    /// any character so the position may be part way through a character
+    fn unexpected(&self, position: usize) -> DescriptorError {
+        match std::str::from_utf8(&self.value[position..]) {
+            Ok(value) => match value.chars().next() {
+                Some(value) => DescriptorError::Unexpected(position, value),
+                None => DescriptorError::UnexpectedEnd,
+            },
+            Err(_) => DescriptorError::Unexpected(position, char::REPLACEMENT_CHARACTER),
+        }
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), DescriptorError> {
+        match self.peek() {
+            Some(value) if value == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err(self.unexpected(self.position))
+        }
+    }
+
+    fn finish(&self) -> Result<(), DescriptorError> {
+        match self.peek() {
+            Some(_) => Err(self.unexpected(self.position)),
+            None => Ok(())
+        }
+    }
+
+    fn field_type(&mut self, allow_void: bool) -> Result<Descriptor, DescriptorError> {
+        let start = self.position;
+        while self.peek() == Some(b'[') {
+            self.position += 1;
+        }
+        let dimensions = self.position - start;
+        if dimensions > 0 {
+            let dimensions = u8::try_from(dimensions)
+                .map_err(|_| DescriptorError::TooManyDimensions(start))?;
+            let descriptor = self.field_type(false)?;
+            return Ok(Descriptor::Array(ArrayDescriptor { dimensions, descriptor: Box::new(descriptor) }));
+        }
+        let value = self.peek().ok_or(DescriptorError::UnexpectedEnd)?;
+        self.position += 1;
+        Ok(match value {
+            b'B' => Descriptor::Byte,
+            b'C' => Descriptor::Char,
+            b'D' => Descriptor::Double,
+            b'F' => Descriptor::Float,
+            b'I' => Descriptor::Int,
+            b'J' => Descriptor::Long,
+            b'S' => Descriptor::Short,
+            b'Z' => Descriptor::Boolean,
+            b'V' if allow_void => Descriptor::Void,
+            b'L' => Descriptor::Class(self.class_name()?),
+            _ => return Err(self.unexpected(start))
+        })
+    }
+
+    /// Reads the internal name of a class after its L prefix up to
+    /// and including the semicolon. Each part of the name must be a
+    /// non-empty unqualified name (JVMS 4.2.2)
+    fn class_name(&mut self) -> Result<ClassPath, DescriptorError> {
+        let start = self.position;
+        let mut segment_start = start;
+        loop {
+            match self.peek() {
+                None => return Err(DescriptorError::UnexpectedEnd),
+                Some(b';' | b'/') if self.position == segment_start => {
+                    return Err(DescriptorError::EmptyName(self.position))
                 }
+                Some(b';') => break,
+                Some(b'/') => segment_start = self.position + 1,
+                Some(b'.' | b'[') => return Err(self.unexpected(self.position)),
+                Some(_) => {}
             }
+            self.position += 1;
         }
+        let name = std::str::from_utf8(&self.value[start..self.position])
+            .map_err(|_| self.unexpected(start))?;
+        self.position += 1;
+        Ok(ClassPath::from(name))
     }
-}
\ No newline at end of file
+}
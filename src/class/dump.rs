@@ -0,0 +1,1119 @@
+//! Textual dump of a class in the same layout as `javap -v`. Unlike the
+//! Debug output of `Class` this can be diffed directly against javap
+use std::fmt::{Debug, Display, LowerExp, Write as FmtWrite};
+use std::io::Write;
+
+use crate::class::access::{AccessFlag, AccessFlags, ClassAccessFlag, FieldAccessFlag, InnerClassAccessFlag, MethodAccessFlag, ModuleAccessFlag, PackageAccessFlag, ParameterAccessFlag, RequiresAccessFlag};
+use crate::class::annotation::{Annotation, ElementValue, TargetInfo, TypeAnnotation};
+use crate::class::attribute::{Attribute, AttributeValue, CodeAttr};
+use crate::class::class::Class;
+use crate::class::constant::{Constant, ConstantPool, PoolIndex, ReferenceKind};
+use crate::class::descriptor::Descriptor;
+use crate::class::frame::{StackMapFrame, VerificationType};
+use crate::class::member::Member;
+use crate::class::module::Module;
+use crate::class::op::{Instr, mnemonic, parse_code};
+use crate::class::signature::{JavaNames, type_parameters_to_java, TypeSignature};
+use crate::error::{SignatureError, WriteError};
+
+/// Column that comments are aligned to relative to the indentation
+const COMMENT_COLUMN: usize = 40;
+
+/// The JVMS names of the flags of the set which are present along with the
+/// raw value (e.g. "(0x0021) ACC_PUBLIC, ACC_SUPER")
+fn flag_names<F: AccessFlag>(flags: AccessFlags) -> String {
+    let names: Vec<&str> = flags.flags::<F>().iter()
+        .map(|flag| flag.name())
+        .collect();
+    format!("({:#06x}) {}", flags.0, names.join(", "))
+}
+
+/// The names of the flags of a module directive each preceded by a space
+fn directive_flags<F: AccessFlag>(flags: AccessFlags) -> String {
+    flags.flags::<F>().iter()
+        .map(|flag| format!(" {}", flag.name()))
+        .collect()
+}
+
+/// Escapes a utf8 constant the same way javap does. This is also valid
+/// as the contents of a Java string literal
+pub(crate) fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '"' => out.push_str("\\\""),
+            '\'' => out.push_str("\\'"),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Quotes class and member names that aren't valid Java identifiers
+/// (e.g. "<init>" or array class names)
+fn check_name(name: &str) -> String {
+    let mut previous = '/';
+    for c in name.chars() {
+        let valid = if previous == '/' {
+            c.is_alphabetic() || c == '_' || c == '$'
+        } else {
+            c == '/' || c.is_alphanumeric() || c == '_' || c == '$'
+        };
+        if !valid {
+            let escaped = name.replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+                .replace('\t', "\\t");
+            return format!("\"{}\"", escaped);
+        }
+        previous = c;
+    }
+    if name.is_empty() {
+        return "\"\"".to_string();
+    }
+    name.to_string()
+}
+
+/// Formats a floating point value like Java's toString
+pub(crate) fn java_float<T: Display + Debug + LowerExp + Into<f64> + Copy>(value: T) -> String {
+    let wide: f64 = value.into();
+    if wide.is_nan() {
+        return "NaN".to_string();
+    }
+    if wide.is_infinite() {
+        return if wide > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    let magnitude = wide.abs();
+    if magnitude == 0.0 || (1e-3..1e7).contains(&magnitude) {
+        let out = format!("{:?}", value);
+        return if out.contains('.') { out } else { out + ".0" };
+    }
+    let out = format!("{:e}", value);
+    let (mantissa, exponent) = out.split_once('e').unwrap_or((&out, "0"));
+    if mantissa.contains('.') {
+        format!("{}E{}", mantissa, exponent)
+    } else {
+        format!("{}.0E{}", mantissa, exponent)
+    }
+}
+
+/// Describes what a type annotation targets the same way as javap
+/// (e.g. "METHOD_FORMAL_PARAMETER, param_index=0, location=[ARRAY]")
+fn type_annotation_position(annotation: &TypeAnnotation) -> String {
+    let mut out = annotation.target_type.name().to_string();
+    match &annotation.target_info {
+        TargetInfo::TypeParameter { type_parameter_index } |
+        TargetInfo::FormalParameter { formal_parameter_index: type_parameter_index } => {
+            write!(out, ", param_index={}", type_parameter_index)
+        }
+        TargetInfo::Supertype { supertype_index: index } |
+        TargetInfo::Throws { throws_type_index: index } => write!(out, ", type_index={}", index),
+        TargetInfo::TypeParameterBound { type_parameter_index, bound_index } => {
+            write!(out, ", param_index={}, bound_index={}", type_parameter_index, bound_index)
+        }
+        TargetInfo::Empty => Ok(()),
+        TargetInfo::LocalVariable(values) => {
+            let ranges: Vec<String> = values.iter()
+                .map(|value| format!("start_pc={}, length={}, index={}", value.start_pc, value.length, value.index))
+                .collect();
+            write!(out, ", {{{}}}", ranges.join("; "))
+        }
+        TargetInfo::Catch { exception_table_index } => write!(out, ", exception_index={}", exception_table_index),
+        TargetInfo::Offset { offset } => write!(out, ", offset={}", offset),
+        TargetInfo::TypeArgument { offset, type_argument_index } => {
+            write!(out, ", offset={}, type_index={}", offset, type_argument_index)
+        }
+    }.ok();
+    if !annotation.type_path.is_empty() {
+        let path: Vec<String> = annotation.type_path.iter()
+            .map(|entry| entry.to_string())
+            .collect();
+        write!(out, ", location=[{}]", path.join(", ")).ok();
+    }
+    out
+}
+
+/// Formats the locals or stack of a stack map frame like javap
+/// (e.g. locals = [ class java/lang/Object, int ])
+fn verification_types(name: &str, values: &[VerificationType]) -> String {
+    let mut out = format!("{} = [", name);
+    for (index, value) in values.iter().enumerate() {
+        out.push(' ');
+        match value {
+            VerificationType::Top => out.push_str("top"),
+            VerificationType::Integer => out.push_str("int"),
+            VerificationType::Float => out.push_str("float"),
+            VerificationType::Long => out.push_str("long"),
+            VerificationType::Double => out.push_str("double"),
+            VerificationType::Null => out.push_str("null"),
+            VerificationType::UninitializedThis => out.push_str("this"),
+            VerificationType::Object(_, class) => {
+                out.push_str("class ");
+                out.push_str(&check_name(&class.internal_path()));
+            }
+            VerificationType::Uninitialized(offset) => out.push_str(&format!("uninitialized {}", offset)),
+        }
+        out.push(if index == values.len() - 1 { ' ' } else { ',' });
+    }
+    out.push(']');
+    out
+}
+
+fn new_line(out: &mut String, indent: usize) {
+    out.push('\n');
+    out.extend(std::iter::repeat_n(' ', indent));
+}
+
+fn java_name(internal_name: &str) -> String {
+    internal_name.replace('/', ".")
+}
+
+/// The Java form of a type. Nested classes keep their $ like javap
+fn type_name(descriptor: &Descriptor) -> String {
+    match descriptor {
+        Descriptor::Class(path) => java_name(&path.internal_path()),
+        Descriptor::Array(array) => format!(
+            "{}{}",
+            type_name(&array.descriptor),
+            "[]".repeat(array.dimensions as usize)
+        ),
+        Descriptor::Unknown(value) => value.clone(),
+        descriptor => descriptor.to_java(),
+    }
+}
+
+/// The Java form of the generic type when the signature is valid
+/// otherwise the erased type of the descriptor
+fn generic_type_name(signature: Result<Option<TypeSignature>, SignatureError>, descriptor: &Descriptor) -> String {
+    match signature {
+        Ok(Some(signature)) => signature.to_java(JavaNames::Qualified),
+        _ => type_name(descriptor),
+    }
+}
+
+/// Prints classes in the same layout as `javap -v`, minus the leading
+/// file information (path, modification time and checksum) which isn't
+/// part of the class. Private members are only included when requested
+/// like `javap -p`
+#[derive(Debug, Clone, Default)]
+pub struct ClassDumper {
+    pub include_private: bool,
+}
+
+impl ClassDumper {
+    pub fn new() -> ClassDumper { ClassDumper::default() }
+
+    pub fn dump<W: Write>(&self, class: &Class, o: &mut W) -> Result<(), WriteError> {
+        o.write_all(self.dump_string(class)?.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn dump_string(&self, class: &Class) -> Result<String, WriteError> {
+        let mut dump = Dump {
+            class,
+            pool: &class.constant_pool,
+            // The indexes that were read are shown like javap does
+            this_class: class.constant_pool.class_index(class.this_class, &class.class_path).ok(),
+            out: String::new(),
+        };
+        dump.header()?;
+        dump.constant_pool();
+        dump.line(0, "{");
+        let members: Vec<(&Member, bool)> = class.fields.iter()
+            .map(|field| (field, true))
+            .chain(class.methods.iter().map(|method| (method, false)))
+            // ACC_PRIVATE is the same bit for fields and methods
+            .filter(|(member, _)| self.include_private || !member.access_flags.is_set(MethodAccessFlag::Private))
+            .collect();
+        for (index, (member, is_field)) in members.into_iter().enumerate() {
+            if index > 0 {
+                dump.line(0, "");
+            }
+            if is_field {
+                dump.field(member)?;
+            } else {
+                dump.method(member)?;
+            }
+        }
+        dump.line(0, "}");
+        dump.attributes(0, &class.attributes, None)?;
+        Ok(dump.out)
+    }
+}
+
+struct Dump<'a> {
+    class: &'a Class,
+    pool: &'a ConstantPool,
+    this_class: Option<PoolIndex>,
+    out: String,
+}
+
+impl<'a> Dump<'a> {
+    fn line(&mut self, indent: usize, text: &str) {
+        let start = self.out.len();
+        self.out.extend(std::iter::repeat_n(' ', indent));
+        self.out.push_str(text);
+        // javap never leaves trailing whitespace (e.g. after empty strings)
+        let end = self.out[start..].trim_end().len() + start;
+        self.out.truncate(end);
+        self.out.push('\n');
+    }
+
+    /// Writes a line followed by a comment aligned to the comment column
+    fn commented(&mut self, indent: usize, text: &str, comment: &str) {
+        let mut value = format!("{:indent$}{}", "", text, indent = indent);
+        let column = indent + COMMENT_COLUMN;
+        if value.len() < column {
+            value.extend(std::iter::repeat_n(' ', column - value.len()));
+        } else {
+            value.push(' ');
+        }
+        value.push_str("// ");
+        value.push_str(comment);
+        self.line(0, &value);
+    }
+
+    fn utf8(&self, index: PoolIndex) -> String {
+        match self.pool.inner.get(&index) {
+            Some(Constant::Utf8(value)) | Some(Constant::Utf8Lossy(value, _)) => value.clone(),
+            _ => "??".to_string(),
+        }
+    }
+
+    fn class_name(&self, index: PoolIndex) -> String {
+        match self.pool.inner.get(&index) {
+            Some(Constant::Class(name_index)) => self.utf8(*name_index),
+            _ => "??".to_string(),
+        }
+    }
+
+    /// The value of a constant as shown in comments
+    fn string_value(&self, index: PoolIndex) -> String {
+        let constant = match self.pool.inner.get(&index) {
+            Some(constant) => constant,
+            None => return "??".to_string(),
+        };
+        match constant {
+            Constant::Utf8(value) | Constant::Utf8Lossy(value, _) => escape(value),
+            Constant::Integer(value) => value.to_string(),
+            Constant::Float(value) => format!("{}f", java_float(*value)),
+            Constant::Long(value) => format!("{}l", value),
+            Constant::Double(value) => format!("{}d", java_float(*value)),
+            Constant::Class(name_index) => check_name(&self.utf8(*name_index)),
+            Constant::String(index) => self.string_value(*index),
+            Constant::FieldRef(value) |
+            Constant::MethodRef(value) |
+            Constant::InterfaceMethodRef(value) => format!(
+                "{}.{}",
+                self.string_value(value.class_index),
+                self.string_value(value.name_and_type_info)
+            ),
+            Constant::NameAndType(value) => format!(
+                "{}:{}",
+                check_name(&self.utf8(value.name_index)),
+                self.string_value(value.descriptor_index)
+            ),
+            Constant::MethodHandle(value) => {
+                let kind = ReferenceKind::try_from(value.reference_kind)
+                    .map(|kind| kind.name().to_string())
+                    .unwrap_or_else(|_| value.reference_kind.to_string());
+                format!("{} {}", kind, self.string_value(value.reference_index))
+            }
+            Constant::MethodType(index) => self.string_value(*index),
+            Constant::Dynamic(value) |
+            Constant::InvokeDynamic(value) => format!(
+                "#{}:{}",
+                value.bootstrap_method_attr_index,
+                self.string_value(value.name_and_type_index)
+            ),
+            Constant::Module(index) |
+            Constant::Package(index) => check_name(&self.utf8(*index)),
+        }
+    }
+
+    /// The kind and value of a constant as shown in instruction comments.
+    /// References to members of this class leave out the class name
+    fn constant_value(&self, index: PoolIndex) -> String {
+        let constant = match self.pool.inner.get(&index) {
+            Some(constant) => constant,
+            None => return "??".to_string(),
+        };
+        let kind = match constant {
+            Constant::Utf8(_) | Constant::Utf8Lossy(..) => "Utf8",
+            Constant::Integer(_) => "int",
+            Constant::Float(_) => "float",
+            Constant::Long(_) => "long",
+            Constant::Double(_) => "double",
+            Constant::Class(_) => "class",
+            Constant::String(_) => "String",
+            Constant::FieldRef(_) => "Field",
+            Constant::MethodRef(_) => "Method",
+            Constant::InterfaceMethodRef(_) => "InterfaceMethod",
+            Constant::NameAndType(_) => "NameAndType",
+            Constant::MethodHandle(_) => "MethodHandle",
+            Constant::MethodType(_) => "MethodType",
+            Constant::Dynamic(_) => "Dynamic",
+            Constant::InvokeDynamic(_) => "InvokeDynamic",
+            Constant::Module(_) => "Module",
+            Constant::Package(_) => "Package",
+        };
+        let value = match constant {
+            Constant::FieldRef(value) |
+            Constant::MethodRef(value) |
+            Constant::InterfaceMethodRef(value) if Some(value.class_index) == self.this_class =>
+                self.string_value(value.name_and_type_info),
+            _ => self.string_value(index),
+        };
+        format!("{} {}", kind, value)
+    }
+
+    fn header(&mut self) -> Result<(), WriteError> {
+        let class = self.class;
+        let declaration = match class.module() {
+            Some(module) if class.is_module() => self.module_declaration(module)?,
+            _ => self.class_declaration()?,
+        };
+        self.line(0, &declaration);
+
+        self.line(2, &format!("minor version: {}", class.version.minor()));
+        self.line(2, &format!("major version: {}", u16::from(class.version.major())));
+        self.line(2, &format!("flags: {}", flag_names::<ClassAccessFlag>(class.access_flags)));
+        let this_class = self.this_class.unwrap_or(0);
+        self.commented(2, &format!("this_class: #{}", this_class), &check_name(&self.class_name(this_class)));
+        match &class.super_class_path {
+            Some(super_class) => {
+                let index = self.pool.class_index(class.super_class, super_class).unwrap_or(0);
+                self.commented(2, &format!("super_class: #{}", index), &check_name(&self.class_name(index)));
+            }
+            None => self.line(2, "super_class: #0"),
+        }
+        self.line(2, &format!(
+            "interfaces: {}, fields: {}, methods: {}, attributes: {}",
+            class.interfaces.len(),
+            class.fields.len(),
+            class.methods.len(),
+            class.attributes.len()
+        ));
+        Ok(())
+    }
+
+    fn module_declaration(&self, module: &Module) -> Result<String, WriteError> {
+        let mut declaration = String::new();
+        if module.is_open() {
+            declaration.push_str("open ");
+        }
+        let name = module.name(self.pool).map(String::as_str).unwrap_or("??");
+        write!(declaration, "module {}", name)?;
+        if module.module_version_index != 0 {
+            write!(declaration, "@{}", self.utf8(module.module_version_index))?;
+        }
+        Ok(declaration)
+    }
+
+    fn class_declaration(&self) -> Result<String, WriteError> {
+        let class = self.class;
+        let mut flags = class.access_flags;
+        let is_interface = flags.is_set(ClassAccessFlag::Interface);
+        // Interfaces are implicitly abstract
+        if is_interface {
+            flags.unset(ClassAccessFlag::Abstract);
+        }
+        let mut declaration = flags.modifiers::<ClassAccessFlag>();
+        declaration.push_str(if is_interface { "interface " } else { "class " });
+        declaration.push_str(&java_name(&class.class_path.internal_path()));
+        // Like javap the superclass is always included when generic
+        if let Ok(Some(signature)) = class.signature() {
+            declaration.push_str(&type_parameters_to_java(&signature.type_parameters, JavaNames::Qualified));
+            let interfaces: Vec<String> = signature.interfaces.iter()
+                .map(|interface| interface.to_java(JavaNames::Qualified))
+                .collect();
+            if !is_interface {
+                write!(declaration, " extends {}", signature.superclass.to_java(JavaNames::Qualified))?;
+            }
+            if !interfaces.is_empty() {
+                let keyword = if is_interface { "extends" } else { "implements" };
+                write!(declaration, " {} {}", keyword, interfaces.join(", "))?;
+            }
+            return Ok(declaration);
+        }
+        let mut interfaces = class.interfaces.iter()
+            .map(|interface| java_name(&interface.internal_path()))
+            .collect::<Vec<String>>()
+            .join(", ");
+        if is_interface {
+            if !interfaces.is_empty() {
+                write!(declaration, " extends {}", interfaces)?;
+            }
+            interfaces.clear();
+        } else if let Some(super_class) = &class.super_class_path {
+            if !super_class.is_object() {
+                write!(declaration, " extends {}", java_name(&super_class.internal_path()))?;
+            }
+        }
+        if !interfaces.is_empty() {
+            write!(declaration, " implements {}", interfaces)?;
+        }
+        Ok(declaration)
+    }
+
+    fn constant_pool(&mut self) {
+        self.line(0, "Constant pool:");
+        let mut indexes: Vec<PoolIndex> = self.pool.inner.keys().copied().collect();
+        indexes.sort();
+        let count = indexes.last()
+            .map(|index| *index as u32 + self.pool.inner[index].width() as u32)
+            .unwrap_or(1);
+        let width = count.to_string().len() + 1;
+        for index in indexes {
+            let constant = &self.pool.inner[&index];
+            let (tag, value, comment) = match constant {
+                Constant::Utf8(value) | Constant::Utf8Lossy(value, _) => ("Utf8", escape(value), None),
+                Constant::Integer(value) => ("Integer", value.to_string(), None),
+                Constant::Float(value) => ("Float", format!("{}f", java_float(*value)), None),
+                Constant::Long(value) => ("Long", format!("{}l", value), None),
+                Constant::Double(value) => ("Double", format!("{}d", java_float(*value)), None),
+                Constant::Class(name_index) => ("Class", format!("#{}", name_index), Some(self.string_value(index))),
+                Constant::String(string_index) => ("String", format!("#{}", string_index), Some(self.string_value(index))),
+                Constant::FieldRef(value) |
+                Constant::MethodRef(value) |
+                Constant::InterfaceMethodRef(value) => (
+                    match constant {
+                        Constant::FieldRef(_) => "Fieldref",
+                        Constant::MethodRef(_) => "Methodref",
+                        _ => "InterfaceMethodref",
+                    },
+                    format!("#{}.#{}", value.class_index, value.name_and_type_info),
+                    Some(self.string_value(index)),
+                ),
+                Constant::NameAndType(value) => (
+                    "NameAndType",
+                    format!("#{}:#{}", value.name_index, value.descriptor_index),
+                    Some(self.string_value(index)),
+                ),
+                Constant::MethodHandle(value) => (
+                    "MethodHandle",
+                    format!("{}:#{}", value.reference_kind, value.reference_index),
+                    Some(self.string_value(index)),
+                ),
+                // javap leaves an extra space before method types
+                Constant::MethodType(descriptor_index) => (
+                    "MethodType",
+                    format!("#{}", descriptor_index),
+                    Some(format!(" {}", self.string_value(index))),
+                ),
+                Constant::Dynamic(value) |
+                Constant::InvokeDynamic(value) => (
+                    if let Constant::Dynamic(_) = constant { "Dynamic" } else { "InvokeDynamic" },
+                    format!("#{}:#{}", value.bootstrap_method_attr_index, value.name_and_type_index),
+                    Some(self.string_value(index)),
+                ),
+                Constant::Module(name_index) => ("Module", format!("#{}", name_index), Some(self.string_value(index))),
+                Constant::Package(name_index) => ("Package", format!("#{}", name_index), Some(self.string_value(index))),
+            };
+            let text = format!("{:>width$} = {:<18} {}", format!("#{}", index), tag, value, width = width);
+            match comment {
+                Some(comment) => self.commented(2, &text, &comment),
+                None => self.line(2, &text),
+            }
+        }
+    }
+
+    fn field(&mut self, field: &Member) -> Result<(), WriteError> {
+        let field_type = generic_type_name(field.field_signature(), &field.descriptor);
+        self.line(2, &format!("{}{} {};", field.access_flags.modifiers::<FieldAccessFlag>(), field_type, field.name));
+        self.member_info::<FieldAccessFlag>(field);
+        self.attributes(4, &field.attributes, None)
+    }
+
+    fn member_info<F: AccessFlag>(&mut self, member: &Member) {
+        self.line(4, &format!("descriptor: {}", member.descriptor.to_internal_java()));
+        self.line(4, &format!("flags: {}", flag_names::<F>(member.access_flags)));
+    }
+
+    fn method(&mut self, method: &Member) -> Result<(), WriteError> {
+        let flags = method.access_flags;
+        let is_interface = self.class.access_flags.is_set(ClassAccessFlag::Interface);
+        let mut declaration = flags.modifiers::<MethodAccessFlag>();
+        if is_interface
+            && !flags.is_set(MethodAccessFlag::Abstract)
+            && !flags.is_set(MethodAccessFlag::Static)
+            && !flags.is_set(MethodAccessFlag::Private)
+            && method.name != "<clinit>" {
+            declaration.push_str("default ");
+        }
+        let signature = method.method_signature().ok().flatten();
+        let parameters = match &method.descriptor {
+            Descriptor::Method(descriptor) => {
+                let mut parameters: Vec<String> = match &signature {
+                    Some(signature) => {
+                        declaration.push_str(&type_parameters_to_java(&signature.type_parameters, JavaNames::Qualified));
+                        if !signature.type_parameters.is_empty() {
+                            declaration.push(' ');
+                        }
+                        signature.parameters.iter()
+                            .map(|parameter| parameter.to_java(JavaNames::Qualified))
+                            .collect()
+                    }
+                    None => descriptor.parameters.iter().map(type_name).collect(),
+                };
+                if flags.is_set(MethodAccessFlag::Varargs) {
+                    if let Some(last) = parameters.last_mut() {
+                        if last.ends_with("[]") {
+                            last.truncate(last.len() - 2);
+                            last.push_str("...");
+                        }
+                    }
+                }
+                let return_type = match &signature {
+                    Some(signature) => signature.return_type.to_java(JavaNames::Qualified),
+                    None => type_name(&descriptor.return_type),
+                };
+                Some((parameters.join(", "), return_type))
+            }
+            _ => None,
+        };
+        let (parameters, return_type) = parameters.unwrap_or_default();
+        match method.name.as_str() {
+            "<init>" => write!(declaration, "{}({})", java_name(&self.class.class_path.internal_path()), parameters)?,
+            "<clinit>" => declaration.push_str("{}"),
+            name => write!(declaration, "{} {}({})", return_type, name, parameters)?,
+        }
+        for attribute in &method.attributes {
+            if let AttributeValue::Exceptions(values) = &attribute.value {
+                // The generic exceptions are used when the signature has any
+                let names: Vec<String> = match &signature {
+                    Some(signature) if !signature.throws.is_empty() => signature.throws.iter()
+                        .map(|value| value.to_java(JavaNames::Qualified))
+                        .collect(),
+                    _ => values.iter()
+                        .map(|index| java_name(&self.class_name(*index)))
+                        .collect(),
+                };
+                write!(declaration, " throws {}", names.join(", "))?;
+            }
+        }
+        declaration.push(';');
+        self.line(2, &declaration);
+        self.member_info::<MethodAccessFlag>(method);
+        self.attributes(4, &method.attributes, Some(method))?;
+        Ok(())
+    }
+
+    /// Writes attributes of the class, a field or the method the
+    /// attributes belong to (needed for the args_size of code)
+    fn attributes(&mut self, indent: usize, attributes: &[Attribute], method: Option<&Member>) -> Result<(), WriteError> {
+        for attribute in attributes {
+            self.attribute(indent, attribute, method)?;
+        }
+        Ok(())
+    }
+
+    fn attribute(&mut self, indent: usize, attribute: &Attribute, method: Option<&Member>) -> Result<(), WriteError> {
+        let name = attribute.name.as_str();
+        match &attribute.value {
+            AttributeValue::ConstantValue(index) => {
+                self.line(indent, &format!("{}: {}", name, self.constant_value(*index)))
+            }
+            AttributeValue::Code(code) => self.code(indent, code, method)?,
+            AttributeValue::Exceptions(values) => {
+                self.line(indent, &format!("{}:", name));
+                let names: Vec<String> = values.iter()
+                    .map(|index| java_name(&self.class_name(*index)))
+                    .collect();
+                self.line(indent + 2, &format!("throws {}", names.join(", ")));
+            }
+            AttributeValue::SourceFile(index) => self.line(indent, &format!("{}: \"{}\"", name, self.utf8(*index))),
+            AttributeValue::NestHost(index) => self.line(indent, &format!("{}: {}", name, self.constant_value(*index))),
+            AttributeValue::NestMembers(values) |
+            AttributeValue::PermittedSubclasses(values) => {
+                self.line(indent, &format!("{}:", name));
+                for index in values {
+                    self.line(indent + 2, &self.string_value(*index));
+                }
+            }
+            AttributeValue::Record(components) => {
+                self.line(indent, &format!("{}:", name));
+                for component in components {
+                    let component_type = generic_type_name(component.signature(), &component.descriptor);
+                    self.line(indent + 2, &format!("{} {};", component_type, component.name));
+                    self.line(indent + 4, &format!("descriptor: {}", component.descriptor.to_internal_java()));
+                    self.attributes(indent + 4, &component.attributes, None)?;
+                    self.line(0, "");
+                }
+            }
+            AttributeValue::Module(module) => {
+                self.line(indent, &format!("{}:", name));
+                self.module(indent + 2, module);
+            }
+            AttributeValue::ModulePackages(values) => {
+                self.line(indent, &format!("{}:", name));
+                for index in values {
+                    self.commented(indent + 2, &format!("#{}", index), &java_name(&self.string_value(*index)));
+                }
+            }
+            AttributeValue::ModuleMainClass(index) => {
+                self.commented(indent, &format!("{}: #{}", name, index), &java_name(&self.string_value(*index)));
+            }
+            AttributeValue::LineNumberTable(values) => {
+                self.line(indent, &format!("{}:", name));
+                for value in values {
+                    self.line(indent + 2, &format!("line {}: {}", value.line_number, value.start_pc));
+                }
+            }
+            AttributeValue::LocalVariableTable(values) => {
+                self.line(indent, &format!("{}:", name));
+                self.line(indent + 2, "Start  Length  Slot  Name   Signature");
+                for value in values {
+                    self.line(indent + 2, &format!(
+                        "{:5} {:7} {:5} {:>5}   {}",
+                        value.start_pc,
+                        value.length,
+                        value.index,
+                        self.utf8(value.name_index),
+                        self.utf8(value.descriptor_index)
+                    ));
+                }
+            }
+            AttributeValue::LocalVariableTypeTable(values) => {
+                self.line(indent, &format!("{}:", name));
+                self.line(indent + 2, "Start  Length  Slot  Name   Signature");
+                for value in values {
+                    self.line(indent + 2, &format!(
+                        "{:5} {:7} {:5} {:>5}   {}",
+                        value.start_pc,
+                        value.length,
+                        value.index,
+                        self.utf8(value.name_index),
+                        self.utf8(value.signature_index)
+                    ));
+                }
+            }
+            AttributeValue::InnerClasses(values) => {
+                self.line(indent, &format!("{}:", name));
+                for value in values {
+                    let mut flags = value.inner_class_access_flags;
+                    // Interfaces are implicitly abstract
+                    if flags.is_set(InnerClassAccessFlag::Interface) {
+                        flags.unset(InnerClassAccessFlag::Abstract);
+                    }
+                    let mut text = flags.modifiers::<InnerClassAccessFlag>();
+                    let mut comment = String::new();
+                    if value.inner_name_index != 0 {
+                        write!(text, "#{}= ", value.inner_name_index)?;
+                        write!(comment, "{}=", self.utf8(value.inner_name_index))?;
+                    }
+                    write!(text, "#{}", value.inner_class_info_index)?;
+                    comment.push_str(&self.constant_value(value.inner_class_info_index));
+                    if value.outer_class_info_index != 0 {
+                        write!(text, " of #{}", value.outer_class_info_index)?;
+                        write!(comment, " of {}", self.constant_value(value.outer_class_info_index))?;
+                    }
+                    text.push(';');
+                    self.commented(indent + 2, &text, &comment);
+                }
+            }
+            AttributeValue::Synthetic |
+            AttributeValue::Depreciated => self.line(indent, &format!("{}: true", name)),
+            AttributeValue::EnclosingMethod(value) => {
+                let mut comment = java_name(&self.class_name(value.class_index));
+                if value.method_index != 0 {
+                    if let Some(Constant::NameAndType(method)) = self.pool.inner.get(&value.method_index) {
+                        write!(comment, ".{}", self.utf8(method.name_index))?;
+                    }
+                }
+                self.commented(indent, &format!("{}: #{}.#{}", name, value.class_index, value.method_index), &comment);
+            }
+            AttributeValue::Signature(index, value) => {
+                self.commented(indent, &format!("{}: #{}", name, index), value);
+            }
+            AttributeValue::SourceDebugExtension(data) => {
+                self.line(indent, &format!("{}:", name));
+                for line in String::from_utf8_lossy(data).lines() {
+                    self.line(indent + 2, line);
+                }
+            }
+            AttributeValue::MethodParameters(values) => {
+                self.line(indent, &format!("{}:", name));
+                self.line(indent + 2, &format!("{:<30} {}", "Name", "Flags"));
+                for value in values {
+                    let name = if value.name_index == 0 { "<no name>".to_string() } else { self.utf8(value.name_index) };
+                    let flags: Vec<String> = value.access_flags.flags::<ParameterAccessFlag>().iter()
+                        .map(|flag| flag.name().trim_start_matches("ACC_").to_lowercase())
+                        .collect();
+                    self.line(indent + 2, &format!("{:<30} {}", name, flags.join(" ")));
+                }
+            }
+            AttributeValue::BootstrapMethods(values) => {
+                self.line(indent, &format!("{}:", name));
+                for (index, value) in values.iter().enumerate() {
+                    self.line(indent + 2, &format!("{}: #{} {}", index, value.method_ref, self.string_value(value.method_ref)));
+                    self.line(indent + 4, "Method arguments:");
+                    for argument in &value.arguments {
+                        self.line(indent + 6, &format!("#{} {}", argument, self.string_value(*argument)));
+                    }
+                }
+            }
+            AttributeValue::RuntimeVisibleAnnotations(values) |
+            AttributeValue::RuntimeInvisibleAnnotations(values) => {
+                self.line(indent, &format!("{}:", name));
+                for (index, annotation) in values.iter().enumerate() {
+                    self.annotation(indent + 2, index, annotation);
+                }
+            }
+            AttributeValue::RuntimeVisibleParameterAnnotations(values) |
+            AttributeValue::RuntimeInvisibleParameterAnnotations(values) => {
+                self.line(indent, &format!("{}:", name));
+                for (parameter, annotations) in values.iter().enumerate() {
+                    self.line(indent + 2, &format!("parameter {}:", parameter));
+                    for (index, annotation) in annotations.iter().enumerate() {
+                        self.annotation(indent + 4, index, annotation);
+                    }
+                }
+            }
+            AttributeValue::AnnotationDefault(value) => {
+                self.line(indent, &format!("{}:", name));
+                self.line(indent + 2, &format!("default_value: {}", self.element_value_indexes(value)));
+                let mut text = String::new();
+                self.element_value_text(&mut text, indent + 4, value);
+                self.line(indent + 4, &text);
+            }
+            AttributeValue::Unknown(data) |
+            AttributeValue::Undecoded(data) => {
+                self.line(indent, &format!("{}: length = {:#x} (unknown attribute)", name, data.len()));
+                for chunk in data.chunks(16) {
+                    let bytes: Vec<String> = chunk.iter().map(|value| format!("{:02x}", value)).collect();
+                    self.line(indent + 1, &bytes.join(" "));
+                }
+            }
+            AttributeValue::RuntimeVisibleTypeAnnotations(values) |
+            AttributeValue::RuntimeInvisibleTypeAnnotations(values) => {
+                self.line(indent, &format!("{}:", name));
+                for (index, value) in values.iter().enumerate() {
+                    self.line(indent + 2, &format!(
+                        "{}: {}: {}",
+                        index,
+                        self.annotation_indexes(&value.annotation),
+                        type_annotation_position(value)
+                    ));
+                    let mut text = String::new();
+                    self.annotation_text(&mut text, indent + 4, &value.annotation);
+                    self.line(indent + 4, &text);
+                }
+            }
+            AttributeValue::StackMapTable(frames) => {
+                self.line(indent, &format!("{}: number_of_entries = {}", name, frames.len()));
+                for frame in frames {
+                    let frame_type = frame.frame_type();
+                    let kind = match frame_type {
+                        0..=63 => "same",
+                        64..=127 => "same_locals_1_stack_item",
+                        247 => "same_locals_1_stack_item_frame_extended",
+                        248..=250 => "chop",
+                        251 => "same_frame_extended",
+                        252..=254 => "append",
+                        _ => "full_frame",
+                    };
+                    self.line(indent + 2, &format!("frame_type = {} /* {} */", frame_type, kind));
+                    if frame_type > 127 {
+                        self.line(indent + 4, &format!("offset_delta = {}", frame.offset_delta()));
+                    }
+                    match frame {
+                        StackMapFrame::SameLocals1StackItem { stack, .. } |
+                        StackMapFrame::SameLocals1StackItemExtended { stack, .. } => {
+                            self.line(indent + 4, &verification_types("stack", std::slice::from_ref(stack)))
+                        }
+                        StackMapFrame::Append { locals, .. } => self.line(indent + 4, &verification_types("locals", locals)),
+                        StackMapFrame::Full { locals, stack, .. } => {
+                            self.line(indent + 4, &verification_types("locals", locals));
+                            self.line(indent + 4, &verification_types("stack", stack));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn module(&mut self, indent: usize, module: &Module) {
+        let index = module.module_name_index;
+        let flags = module.module_flags;
+        self.commented(indent, &format!("#{},{:x}", index, flags.0), &format!("{}{}", self.string_value(index), directive_flags::<ModuleAccessFlag>(flags)));
+        self.version(indent, module.module_version_index);
+
+        self.commented(indent, &module.requires.len().to_string(), "requires");
+        for value in &module.requires {
+            let index = value.requires_index;
+            let flags = value.requires_flags;
+            self.commented(indent + 2, &format!("#{},{:x}", index, flags.0), &format!("{}{}", self.string_value(index), directive_flags::<RequiresAccessFlag>(flags)));
+            self.version(indent + 2, value.requires_version_index);
+        }
+        self.commented(indent, &module.exports.len().to_string(), "exports");
+        for value in &module.exports {
+            self.package_directive(indent + 2, value.exports_index, value.exports_flags, &value.exports_to_index);
+        }
+        self.commented(indent, &module.opens.len().to_string(), "opens");
+        for value in &module.opens {
+            self.package_directive(indent + 2, value.opens_index, value.opens_flags, &value.opens_to_index);
+        }
+        self.commented(indent, &module.uses_index.len().to_string(), "uses");
+        for index in &module.uses_index {
+            self.commented(indent + 2, &format!("#{}", index), &self.string_value(*index));
+        }
+        self.commented(indent, &module.provides.len().to_string(), "provides");
+        for value in &module.provides {
+            let index = value.provides_index;
+            let with = &value.provides_with_index;
+            self.commented(indent + 2, &format!("#{}", index), &format!("{} with ... {}", self.string_value(index), with.len()));
+            for index in with {
+                self.commented(indent + 4, &format!("#{}", index), &format!("... with {}", self.string_value(*index)));
+            }
+        }
+    }
+
+    /// Writes the version index of a module with the version as a
+    /// comment unless the version isn't present
+    fn version(&mut self, indent: usize, index: PoolIndex) {
+        if index == 0 {
+            self.line(indent, "#0");
+        } else {
+            self.commented(indent, &format!("#{}", index), &self.string_value(index));
+        }
+    }
+
+    /// Writes an exports or opens directive along with the modules the
+    /// package is exported or opened to
+    fn package_directive(&mut self, indent: usize, index: PoolIndex, flags: AccessFlags, to: &[PoolIndex]) {
+        let mut comment = format!("{}{}", self.string_value(index), directive_flags::<PackageAccessFlag>(flags));
+        if !to.is_empty() {
+            write!(comment, " to ... {}", to.len()).ok();
+        }
+        self.commented(indent, &format!("#{},{:x}", index, flags.0), &comment);
+        for index in to {
+            self.commented(indent + 2, &format!("#{}", index), &format!("... to {}", self.string_value(*index)));
+        }
+    }
+
+    /// Writes an annotation in the raw index form followed by the
+    /// resolved form on the lines below it
+    fn annotation(&mut self, indent: usize, index: usize, annotation: &Annotation) {
+        self.line(indent, &format!("{}: {}", index, self.annotation_indexes(annotation)));
+        let mut text = String::new();
+        self.annotation_text(&mut text, indent + 2, annotation);
+        self.line(indent + 2, &text);
+    }
+
+    fn annotation_indexes(&self, annotation: &Annotation) -> String {
+        let elements: Vec<String> = annotation.elements.iter()
+            .map(|(name_index, value)| format!("#{}={}", name_index, self.element_value_indexes(value)))
+            .collect();
+        format!("#{}({})", annotation.type_index, elements.join(","))
+    }
+
+    fn element_value_indexes(&self, value: &ElementValue) -> String {
+        match value {
+            ElementValue::Const { tag, const_value_index } => format!("{}#{}", *tag as char, const_value_index),
+            ElementValue::Enum { type_name_index, const_name_index } => format!("e#{}.#{}", type_name_index, const_name_index),
+            ElementValue::Class(index) => format!("c#{}", index),
+            ElementValue::Annotation(annotation) => format!("@{}", self.annotation_indexes(annotation)),
+            ElementValue::Array(values) => {
+                let values: Vec<String> = values.iter()
+                    .map(|value| self.element_value_indexes(value))
+                    .collect();
+                format!("[{}]", values.join(","))
+            }
+        }
+    }
+
+    /// Appends the resolved form of an annotation. Each element goes on
+    /// its own line indented past the `indent` of the annotation
+    fn annotation_text(&self, out: &mut String, indent: usize, annotation: &Annotation) {
+        out.push_str(&type_name(&Descriptor::parse(&self.utf8(annotation.type_index))));
+        if annotation.elements.is_empty() {
+            return;
+        }
+        out.push('(');
+        for (name_index, value) in &annotation.elements {
+            new_line(out, indent + 2);
+            out.push_str(&self.utf8(*name_index));
+            out.push('=');
+            self.element_value_text(out, indent + 2, value);
+        }
+        new_line(out, indent);
+        out.push(')');
+    }
+
+    fn element_value_text(&self, out: &mut String, indent: usize, value: &ElementValue) {
+        match value {
+            ElementValue::Const { tag, const_value_index } => {
+                let value = self.string_value(*const_value_index);
+                let integer = match self.pool.inner.get(const_value_index) {
+                    Some(Constant::Integer(value)) => Some(*value),
+                    _ => None
+                };
+                match (tag, integer) {
+                    (b'B', _) => write!(out, "(byte) {}", value),
+                    (b'S', _) => write!(out, "(short) {}", value),
+                    (b'C', Some(integer)) => write!(
+                        out,
+                        "'{}'",
+                        char::from_u32(integer as u32).unwrap_or(char::REPLACEMENT_CHARACTER)
+                    ),
+                    (b'Z', Some(integer)) => write!(out, "{}", integer != 0),
+                    (b's', _) => write!(out, "\"{}\"", value),
+                    _ => write!(out, "{}", value),
+                }.ok();
+            }
+            ElementValue::Enum { type_name_index, const_name_index } => {
+                write!(out, "{}.{}", self.utf8(*type_name_index), self.utf8(*const_name_index)).ok();
+            }
+            ElementValue::Class(index) => {
+                write!(out, "class {}", self.utf8(*index)).ok();
+            }
+            ElementValue::Annotation(annotation) => {
+                out.push('@');
+                self.annotation_text(out, indent, annotation);
+            }
+            ElementValue::Array(values) => {
+                out.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    self.element_value_text(out, indent, value);
+                }
+                out.push(']');
+            }
+        }
+    }
+
+    fn code(&mut self, indent: usize, code: &CodeAttr, method: Option<&Member>) -> Result<(), WriteError> {
+        self.line(indent, "Code:");
+        let indent = indent + 2;
+        // javap counts the parameters rather than the slots they take
+        let args_size = method.map(|method| {
+            let parameters = match &method.descriptor {
+                Descriptor::Method(descriptor) => descriptor.parameters.len(),
+                _ => 0,
+            };
+            if !method.access_flags.is_set(MethodAccessFlag::Static) { parameters + 1 } else { parameters }
+        }).unwrap_or(0);
+        self.line(indent, &format!("stack={}, locals={}, args_size={}", code.max_stack, code.max_locals, args_size));
+        if !code.code.is_empty() {
+            let instructions = parse_code(&code.code).map_err(|_| WriteError::BadCodeAttribute)?;
+            for (offset, instr) in instructions {
+                self.instruction(indent, &code.code, offset, &instr)?;
+            }
+        }
+        if !code.exception_table.is_empty() {
+            self.line(indent, "Exception table:");
+            self.line(indent + 2, " from    to  target type");
+            for entry in &code.exception_table {
+                let catch_type = if entry.catch_type == 0 {
+                    "any".to_string()
+                } else {
+                    format!("Class {}", self.string_value(entry.catch_type))
+                };
+                self.line(indent + 2, &format!(
+                    " {:5} {:5} {:5}   {}",
+                    entry.start_pc, entry.end_pc, entry.handler_pc, catch_type
+                ));
+            }
+        }
+        self.attributes(indent, &code.attributes, method)
+    }
+
+    fn instruction(&mut self, indent: usize, code: &[u8], offset: u64, instr: &Instr) -> Result<(), WriteError> {
+        let position = offset as usize;
+        let mut opcode = code[position];
+        let wide = opcode == 0xc4;
+        if wide {
+            opcode = code.get(position + 1).copied().unwrap_or(0);
+        }
+        let mut name = mnemonic(opcode).unwrap_or("???").to_string();
+        if wide {
+            name.push_str("_w");
+        }
+        let prefix = format!("{:4}: {:<13} ", offset, name);
+        // Instructions whose local variable index is an operand rather than
+        // part of the opcode (e.g. iload rather than iload_0)
+        let explicit_local = matches!(opcode, 0x15..=0x19 | 0x36..=0x3a | 0xa9);
+        let operands = match instr {
+            Instr::BIPush(value) => value.to_string(),
+            Instr::SIPush(value) => value.to_string(),
+            Instr::ILoad(index) | Instr::LLoad(index) |
+            Instr::FLoad(index) | Instr::DLoad(index) |
+            Instr::ALoad(index) | Instr::IStore(index) |
+            Instr::LStore(index) | Instr::FStore(index) |
+            Instr::DStore(index) | Instr::AStore(index) |
+            Instr::Ret(index) if explicit_local => index.to_string(),
+            Instr::IInc { index, value } => format!("{}, {}", index, value),
+            Instr::NewArray(kind) => format!(" {}", format!("{:?}", kind).to_lowercase()),
+            Instr::InvokeInterface(index) => {
+                let count = code.get(position + 3).copied().unwrap_or(0);
+                let text = format!("{}#{},  {}", prefix, index, count);
+                self.commented(indent, &text, &self.constant_value(*index));
+                return Ok(());
+            }
+            Instr::InvokeDynamic(index) => {
+                let text = format!("{}#{},  0", prefix, index);
+                self.commented(indent, &text, &self.constant_value(*index));
+                return Ok(());
+            }
+            Instr::MultiANewArray { index, dimensions } => {
+                let text = format!("{}#{},  {}", prefix, index, dimensions);
+                self.commented(indent, &text, &self.constant_value(*index));
+                return Ok(());
+            }
+            Instr::TableSwitch { default, low, offsets, .. } => {
+                let low = *low as i32;
+                let high = low.wrapping_add(offsets.len() as i32).wrapping_sub(1);
+                self.line(indent, &format!("{}{{ // {} to {}", prefix, low, high));
+                for (index, target) in offsets.iter().enumerate() {
+                    self.line(indent, &format!("{:>18}: {}", low.wrapping_add(index as i32), target));
+                }
+                self.switch_end(indent, *default);
+                return Ok(());
+            }
+            Instr::LookupSwitch { default, pairs } => {
+                self.line(indent, &format!("{}{{ // {}", prefix, pairs.len()));
+                for (key, target) in pairs {
+                    self.line(indent, &format!("{:>18}: {}", key, target));
+                }
+                self.switch_end(indent, *default);
+                return Ok(());
+            }
+            Instr::IfACmpEq(target) | Instr::IfACmpNe(target) |
+            Instr::IfICmpEq(target) | Instr::IfICmpNe(target) |
+            Instr::IfICmpLt(target) | Instr::IfICmpGe(target) |
+            Instr::IfICmpGt(target) | Instr::IfICmpLe(target) |
+            Instr::IfNull(target) | Instr::IfNonNull(target) |
+            Instr::IfEq(target) | Instr::IfNe(target) |
+            Instr::IfLt(target) | Instr::IfGe(target) |
+            Instr::IfGt(target) | Instr::IfLe(target) |
+            Instr::Goto(target) | Instr::JSr(target) => target.to_string(),
+            instr => match instr.pool_index() {
+                Some(index) => {
+                    let text = format!("{}#{}", prefix, index);
+                    self.commented(indent, &text, &self.constant_value(index));
+                    return Ok(());
+                }
+                None => String::new()
+            }
+        };
+        self.line(indent, &format!("{}{}", prefix, operands));
+        Ok(())
+    }
+
+    fn switch_end(&mut self, indent: usize, default: u32) {
+        self.line(indent, &format!("{:>18}: {}", "default", default));
+        self.line(indent + 6, "}");
+    }
+}
@@ -0,0 +1,348 @@
+//! Stack map frames stored in the StackMapTable attribute of a method's
+//! code. Each frame is stored relative to the one before it so the frames
+//! are expanded into the complete locals and operand stack at each offset
+//! using `expand_frames`
+use std::io::{Read, Write};
+
+use crate::class::access::MethodAccessFlag;
+use crate::class::class::ClassPath;
+use crate::class::constant::{ConstantPool, PoolIndex};
+use crate::class::descriptor::Descriptor;
+use crate::class::member::Member;
+use crate::error::{ReadError, WriteError};
+use crate::io::{Readable, ReadResult, VecReadableFn, VecWritableFn, Writable, WriteResult};
+
+/// The type of a single local variable or operand stack entry
+#[derive(Debug, Clone, Eq)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    // The this reference of a constructor before the super constructor is called
+    UninitializedThis,
+    // An initialized instance of the class (or array class) along with the
+    // index of the class constant it was read from (0 for types built in code)
+    Object(PoolIndex, ClassPath),
+    // An object created by the new instruction at the offset that isn't initialized yet
+    Uninitialized(u16),
+}
+
+impl VerificationType {
+    /// An initialized instance of the class which wasn't read from a class
+    pub fn object(class: ClassPath) -> VerificationType {
+        VerificationType::Object(0, class)
+    }
+
+    pub fn read<R: Read>(i: &mut R, constant_pool: &ConstantPool) -> ReadResult<VerificationType> {
+        let tag = u8::read(i)?;
+        Ok(match tag {
+            0 => VerificationType::Top,
+            1 => VerificationType::Integer,
+            2 => VerificationType::Float,
+            3 => VerificationType::Double,
+            4 => VerificationType::Long,
+            5 => VerificationType::Null,
+            6 => VerificationType::UninitializedThis,
+            7 => {
+                let index = PoolIndex::read(i)?;
+                VerificationType::Object(index, constant_pool.get_class_path_required(&index)?)
+            }
+            8 => VerificationType::Uninitialized(u16::read(i)?),
+            tag => Err(ReadError::UnknownVerificationType(tag))?
+        })
+    }
+
+    pub fn write<W: Write>(&self, o: &mut W, constant_pool: &ConstantPool) -> WriteResult {
+        self.tag().write(o)?;
+        match self {
+            VerificationType::Object(index, class) => constant_pool.class_index(*index, class)?.write(o),
+            VerificationType::Uninitialized(offset) => offset.write(o),
+            _ => Ok(())
+        }
+    }
+
+    pub fn tag(&self) -> u8 {
+        match self {
+            VerificationType::Top => 0,
+            VerificationType::Integer => 1,
+            VerificationType::Float => 2,
+            VerificationType::Double => 3,
+            VerificationType::Long => 4,
+            VerificationType::Null => 5,
+            VerificationType::UninitializedThis => 6,
+            VerificationType::Object(..) => 7,
+            VerificationType::Uninitialized(_) => 8,
+        }
+    }
+
+    /// Whether values of this type take up two local variable slots
+    pub fn is_wide(&self) -> bool {
+        matches!(self, VerificationType::Long | VerificationType::Double)
+    }
+
+    /// The type values of the field descriptor have once loaded. Values
+    /// smaller than an int are widened to an int. None for void
+    pub fn from_descriptor(descriptor: &Descriptor) -> Option<VerificationType> {
+        Some(match descriptor {
+            Descriptor::Byte |
+            Descriptor::Char |
+            Descriptor::Short |
+            Descriptor::Boolean |
+            Descriptor::Int => VerificationType::Integer,
+            Descriptor::Float => VerificationType::Float,
+            Descriptor::Long => VerificationType::Long,
+            Descriptor::Double => VerificationType::Double,
+            Descriptor::Class(class) => VerificationType::object(class.clone()),
+            // Array classes are named using their descriptor
+            Descriptor::Array(_) => VerificationType::object(ClassPath::from(&descriptor.to_internal_java())),
+            Descriptor::Method(_) |
+            Descriptor::Void |
+            Descriptor::Unknown(_) => return None,
+        })
+    }
+}
+
+// Types are the same when they name the same class regardless of which
+// of the (possibly duplicate) class constants they were read from
+impl PartialEq for VerificationType {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (VerificationType::Object(_, class), VerificationType::Object(_, other)) => class == other,
+            (VerificationType::Uninitialized(offset), VerificationType::Uninitialized(other)) => offset == other,
+            _ => self.tag() == other.tag(),
+        }
+    }
+}
+
+/// A frame as it is stored in the StackMapTable. The offset of each frame
+/// is the offset_delta + 1 past the previous frame (or the offset_delta for
+/// the first frame)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StackMapFrame {
+    // Same locals as the previous frame and an empty stack (frame types 0-63)
+    Same { offset_delta: u16 },
+    // Same locals as the previous frame and a single stack item (frame types 64-127)
+    SameLocals1StackItem { offset_delta: u16, stack: VerificationType },
+    // Frame type 247
+    SameLocals1StackItemExtended { offset_delta: u16, stack: VerificationType },
+    // The previous locals without the last 1-3 locals and an empty stack (frame types 248-250)
+    Chop { offset_delta: u16, count: u8 },
+    // Frame type 251
+    SameExtended { offset_delta: u16 },
+    // The previous locals with 1-3 extra locals and an empty stack (frame types 252-254)
+    Append { offset_delta: u16, locals: Vec<VerificationType> },
+    // Frame type 255
+    Full { offset_delta: u16, locals: Vec<VerificationType>, stack: Vec<VerificationType> },
+}
+
+impl StackMapFrame {
+    pub fn read<R: Read>(i: &mut R, constant_pool: &ConstantPool) -> ReadResult<StackMapFrame> {
+        let frame_type = u8::read(i)?;
+        let read_type = |r: &mut R| VerificationType::read(r, constant_pool);
+        Ok(match frame_type {
+            0..=63 => StackMapFrame::Same { offset_delta: frame_type as u16 },
+            64..=127 => StackMapFrame::SameLocals1StackItem {
+                offset_delta: (frame_type - 64) as u16,
+                stack: read_type(i)?,
+            },
+            247 => StackMapFrame::SameLocals1StackItemExtended {
+                offset_delta: u16::read(i)?,
+                stack: read_type(i)?,
+            },
+            248..=250 => StackMapFrame::Chop {
+                offset_delta: u16::read(i)?,
+                count: 251 - frame_type,
+            },
+            251 => StackMapFrame::SameExtended { offset_delta: u16::read(i)? },
+            252..=254 => {
+                let offset_delta = u16::read(i)?;
+                let locals = (0..frame_type - 251)
+                    .map(|_| read_type(i))
+                    .collect::<ReadResult<Vec<VerificationType>>>()?;
+                StackMapFrame::Append { offset_delta, locals }
+            }
+            255 => StackMapFrame::Full {
+                offset_delta: u16::read(i)?,
+                locals: u16::read_vec_closure(i, read_type)
+                    .map_err(|err| err.in_field("locals"))?,
+                stack: u16::read_vec_closure(i, read_type)
+                    .map_err(|err| err.in_field("stack"))?,
+            },
+            // 128-246 are reserved
+            frame_type => Err(ReadError::UnknownFrameType(frame_type))?
+        })
+    }
+
+    /// The frame type written for this frame. Same frames with an offset
+    /// delta too large for the short form use the extended form instead
+    pub fn frame_type(&self) -> u8 {
+        match self {
+            StackMapFrame::Same { offset_delta } if *offset_delta < 64 => *offset_delta as u8,
+            StackMapFrame::SameLocals1StackItem { offset_delta, .. } if *offset_delta < 64 => 64 + *offset_delta as u8,
+            StackMapFrame::Same { .. } |
+            StackMapFrame::SameExtended { .. } => 251,
+            StackMapFrame::SameLocals1StackItem { .. } |
+            StackMapFrame::SameLocals1StackItemExtended { .. } => 247,
+            StackMapFrame::Chop { count, .. } => 251u8.saturating_sub(*count),
+            StackMapFrame::Append { locals, .. } => 251u8.saturating_add(locals.len() as u8),
+            StackMapFrame::Full { .. } => 255,
+        }
+    }
+
+    pub fn offset_delta(&self) -> u16 {
+        match self {
+            StackMapFrame::Same { offset_delta } |
+            StackMapFrame::SameLocals1StackItem { offset_delta, .. } |
+            StackMapFrame::SameLocals1StackItemExtended { offset_delta, .. } |
+            StackMapFrame::Chop { offset_delta, .. } |
+            StackMapFrame::SameExtended { offset_delta } |
+            StackMapFrame::Append { offset_delta, .. } |
+            StackMapFrame::Full { offset_delta, .. } => *offset_delta,
+        }
+    }
+
+    /// The verification types of the locals followed by the stack that
+    /// are stored in this frame
+    pub fn verification_types(&self) -> Vec<&VerificationType> {
+        match self {
+            StackMapFrame::SameLocals1StackItem { stack, .. } |
+            StackMapFrame::SameLocals1StackItemExtended { stack, .. } => vec![stack],
+            StackMapFrame::Append { locals, .. } => locals.iter().collect(),
+            StackMapFrame::Full { locals, stack, .. } => locals.iter().chain(stack).collect(),
+            _ => Vec::new()
+        }
+    }
+
+    pub fn write<W: Write>(&self, o: &mut W, constant_pool: &ConstantPool) -> WriteResult {
+        match self {
+            StackMapFrame::Chop { count, .. } if !(1..=3).contains(count) => {
+                Err(WriteError::LengthOverflow(*count as usize))?
+            }
+            StackMapFrame::Append { locals, .. } if !(1..=3).contains(&locals.len()) => {
+                Err(WriteError::LengthOverflow(locals.len()))?
+            }
+            _ => {}
+        }
+        let frame_type = self.frame_type();
+        frame_type.write(o)?;
+        if frame_type > 127 {
+            self.offset_delta().write(o)?;
+        }
+        let write_type = |o: &mut W, value: &VerificationType| value.write(o, constant_pool);
+        match self {
+            StackMapFrame::SameLocals1StackItem { stack, .. } |
+            StackMapFrame::SameLocals1StackItemExtended { stack, .. } => write_type(o, stack)?,
+            StackMapFrame::Append { locals, .. } => {
+                for local in locals {
+                    write_type(o, local)?;
+                }
+            }
+            StackMapFrame::Full { locals, stack, .. } => {
+                u16::write_vec_closure(o, locals, write_type)?;
+                u16::write_vec_closure(o, stack, write_type)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// The complete state of the locals and operand stack at an offset in
+/// the code. Long and double locals are stored as a single entry like
+/// they are in the StackMapTable
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Frame {
+    pub offset: u16,
+    pub locals: Vec<VerificationType>,
+    pub stack: Vec<VerificationType>,
+}
+
+impl Frame {
+    /// The implicit frame at the start of a method which holds this
+    /// (unless the method is static) followed by the method parameters
+    pub fn initial(class_path: &ClassPath, method: &Member) -> Frame {
+        let mut locals = Vec::new();
+        if !method.access_flags.is_set(MethodAccessFlag::Static) {
+            // The super constructor hasn't been called yet at the start of a
+            // constructor. Object is the exception having no super class
+            if method.is_init() && !class_path.is_object() {
+                locals.push(VerificationType::UninitializedThis);
+            } else {
+                locals.push(VerificationType::object(class_path.clone()));
+            }
+        }
+        if let Descriptor::Method(descriptor) = &method.descriptor {
+            locals.extend(descriptor.parameters.iter().filter_map(VerificationType::from_descriptor));
+        }
+        Frame { offset: 0, locals, stack: Vec::new() }
+    }
+
+    /// The type of the local variable in the slot. The second slot of a
+    /// long or double is Top
+    pub fn local(&self, slot: usize) -> Option<&VerificationType> {
+        let mut current = 0;
+        for local in &self.locals {
+            if current == slot {
+                return Some(local);
+            }
+            if local.is_wide() {
+                current += 1;
+                if current == slot {
+                    return Some(&VerificationType::Top);
+                }
+            }
+            current += 1;
+        }
+        None
+    }
+
+    /// The number of local variable slots the locals take up
+    pub fn locals_size(&self) -> usize {
+        self.locals.iter()
+            .map(|local| if local.is_wide() { 2 } else { 1 })
+            .sum()
+    }
+}
+
+/// Expands the frames of a StackMapTable into the complete frame at the
+/// offset of each one. The initial frame is the frame of the method before
+/// any code has run (see `Frame::initial`) and isn't included in the output
+pub fn expand_frames(initial: &Frame, frames: &[StackMapFrame]) -> ReadResult<Vec<Frame>> {
+    let mut out: Vec<Frame> = Vec::with_capacity(frames.len());
+    let mut locals = initial.locals.clone();
+    for (index, frame) in frames.iter().enumerate() {
+        let offset = match out.last() {
+            Some(previous) => previous.offset as u32 + frame.offset_delta() as u32 + 1,
+            None => frame.offset_delta() as u32,
+        };
+        let offset = u16::try_from(offset)
+            .map_err(|_| ReadError::InvalidStackMapFrame("offset is past the end of the code").in_element(index))?;
+        let stack = match frame {
+            StackMapFrame::Same { .. } |
+            StackMapFrame::SameExtended { .. } => Vec::new(),
+            StackMapFrame::SameLocals1StackItem { stack, .. } |
+            StackMapFrame::SameLocals1StackItemExtended { stack, .. } => vec![stack.clone()],
+            StackMapFrame::Chop { count, .. } => {
+                let count = *count as usize;
+                if count > locals.len() {
+                    Err(ReadError::InvalidStackMapFrame("chops more locals than are present").in_element(index))?;
+                }
+                locals.truncate(locals.len() - count);
+                Vec::new()
+            }
+            StackMapFrame::Append { locals: values, .. } => {
+                locals.extend(values.iter().cloned());
+                Vec::new()
+            }
+            StackMapFrame::Full { locals: values, stack, .. } => {
+                locals = values.clone();
+                stack.clone()
+            }
+        };
+        out.push(Frame { offset, locals: locals.clone(), stack });
+    }
+    Ok(out)
+}
@@ -0,0 +1,103 @@
+//! A merged view of the LocalVariableTable and LocalVariableTypeTable
+//! attributes of a method's code. The type table only holds the variables
+//! which have a generic type so each of its entries is matched to the entry
+//! of the variable table with the same slot and pc range
+use crate::class::attribute::{AttributeValue, CodeAttr};
+use crate::class::constant::ConstantPool;
+use crate::class::descriptor::Descriptor;
+use crate::class::signature::TypeSignature;
+use crate::error::ReadError;
+use crate::io::ReadResult;
+
+/// A named local variable and the range of code it is in scope for
+#[derive(Debug, Clone)]
+pub struct Local {
+    pub start_pc: u16,
+    pub length: u16,
+    // The slot of the variable in the locals of the frame
+    pub index: u16,
+    pub name: String,
+    pub descriptor: Descriptor,
+    // The generic type when the variable has one (e.g. List<String>)
+    pub signature: Option<TypeSignature>,
+}
+
+impl Local {
+    /// Whether the variable is in scope at the offset in the code
+    pub fn contains(&self, pc: u16) -> bool {
+        pc >= self.start_pc && (pc as u32) < self.start_pc as u32 + self.length as u32
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LocalVariables {
+    // Sorted by slot then by start_pc
+    pub variables: Vec<Local>,
+}
+
+impl LocalVariables {
+    /// Merges the tables of the code attribute. Empty when the class was
+    /// compiled without debug information (javac -g:vars)
+    pub fn from_code(code: &CodeAttr, constant_pool: &ConstantPool) -> ReadResult<LocalVariables> {
+        let mut variables = Vec::new();
+        for attribute in &code.attributes {
+            if let AttributeValue::LocalVariableTable(values) = &attribute.value {
+                for (element, value) in values.iter().enumerate() {
+                    let local = value.name(constant_pool)
+                        .and_then(|name| Ok(Local {
+                            start_pc: value.start_pc,
+                            length: value.length,
+                            index: value.index,
+                            name: name.clone(),
+                            descriptor: value.descriptor(constant_pool)?,
+                            signature: None,
+                        }))
+                        .map_err(|err| ReadError::from(err).in_element(element).in_key(attribute.name.as_str()))?;
+                    variables.push(local);
+                }
+            }
+        }
+        for attribute in &code.attributes {
+            if let AttributeValue::LocalVariableTypeTable(values) = &attribute.value {
+                for (element, value) in values.iter().enumerate() {
+                    let signature = value.signature(constant_pool)
+                        .map_err(|err| err.in_element(element).in_key(attribute.name.as_str()))?;
+                    let existing = variables.iter_mut().find(|local| local.index == value.index
+                        && local.start_pc == value.start_pc
+                        && local.length == value.length);
+                    match existing {
+                        Some(local) => local.signature = Some(signature),
+                        // Only valid alongside a variable table entry but the
+                        // descriptor can still be recovered from the signature
+                        None => variables.push(Local {
+                            start_pc: value.start_pc,
+                            length: value.length,
+                            index: value.index,
+                            name: value.name(constant_pool)
+                                .map_err(|err| ReadError::from(err).in_element(element).in_key(attribute.name.as_str()))?
+                                .clone(),
+                            descriptor: signature.erasure(),
+                            signature: Some(signature),
+                        }),
+                    }
+                }
+            }
+        }
+        variables.sort_by_key(|local| (local.index, local.start_pc));
+        Ok(LocalVariables { variables })
+    }
+
+    pub fn is_empty(&self) -> bool { self.variables.is_empty() }
+
+    /// The variable stored in the slot at the offset in the code
+    pub fn get(&self, index: u16, pc: u16) -> Option<&Local> {
+        self.variables.iter()
+            .find(|local| local.index == index && local.contains(pc))
+    }
+
+    /// Every variable that is stored in the slot at some point in the code
+    pub fn in_slot(&self, index: u16) -> impl Iterator<Item=&Local> {
+        self.variables.iter()
+            .filter(move |local| local.index == index)
+    }
+}
@@ -1,16 +1,24 @@
 use std::fmt::Debug;
-use std::io::Read;
+use std::io::{Read, Write};
 
 use crate::class::access::AccessFlags;
-use crate::class::attribute::Attribute;
-use crate::class::constant::ConstantPool;
+use crate::class::annotation::{self, Annotation};
+use crate::class::attribute::{Attribute, AttributeValue, CodeAttr};
+use crate::class::class::ClassPath;
+use crate::class::constant::{ConstantPool, PoolIndex};
 use crate::class::descriptor::Descriptor;
-use crate::io::{Readable, ReadResult, VecReadableFn};
+use crate::class::frame::{expand_frames, Frame};
+use crate::class::local::LocalVariables;
+use crate::class::signature::{self, MethodSignature, TypeSignature};
+use crate::error::SignatureError;
+use crate::io::{ParseLimits, Readable, ReadResult, VecReadableFn, VecWritableFn, Writable, WriteResult};
 
 #[derive(Debug,Clone)]
 pub struct Member {
     pub access_flags: AccessFlags,
+    pub name_index: PoolIndex,
     pub name: String,
+    pub descriptor_index: PoolIndex,
     pub descriptor: Descriptor,
     pub attributes: Vec<Attribute>,
 }
@@ -19,21 +27,122 @@ pub struct Member {
 impl Member {
     pub fn is_init(&self) -> bool { self.name == "<init>" }
 
+    /// The visible and invisible annotations of this member
+    pub fn annotations(&self) -> impl Iterator<Item=&Annotation> {
+        annotation::annotations(&self.attributes)
+    }
+
+    /// The annotations of the parameter at the provided index of the
+    /// method descriptor. Always empty for fields
+    pub fn parameter_annotations(&self, parameter: usize) -> impl Iterator<Item=&Annotation> {
+        let parameter_count = match &self.descriptor {
+            Descriptor::Method(descriptor) => descriptor.parameters.len(),
+            _ => 0
+        };
+        annotation::parameter_annotations(&self.attributes, parameter, parameter_count)
+    }
+
+    /// The generic type of this field. None when the type isn't generic
+    pub fn field_signature(&self) -> Result<Option<TypeSignature>, SignatureError> {
+        signature::signature(&self.attributes)
+            .map(|value| TypeSignature::parse(value))
+            .transpose()
+    }
+
+    /// The generic signature of this method. None when the method doesn't
+    /// declare type parameters or use any generic types
+    pub fn method_signature(&self) -> Result<Option<MethodSignature>, SignatureError> {
+        signature::signature(&self.attributes)
+            .map(|value| MethodSignature::parse(value))
+            .transpose()
+    }
+
+    /// The code of this method. None for fields and for abstract and native methods
+    pub fn code(&self) -> Option<&CodeAttr> {
+        self.attributes.iter()
+            .find_map(|attribute| match &attribute.value {
+                AttributeValue::Code(code) => Some(code),
+                _ => None
+            })
+    }
+
+    /// The named local variables of this method with their generic
+    /// types. Empty when the method has no code or no debug information
+    pub fn local_variables(&self, constant_pool: &ConstantPool) -> ReadResult<LocalVariables> {
+        match self.code() {
+            Some(code) => LocalVariables::from_code(code, constant_pool)
+                .map_err(|err| err.in_key("Code")),
+            None => Ok(LocalVariables::default())
+        }
+    }
+
+    /// The complete frames at each offset of the StackMapTable of this
+    /// method expanded from its initial frame. Empty when the method has
+    /// no code or no branches
+    pub fn stack_map_frames(&self, class_path: &ClassPath) -> ReadResult<Vec<Frame>> {
+        let frames = self.code().and_then(|code| code.attributes.iter()
+            .find_map(|attribute| match &attribute.value {
+                AttributeValue::StackMapTable(frames) => Some(frames),
+                _ => None
+            }));
+        match frames {
+            Some(frames) => expand_frames(&Frame::initial(class_path, self), frames),
+            None => Ok(Vec::new())
+        }
+    }
+
     pub fn read<R: Read>(
         i: &mut R,
         constant_pool: &ConstantPool,
+        limits: &ParseLimits,
     ) -> ReadResult<Member> {
-        let access_flags = AccessFlags::read(i)?;
-        let name = constant_pool.read_utf8(i)?.clone();
-        let raw_descriptor = constant_pool.read_utf8(i)?;
+        Member::read_with(i, constant_pool, limits, Attribute::read)
+    }
+
+    /// Reads a member leaving all of its attributes undecoded
+    pub fn read_lazy<R: Read>(
+        i: &mut R,
+        constant_pool: &ConstantPool,
+        limits: &ParseLimits,
+    ) -> ReadResult<Member> {
+        Member::read_with(i, constant_pool, limits, Attribute::read_lazy)
+    }
+
+    fn read_with<R: Read, F: Fn(&mut R, &ConstantPool, &ParseLimits) -> ReadResult<Attribute>>(
+        i: &mut R,
+        constant_pool: &ConstantPool,
+        limits: &ParseLimits,
+        read_attribute: F,
+    ) -> ReadResult<Member> {
+        let access_flags = AccessFlags::read(i)
+            .map_err(|err| err.in_field("access_flags"))?;
+        let (name_index, name) = constant_pool.read_utf8_indexed(i)
+            .map_err(|err| err.in_field("name_index"))?;
+        let name = name.clone();
+        let (descriptor_index, raw_descriptor) = constant_pool.read_utf8_indexed(i)
+            .map_err(|err| err.in_field("descriptor_index"))?;
         let descriptor = Descriptor::parse(raw_descriptor);
-        let attributes = u16::read_vec_closure(i, |r| Attribute::read(r, constant_pool))?;
+        let attributes = u16::read_vec_closure(i, |r| read_attribute(r, constant_pool, limits))
+            .map_err(|err| err.in_field("attributes"))?;
         Ok(Member {
             access_flags,
+            name_index,
             name,
+            descriptor_index,
             descriptor,
             attributes,
         })
     }
+
+    pub fn write<W: Write>(
+        &self,
+        o: &mut W,
+        constant_pool: &ConstantPool,
+    ) -> WriteResult {
+        self.access_flags.write(o)?;
+        constant_pool.utf8_index(self.name_index, &self.name)?.write(o)?;
+        constant_pool.utf8_index(self.descriptor_index, &self.descriptor.to_internal_java())?.write(o)?;
+        u16::write_vec_closure(o, &self.attributes, |o, attribute| attribute.write(o, constant_pool))
+    }
 }
 
@@ -1,7 +1,19 @@
 pub mod constant;
+#[allow(clippy::module_inception)]
 pub mod class;
 pub mod descriptor;
+pub mod signature;
 pub mod access;
 pub mod member;
 pub mod attribute;
-pub mod op;
\ No newline at end of file
+pub mod annotation;
+pub mod frame;
+pub mod local;
+pub mod module;
+pub mod nesting;
+pub mod op;
+pub mod view;
+pub mod validate;
+pub mod builder;
+pub mod xref;
+pub mod dump;
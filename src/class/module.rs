@@ -0,0 +1,222 @@
+//! The Module, ModulePackages and ModuleMainClass attributes of a
+//! module-info class. Modules, packages and classes are referenced using
+//! their pool index and resolved using the pool of the class
+use std::io::{Read, Write};
+
+use crate::class::access::{AccessFlags, ModuleAccessFlag, RequiresAccessFlag};
+use crate::class::class::ClassPath;
+use crate::class::constant::{ConstantPool, PoolIndex};
+use crate::error::ConstantError;
+use crate::io::{Readable, ReadResult, VecReadableSize, VecWritableSize, Writable, WriteResult};
+use crate::readable_struct;
+
+readable_struct! {
+    struct Requires {
+        requires_index: PoolIndex,
+        requires_flags: AccessFlags,
+        // Zero when the version of the module wasn't recorded
+        requires_version_index: PoolIndex,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub module_name_index: PoolIndex,
+    pub module_flags: AccessFlags,
+    // Zero when the module has no version
+    pub module_version_index: PoolIndex,
+    pub requires: Vec<Requires>,
+    pub exports: Vec<Exports>,
+    pub opens: Vec<Opens>,
+    // Class indexes of the service interfaces used
+    pub uses_index: Vec<PoolIndex>,
+    pub provides: Vec<Provides>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Exports {
+    pub exports_index: PoolIndex,
+    pub exports_flags: AccessFlags,
+    // Modules the package is exported to. Empty when exported to all modules
+    pub exports_to_index: Vec<PoolIndex>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Opens {
+    pub opens_index: PoolIndex,
+    pub opens_flags: AccessFlags,
+    // Modules the package is opened to. Empty when opened to all modules
+    pub opens_to_index: Vec<PoolIndex>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Provides {
+    // Class index of the service interface
+    pub provides_index: PoolIndex,
+    // Class indexes of the implementations
+    pub provides_with_index: Vec<PoolIndex>,
+}
+
+/// Resolves the version at the index which is optional for
+/// both modules and the modules they require
+fn version(constant_pool: &ConstantPool, index: PoolIndex) -> Result<Option<&String>, ConstantError> {
+    match index {
+        0 => Ok(None),
+        index => constant_pool.get_utf8(&index).map(Some)
+    }
+}
+
+impl Module {
+    pub fn name<'a>(&self, constant_pool: &'a ConstantPool) -> Result<&'a String, ConstantError> {
+        constant_pool.get_module_name(&self.module_name_index)
+    }
+
+    pub fn version<'a>(&self, constant_pool: &'a ConstantPool) -> Result<Option<&'a String>, ConstantError> {
+        version(constant_pool, self.module_version_index)
+    }
+
+    /// Whether all the packages of the module are open for reflection
+    pub fn is_open(&self) -> bool { self.module_flags.is_set(ModuleAccessFlag::Open) }
+
+    pub fn uses(&self, constant_pool: &ConstantPool) -> Result<Vec<ClassPath>, ConstantError> {
+        self.uses_index.iter()
+            .map(|index| constant_pool.get_class_path_required(index))
+            .collect()
+    }
+}
+
+impl Readable for Module {
+    fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized {
+        Ok(Module {
+            module_name_index: PoolIndex::read(i)?,
+            module_flags: AccessFlags::read(i)?,
+            module_version_index: PoolIndex::read(i)?,
+            requires: u16::read_vec(i).map_err(|err| err.in_field("requires"))?,
+            exports: u16::read_vec(i).map_err(|err| err.in_field("exports"))?,
+            opens: u16::read_vec(i).map_err(|err| err.in_field("opens"))?,
+            uses_index: u16::read_vec(i).map_err(|err| err.in_field("uses_index"))?,
+            provides: u16::read_vec(i).map_err(|err| err.in_field("provides"))?,
+        })
+    }
+}
+
+impl Writable for Module {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        self.module_name_index.write(o)?;
+        self.module_flags.write(o)?;
+        self.module_version_index.write(o)?;
+        u16::write_vec(o, &self.requires)?;
+        u16::write_vec(o, &self.exports)?;
+        u16::write_vec(o, &self.opens)?;
+        u16::write_vec(o, &self.uses_index)?;
+        u16::write_vec(o, &self.provides)
+    }
+}
+
+impl Requires {
+    pub fn module<'a>(&self, constant_pool: &'a ConstantPool) -> Result<&'a String, ConstantError> {
+        constant_pool.get_module_name(&self.requires_index)
+    }
+
+    pub fn version<'a>(&self, constant_pool: &'a ConstantPool) -> Result<Option<&'a String>, ConstantError> {
+        version(constant_pool, self.requires_version_index)
+    }
+
+    pub fn is_transitive(&self) -> bool { self.requires_flags.is_set(RequiresAccessFlag::Transitive) }
+
+    /// Whether the module is only required at compile time (requires static)
+    pub fn is_static_phase(&self) -> bool { self.requires_flags.is_set(RequiresAccessFlag::StaticPhase) }
+
+    /// Whether the dependency is implicit (e.g. java.base) so isn't declared in source
+    pub fn is_mandated(&self) -> bool { self.requires_flags.is_set(RequiresAccessFlag::Mandated) }
+}
+
+impl Exports {
+    /// The internal name of the package (e.g. java/lang)
+    pub fn package<'a>(&self, constant_pool: &'a ConstantPool) -> Result<&'a String, ConstantError> {
+        constant_pool.get_package_name(&self.exports_index)
+    }
+
+    pub fn to_modules<'a>(&self, constant_pool: &'a ConstantPool) -> Result<Vec<&'a String>, ConstantError> {
+        self.exports_to_index.iter()
+            .map(|index| constant_pool.get_module_name(index))
+            .collect()
+    }
+}
+
+impl Readable for Exports {
+    fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized {
+        Ok(Exports {
+            exports_index: PoolIndex::read(i)?,
+            exports_flags: AccessFlags::read(i)?,
+            exports_to_index: u16::read_vec(i)?,
+        })
+    }
+}
+
+impl Writable for Exports {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        self.exports_index.write(o)?;
+        self.exports_flags.write(o)?;
+        u16::write_vec(o, &self.exports_to_index)
+    }
+}
+
+impl Opens {
+    /// The internal name of the package (e.g. java/lang)
+    pub fn package<'a>(&self, constant_pool: &'a ConstantPool) -> Result<&'a String, ConstantError> {
+        constant_pool.get_package_name(&self.opens_index)
+    }
+
+    pub fn to_modules<'a>(&self, constant_pool: &'a ConstantPool) -> Result<Vec<&'a String>, ConstantError> {
+        self.opens_to_index.iter()
+            .map(|index| constant_pool.get_module_name(index))
+            .collect()
+    }
+}
+
+impl Readable for Opens {
+    fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized {
+        Ok(Opens {
+            opens_index: PoolIndex::read(i)?,
+            opens_flags: AccessFlags::read(i)?,
+            opens_to_index: u16::read_vec(i)?,
+        })
+    }
+}
+
+impl Writable for Opens {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        self.opens_index.write(o)?;
+        self.opens_flags.write(o)?;
+        u16::write_vec(o, &self.opens_to_index)
+    }
+}
+
+impl Provides {
+    pub fn service(&self, constant_pool: &ConstantPool) -> Result<ClassPath, ConstantError> {
+        constant_pool.get_class_path_required(&self.provides_index)
+    }
+
+    pub fn implementations(&self, constant_pool: &ConstantPool) -> Result<Vec<ClassPath>, ConstantError> {
+        self.provides_with_index.iter()
+            .map(|index| constant_pool.get_class_path_required(index))
+            .collect()
+    }
+}
+
+impl Readable for Provides {
+    fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized {
+        Ok(Provides {
+            provides_index: PoolIndex::read(i)?,
+            provides_with_index: u16::read_vec(i)?,
+        })
+    }
+}
+
+impl Writable for Provides {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        self.provides_index.write(o)?;
+        u16::write_vec(o, &self.provides_with_index)
+    }
+}
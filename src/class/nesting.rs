@@ -0,0 +1,149 @@
+//! Resolves how the classes referenced by a class are nested using its
+//! InnerClasses and EnclosingMethod attributes (JVMS 4.7.6 and 4.7.7). The
+//! binary name of a nested class only joins the names of its outer classes
+//! with $ by convention so splitting on $ is left as a fallback for classes
+//! which don't have the attributes
+use std::collections::HashMap;
+
+use crate::class::attribute::{Attribute, AttributeValue};
+use crate::class::class::{ClassPath, NestingKind};
+use crate::class::constant::ConstantPool;
+use crate::class::descriptor::Descriptor;
+
+#[derive(Debug, Clone)]
+enum Entry {
+    Member { outer: String },
+    // The simple name of the class without the number javac prefixes it with
+    Local { name: String },
+    Anonymous,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClassNesting {
+    // Keyed by the internal name of the nested class
+    entries: HashMap<String, Entry>,
+    // Whether the entries are from an InnerClasses attribute. Every nested
+    // class a class references must have an entry so any class without one
+    // is top level
+    complete: bool,
+    // The internal names of the class and the class enclosing it when the
+    // class is local or anonymous
+    enclosing: Option<(String, String)>,
+}
+
+impl ClassNesting {
+    /// Collects the nesting from the attributes of a class. Entries which
+    /// reference invalid constants are skipped
+    pub fn new(this_class: &str, attributes: &[Attribute], constant_pool: &ConstantPool) -> ClassNesting {
+        let mut nesting = ClassNesting::default();
+        for attribute in attributes {
+            match &attribute.value {
+                AttributeValue::InnerClasses(values) => {
+                    nesting.complete = true;
+                    for value in values {
+                        let inner = match constant_pool.get_class_name(&value.inner_class_info_index) {
+                            Ok(inner) => inner,
+                            Err(_) => continue,
+                        };
+                        let entry = if value.outer_class_info_index != 0 {
+                            match constant_pool.get_class_name(&value.outer_class_info_index) {
+                                Ok(outer) => Entry::Member { outer: outer.clone() },
+                                Err(_) => continue,
+                            }
+                        } else if value.inner_name_index != 0 {
+                            match constant_pool.get_utf8(&value.inner_name_index) {
+                                Ok(name) => Entry::Local { name: name.clone() },
+                                Err(_) => continue,
+                            }
+                        } else {
+                            Entry::Anonymous
+                        };
+                        nesting.entries.insert(inner.clone(), entry);
+                    }
+                }
+                AttributeValue::EnclosingMethod(value) => {
+                    if let Ok(outer) = constant_pool.get_class_name(&value.class_index) {
+                        nesting.enclosing = Some((this_class.to_string(), outer.clone()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        // The class is local or anonymous even when its own entry is missing
+        if let Some((this_class, outer)) = &nesting.enclosing {
+            let segment = this_class.strip_prefix(outer.as_str())
+                .and_then(|rest| rest.strip_prefix('$'))
+                .filter(|segment| !segment.is_empty());
+            if let Some(segment) = segment {
+                let name = segment.trim_start_matches(|c: char| c.is_ascii_digit());
+                let entry = if name.is_empty() { Entry::Anonymous } else { Entry::Local { name: name.to_string() } };
+                nesting.entries.entry(this_class.clone()).or_insert(entry);
+            }
+        }
+        nesting
+    }
+
+    /// Whether the nesting came from an InnerClasses attribute rather than
+    /// having to be guessed from the names
+    pub fn is_complete(&self) -> bool { self.complete }
+
+    /// The path of the class with the internal name (e.g. java/util/Map$Entry)
+    pub fn resolve(&self, internal_name: &str) -> ClassPath {
+        let (outer, kind) = match self.entries.get(internal_name) {
+            Some(Entry::Member { outer }) => (Some(outer.as_str()), NestingKind::Member),
+            Some(Entry::Local { name }) => (self.enclosing_class(internal_name, name), NestingKind::Local),
+            Some(Entry::Anonymous) => (self.enclosing_class(internal_name, ""), NestingKind::Anonymous),
+            None if self.complete => return ClassPath::top_level(internal_name),
+            None => return ClassPath::from(internal_name),
+        };
+        let outer = match outer {
+            Some(outer) => outer,
+            None => return ClassPath::from(internal_name),
+        };
+        let segment = internal_name.strip_prefix(outer)
+            .and_then(|rest| rest.strip_prefix('$'))
+            .filter(|segment| !segment.is_empty());
+        match segment {
+            // The outer class is always shorter so this terminates
+            Some(segment) => self.resolve(outer).nested(segment, kind),
+            // Obfuscated classes don't always have names starting with the
+            // name of their outer class so can't be split
+            None => {
+                let mut path = ClassPath::top_level(internal_name);
+                path.nesting = vec![kind];
+                path
+            }
+        }
+    }
+
+    /// Resolves the paths of the classes in a field or method descriptor
+    pub fn resolve_descriptor(&self, descriptor: &mut Descriptor) {
+        match descriptor {
+            Descriptor::Class(path) => *path = self.resolve(&path.internal_path()),
+            Descriptor::Array(array) => self.resolve_descriptor(&mut array.descriptor),
+            Descriptor::Method(method) => {
+                for parameter in &mut method.parameters {
+                    self.resolve_descriptor(parameter);
+                }
+                self.resolve_descriptor(&mut method.return_type);
+            }
+            _ => {}
+        }
+    }
+
+    /// The internal name of the class enclosing a local or anonymous class.
+    /// Only the EnclosingMethod attribute of the class itself names it so
+    /// for other classes the name is found by removing the simple name and
+    /// the number javac adds before it (e.g. Outer$1Local and Outer$1)
+    fn enclosing_class<'a>(&'a self, internal_name: &'a str, name: &str) -> Option<&'a str> {
+        if let Some((this_class, outer)) = &self.enclosing {
+            if this_class == internal_name {
+                return Some(outer);
+            }
+        }
+        internal_name.strip_suffix(name)?
+            .trim_end_matches(|c: char| c.is_ascii_digit())
+            .strip_suffix('$')
+            .filter(|outer| !outer.is_empty())
+    }
+}
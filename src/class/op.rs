@@ -182,7 +182,29 @@ pub enum Instr {
 }
 
 impl Instr {
-    fn read_instr(i: &mut Cursor<Vec<u8>>, wide: bool, pos: i32) -> Result<Self, DecompileError> where Self: Sized {
+    /// The constant pool index used by this instruction if it has one
+    pub fn pool_index(&self) -> Option<PoolIndex> {
+        match self {
+            Instr::InvokeSpecial(index) |
+            Instr::InvokeStatic(index) |
+            Instr::InvokeVirtual(index) |
+            Instr::InvokeInterface(index) |
+            Instr::InvokeDynamic(index) |
+            Instr::PutField(index) |
+            Instr::GetField(index) |
+            Instr::PutStatic(index) |
+            Instr::GetStatic(index) |
+            Instr::CheckCast(index) |
+            Instr::InstanceOf(index) |
+            Instr::LoadConst(index) |
+            Instr::ANewArray(index) |
+            Instr::New(index) |
+            Instr::MultiANewArray { index, .. } => Some(*index),
+            _ => None
+        }
+    }
+
+    fn read_instr(i: &mut Cursor<&[u8]>, wide: bool, pos: i32) -> Result<Self, DecompileError> where Self: Sized {
         let code = u8::read(i)?;
         Ok(match code {
             0x0 => Instr::Nop,
@@ -340,34 +362,35 @@ impl Instr {
             0x96 => Instr::FCmpG,
             0x97 => Instr::DCmpL,
             0x98 => Instr::DCmpG,
-            0x99 => Instr::IfEq((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0x9a => Instr::IfNe((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0x9b => Instr::IfLt((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0x9c => Instr::IfGe((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0x9d => Instr::IfGt((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0x9e => Instr::IfLe((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0x9f => Instr::IfICmpEq((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0xa0 => Instr::IfICmpNe((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0xa1 => Instr::IfICmpLt((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0xa2 => Instr::IfICmpGe((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0xa3 => Instr::IfICmpGt((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0xa4 => Instr::IfICmpLe((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0xa5 => Instr::IfACmpEq((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0xa6 => Instr::IfACmpNe((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0xa7 => Instr::Goto((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0xa8 => Instr::JSr((pos + ((u16::read(i)? as i16) as i32)) as u16),
+            0x99 => Instr::IfEq(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0x9a => Instr::IfNe(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0x9b => Instr::IfLt(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0x9c => Instr::IfGe(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0x9d => Instr::IfGt(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0x9e => Instr::IfLe(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0x9f => Instr::IfICmpEq(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0xa0 => Instr::IfICmpNe(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0xa1 => Instr::IfICmpLt(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0xa2 => Instr::IfICmpGe(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0xa3 => Instr::IfICmpGt(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0xa4 => Instr::IfICmpLe(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0xa5 => Instr::IfACmpEq(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0xa6 => Instr::IfACmpNe(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0xa7 => Instr::Goto(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0xa8 => Instr::JSr(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
             0xa9 => Instr::Ret(if wide { u16::read(i)? } else { u8::read(i)? as u16 }),
             0xaa => {
                 let pad = (1 + ((i.position() - 1) / 4)) * 4 - i.position();
                 for _ in 0..pad {
                     u8::read(i)?;
                 }
-                let default = (pos + (u32::read(i)? as i32)) as u32;
+                let default = pos.wrapping_add(u32::read(i)? as i32) as u32;
                 let low = u32::read(i)?;
                 let high = u32::read(i)?;
                 let mut offsets = Vec::new();
-                for _ in low..=high {
-                    offsets.push((pos + (u32::read(i)? as i32)) as u32);
+                // The bounds are signed so a negative low must not be compared as unsigned
+                for _ in (low as i32)..=(high as i32) {
+                    offsets.push(pos.wrapping_add(u32::read(i)? as i32) as u32);
                 }
                 Instr::TableSwitch {
                     default,
@@ -381,13 +404,13 @@ impl Instr {
                 for _ in 0..pad {
                     u8::read(i)?;
                 }
-                let default = (pos + (u32::read(i)? as i32)) as u32;
+                let default = pos.wrapping_add(u32::read(i)? as i32) as u32;
                 let count = u32::read(i)?;
                 let mut pairs = Vec::new();
                 for _ in 0..count {
                     pairs.push((
                         u32::read(i)? as i32,
-                        (pos + (u32::read(i)? as i32)) as u32,
+                        pos.wrapping_add(u32::read(i)? as i32) as u32,
                     ));
                 }
                 Instr::LookupSwitch { default, pairs }
@@ -438,23 +461,67 @@ impl Instr {
             0xc1 => Instr::InstanceOf(PoolIndex::read(i)?),
             0xc2 => Instr::MonitorEnter,
             0xc3 => Instr::MonitorExit,
-            0xc4 => Instr::read_instr(i, true, pos)?,
+            // A wide instruction can't modify another wide instruction
+            0xc4 if !wide => Instr::read_instr(i, true, pos)?,
             0xc5 => Instr::MultiANewArray {
                 index: u16::read(i)?,
                 dimensions: u8::read(i)?,
             },
-            0xc6 => Instr::IfNull((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0xc7 => Instr::IfNonNull((pos + ((u16::read(i)? as i16) as i32)) as u16),
-            0xc8 => Instr::Goto((pos + (u32::read(i)? as i32)) as u16),
-            0xc9 => Instr::JSr((pos + (u32::read(i)? as i32)) as u16),
+            0xc6 => Instr::IfNull(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0xc7 => Instr::IfNonNull(pos.wrapping_add((u16::read(i)? as i16) as i32) as u16),
+            0xc8 => Instr::Goto(pos.wrapping_add(u32::read(i)? as i32) as u16),
+            0xc9 => Instr::JSr(pos.wrapping_add(u32::read(i)? as i32) as u16),
             _ => return Err(DecompileError::UnknownInstruction(code)),
         })
     }
 }
 
+/// Names of the opcodes from nop (0x00) to jsr_w (0xc9) as used by javap
+const MNEMONICS: [&str; 0xca] = [
+    "nop", "aconst_null", "iconst_m1", "iconst_0", "iconst_1", "iconst_2",
+    "iconst_3", "iconst_4", "iconst_5", "lconst_0", "lconst_1", "fconst_0",
+    "fconst_1", "fconst_2", "dconst_0", "dconst_1", "bipush", "sipush",
+    "ldc", "ldc_w", "ldc2_w", "iload", "lload", "fload",
+    "dload", "aload", "iload_0", "iload_1", "iload_2", "iload_3",
+    "lload_0", "lload_1", "lload_2", "lload_3", "fload_0", "fload_1",
+    "fload_2", "fload_3", "dload_0", "dload_1", "dload_2", "dload_3",
+    "aload_0", "aload_1", "aload_2", "aload_3", "iaload", "laload",
+    "faload", "daload", "aaload", "baload", "caload", "saload",
+    "istore", "lstore", "fstore", "dstore", "astore", "istore_0",
+    "istore_1", "istore_2", "istore_3", "lstore_0", "lstore_1", "lstore_2",
+    "lstore_3", "fstore_0", "fstore_1", "fstore_2", "fstore_3", "dstore_0",
+    "dstore_1", "dstore_2", "dstore_3", "astore_0", "astore_1", "astore_2",
+    "astore_3", "iastore", "lastore", "fastore", "dastore", "aastore",
+    "bastore", "castore", "sastore", "pop", "pop2", "dup",
+    "dup_x1", "dup_x2", "dup2", "dup2_x1", "dup2_x2", "swap",
+    "iadd", "ladd", "fadd", "dadd", "isub", "lsub",
+    "fsub", "dsub", "imul", "lmul", "fmul", "dmul",
+    "idiv", "ldiv", "fdiv", "ddiv", "irem", "lrem",
+    "frem", "drem", "ineg", "lneg", "fneg", "dneg",
+    "ishl", "lshl", "ishr", "lshr", "iushr", "lushr",
+    "iand", "land", "ior", "lor", "ixor", "lxor",
+    "iinc", "i2l", "i2f", "i2d", "l2i", "l2f",
+    "l2d", "f2i", "f2l", "f2d", "d2i", "d2l",
+    "d2f", "i2b", "i2c", "i2s", "lcmp", "fcmpl",
+    "fcmpg", "dcmpl", "dcmpg", "ifeq", "ifne", "iflt",
+    "ifge", "ifgt", "ifle", "if_icmpeq", "if_icmpne", "if_icmplt",
+    "if_icmpge", "if_icmpgt", "if_icmple", "if_acmpeq", "if_acmpne", "goto",
+    "jsr", "ret", "tableswitch", "lookupswitch", "ireturn", "lreturn",
+    "freturn", "dreturn", "areturn", "return", "getstatic", "putstatic",
+    "getfield", "putfield", "invokevirtual", "invokespecial", "invokestatic", "invokeinterface",
+    "invokedynamic", "new", "newarray", "anewarray", "arraylength", "athrow",
+    "checkcast", "instanceof", "monitorenter", "monitorexit", "wide", "multianewarray",
+    "ifnull", "ifnonnull", "goto_w", "jsr_w",
+];
+
+/// The name of an opcode or None if the opcode isn't defined
+pub fn mnemonic(opcode: u8) -> Option<&'static str> {
+    MNEMONICS.get(opcode as usize).copied()
+}
+
 pub type InstrSet = Vec<(u64, Instr)>;
 
-pub fn parse_code(data: Vec<u8>) -> Result<InstrSet, DecompileError> {
+pub fn parse_code(data: &[u8]) -> Result<InstrSet, DecompileError> {
     let length = data.len() as u64;
     let mut cursor = Cursor::new(data);
     let mut instructions = Vec::new();
@@ -0,0 +1,646 @@
+//! Generic signatures stored in the Signature and LocalVariableTypeTable
+//! attributes. Unlike descriptors, signatures keep the type arguments and
+//! type variables that are erased when the class is compiled. Signatures
+//! are parsed following JVMS 4.7.9.1 and can be rendered back to Java
+use std::fmt::{Display, Formatter, Write};
+
+use crate::class::attribute::{Attribute, AttributeValue};
+use crate::class::class::ClassPath;
+use crate::class::descriptor::{ArrayDescriptor, Descriptor};
+use crate::error::SignatureError;
+use crate::io::ParseLimits;
+
+/// The generic type of a field, local variable, parameter or type argument
+#[derive(Debug, Clone)]
+pub enum TypeSignature {
+    // A primitive type (or void for method return types)
+    Base(Descriptor),
+    Class(ClassTypeSignature),
+    // The name of a type variable (e.g. T)
+    TypeVariable(String),
+    // An array of the component type
+    Array(Box<TypeSignature>),
+}
+
+/// A class type along with the type arguments of the class and
+/// of each of the classes that it is nested in
+#[derive(Debug, Clone)]
+pub struct ClassTypeSignature {
+    // Internal name of the package (e.g. java/util). Empty for the default package
+    pub package: String,
+    // The outermost class followed by each inner class (e.g. Map then Entry)
+    pub classes: Vec<SimpleClassTypeSignature>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SimpleClassTypeSignature {
+    pub name: String,
+    pub type_arguments: Vec<TypeArgument>,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeArgument {
+    // ?
+    Wildcard,
+    Exact(TypeSignature),
+    // ? extends
+    Extends(TypeSignature),
+    // ? super
+    Super(TypeSignature),
+}
+
+/// A type parameter declared by a generic class or method
+/// along with its bounds (e.g. T extends Number & Comparable<T>)
+#[derive(Debug, Clone)]
+pub struct TypeParameter {
+    pub name: String,
+    // None when the only bounds are interfaces (e.g. T::Ljava/lang/Runnable;)
+    pub class_bound: Option<TypeSignature>,
+    pub interface_bounds: Vec<TypeSignature>,
+}
+
+/// The signature of a generic class or of a class extending
+/// or implementing a parameterized type
+#[derive(Debug, Clone)]
+pub struct ClassSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub superclass: ClassTypeSignature,
+    pub interfaces: Vec<ClassTypeSignature>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MethodSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub parameters: Vec<TypeSignature>,
+    pub return_type: TypeSignature,
+    // Class types or type variables
+    pub throws: Vec<TypeSignature>,
+}
+
+/// The unparsed value of the Signature attribute of a class, field,
+/// method or record component. None when the type isn't generic
+pub fn signature(attributes: &[Attribute]) -> Option<&String> {
+    attributes.iter()
+        .find_map(|attribute| match &attribute.value {
+            AttributeValue::Signature(_, value) => Some(value),
+            _ => None
+        })
+}
+
+/// How class names are written when rendering a signature as Java
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum JavaNames {
+    // Only the name of the class (e.g. List) as written in source where the
+    // class is imported. Type parameters bounded by Object omit the bound
+    Simple,
+    // The fully qualified name (e.g. java.util.List) with nested classes
+    // kept as they are in the signature like javap
+    Qualified,
+}
+
+impl TypeSignature {
+    /// Parses a field signature (e.g. Ljava/util/List<TT;>;). Primitive
+    /// types are accepted as well as they appear in method signatures
+    pub fn parse(value: &str) -> Result<TypeSignature, SignatureError> {
+        TypeSignature::parse_with_limits(value, &ParseLimits::default())
+    }
+
+    /// Parses a field signature failing if type arguments are nested
+    /// deeper than the maximum nesting depth of the limits
+    pub fn parse_with_limits(value: &str, limits: &ParseLimits) -> Result<TypeSignature, SignatureError> {
+        let mut reader = SignatureReader::new(value, limits);
+        let signature = reader.type_signature()?;
+        reader.finish()?;
+        Ok(signature)
+    }
+
+    /// The descriptor this type is erased to. Type variables are erased
+    /// to Object as their bounds aren't known from the type alone
+    pub fn erasure(&self) -> Descriptor {
+        match self {
+            TypeSignature::Base(descriptor) => descriptor.clone(),
+            TypeSignature::Class(class) => Descriptor::Class(class.class_path()),
+            TypeSignature::TypeVariable(_) => Descriptor::Class(ClassPath::from("java/lang/Object")),
+            TypeSignature::Array(component) => {
+                let (dimensions, descriptor) = match component.erasure() {
+                    Descriptor::Array(array) => (array.dimensions.saturating_add(1), array.descriptor),
+                    descriptor => (1, Box::new(descriptor))
+                };
+                Descriptor::Array(ArrayDescriptor { dimensions, descriptor })
+            }
+        }
+    }
+
+    /// Renders the type as Java (e.g. Map<String, ? extends List<T>>)
+    pub fn to_java(&self, names: JavaNames) -> String {
+        let mut out = String::new();
+        self.write_java(&mut out, names);
+        out
+    }
+
+    fn write_java(&self, out: &mut String, names: JavaNames) {
+        match self {
+            TypeSignature::Base(descriptor) => out.push_str(&descriptor.to_java()),
+            TypeSignature::Class(class) => class.write_java(out, names),
+            TypeSignature::TypeVariable(name) => out.push_str(name),
+            TypeSignature::Array(component) => {
+                component.write_java(out, names);
+                out.push_str("[]");
+            }
+        }
+    }
+
+    /// Whether this is the class java.lang.Object without any type arguments
+    pub fn is_object(&self) -> bool {
+        matches!(self, TypeSignature::Class(class) if class.is_object())
+    }
+
+    /// The classes named by this type and any of its type arguments. Only
+    /// the outermost class of a nested class of a parameterized class is
+    /// included (e.g. Outer for Outer<T>.Inner) as that is the name used
+    pub fn class_paths(&self) -> Vec<ClassPath> {
+        let mut out = Vec::new();
+        self.collect_class_paths(&mut out);
+        out
+    }
+
+    fn collect_class_paths(&self, out: &mut Vec<ClassPath>) {
+        match self {
+            TypeSignature::Class(class) => class.collect_class_paths(out),
+            TypeSignature::Array(component) => component.collect_class_paths(out),
+            TypeSignature::Base(_) | TypeSignature::TypeVariable(_) => {}
+        }
+    }
+}
+
+impl Display for TypeSignature {
+    /// Writes the signature back out in its internal form
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeSignature::Base(descriptor) => f.write_str(&descriptor.to_internal_java()),
+            TypeSignature::Class(class) => write!(f, "{}", class),
+            TypeSignature::TypeVariable(name) => write!(f, "T{};", name),
+            TypeSignature::Array(component) => write!(f, "[{}", component),
+        }
+    }
+}
+
+impl ClassTypeSignature {
+    /// The path of the innermost class with the type arguments removed
+    pub fn class_path(&self) -> ClassPath {
+        let names: Vec<&str> = self.classes.iter()
+            .map(|class| class.name.as_str())
+            .collect();
+        let name = names.join("$");
+        if self.package.is_empty() {
+            ClassPath::from(name.as_str())
+        } else {
+            ClassPath::from(format!("{}/{}", self.package, name).as_str())
+        }
+    }
+
+    pub fn to_java(&self, names: JavaNames) -> String {
+        let mut out = String::new();
+        self.write_java(&mut out, names);
+        out
+    }
+
+    fn write_java(&self, out: &mut String, names: JavaNames) {
+        for (index, class) in self.classes.iter().enumerate() {
+            if index > 0 {
+                out.push('.');
+                out.push_str(&class.name);
+            } else {
+                match names {
+                    JavaNames::Simple => out.push_str(&ClassPath::from(class.name.as_str()).name),
+                    JavaNames::Qualified => {
+                        if !self.package.is_empty() {
+                            out.push_str(&self.package.replace('/', "."));
+                            out.push('.');
+                        }
+                        out.push_str(&class.name);
+                    }
+                }
+            }
+            if !class.type_arguments.is_empty() {
+                out.push('<');
+                for (index, argument) in class.type_arguments.iter().enumerate() {
+                    if index > 0 {
+                        out.push_str(", ");
+                    }
+                    match argument {
+                        TypeArgument::Wildcard => out.push('?'),
+                        TypeArgument::Exact(value) => value.write_java(out, names),
+                        TypeArgument::Extends(value) => {
+                            out.push_str("? extends ");
+                            value.write_java(out, names);
+                        }
+                        TypeArgument::Super(value) => {
+                            out.push_str("? super ");
+                            value.write_java(out, names);
+                        }
+                    }
+                }
+                out.push('>');
+            }
+        }
+    }
+
+    pub fn is_object(&self) -> bool {
+        self.package == "java/lang"
+            && matches!(&self.classes[..], [class] if class.name == "Object" && class.type_arguments.is_empty())
+    }
+
+    fn collect_class_paths(&self, out: &mut Vec<ClassPath>) {
+        // Nested classes of parameterized classes are named through the outer class
+        match self.classes.first() {
+            Some(class) if self.classes.len() > 1 => out.push(ClassTypeSignature {
+                package: self.package.clone(),
+                classes: vec![SimpleClassTypeSignature { name: class.name.clone(), type_arguments: Vec::new() }],
+            }.class_path()),
+            _ => out.push(self.class_path()),
+        }
+        for class in &self.classes {
+            for argument in &class.type_arguments {
+                match argument {
+                    TypeArgument::Wildcard => {}
+                    TypeArgument::Exact(value) |
+                    TypeArgument::Extends(value) |
+                    TypeArgument::Super(value) => value.collect_class_paths(out),
+                }
+            }
+        }
+    }
+}
+
+impl TypeParameter {
+    /// Renders the parameter and its bounds (e.g. T extends Comparable<T>)
+    pub fn to_java(&self, names: JavaNames) -> String {
+        let mut out = self.name.clone();
+        let mut separator = " extends ";
+        if let Some(bound) = &self.class_bound {
+            // Object bounds are implicit in source but shown by javap
+            if names == JavaNames::Qualified || !bound.is_object() {
+                out.push_str(separator);
+                bound.write_java(&mut out, names);
+                separator = " & ";
+            }
+        }
+        for bound in &self.interface_bounds {
+            out.push_str(separator);
+            bound.write_java(&mut out, names);
+            separator = " & ";
+        }
+        out
+    }
+
+    fn class_paths(&self) -> Vec<ClassPath> {
+        self.class_bound.iter()
+            .chain(&self.interface_bounds)
+            .flat_map(TypeSignature::class_paths)
+            .collect()
+    }
+}
+
+/// Renders a list of type parameters (e.g. <K, V extends Number>).
+/// Empty when there are no type parameters
+pub fn type_parameters_to_java(values: &[TypeParameter], names: JavaNames) -> String {
+    if values.is_empty() {
+        return String::new();
+    }
+    let values: Vec<String> = values.iter()
+        .map(|value| value.to_java(names))
+        .collect();
+    format!("<{}>", values.join(", "))
+}
+
+impl ClassSignature {
+    pub fn parse(value: &str) -> Result<ClassSignature, SignatureError> {
+        ClassSignature::parse_with_limits(value, &ParseLimits::default())
+    }
+
+    pub fn parse_with_limits(value: &str, limits: &ParseLimits) -> Result<ClassSignature, SignatureError> {
+        let mut reader = SignatureReader::new(value, limits);
+        let type_parameters = reader.type_parameters()?;
+        let superclass = reader.class_type_signature()?;
+        let mut interfaces = Vec::new();
+        while reader.peek().is_some() {
+            interfaces.push(reader.class_type_signature()?);
+        }
+        Ok(ClassSignature { type_parameters, superclass, interfaces })
+    }
+
+    /// The classes named by the bounds, superclass and interfaces
+    pub fn class_paths(&self) -> Vec<ClassPath> {
+        let mut out: Vec<ClassPath> = self.type_parameters.iter()
+            .flat_map(TypeParameter::class_paths)
+            .collect();
+        self.superclass.collect_class_paths(&mut out);
+        for interface in &self.interfaces {
+            interface.collect_class_paths(&mut out);
+        }
+        out
+    }
+}
+
+impl Display for ClassSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_type_parameters(f, &self.type_parameters)?;
+        write!(f, "{}", self.superclass)?;
+        for interface in &self.interfaces {
+            write!(f, "{}", interface)?;
+        }
+        Ok(())
+    }
+}
+
+impl MethodSignature {
+    pub fn parse(value: &str) -> Result<MethodSignature, SignatureError> {
+        MethodSignature::parse_with_limits(value, &ParseLimits::default())
+    }
+
+    pub fn parse_with_limits(value: &str, limits: &ParseLimits) -> Result<MethodSignature, SignatureError> {
+        let mut reader = SignatureReader::new(value, limits);
+        let type_parameters = reader.type_parameters()?;
+        reader.expect('(')?;
+        let mut parameters = Vec::new();
+        while reader.peek() != Some(')') {
+            parameters.push(reader.type_signature()?);
+        }
+        reader.expect(')')?;
+        let return_type = reader.type_signature()?;
+        let mut throws = Vec::new();
+        while reader.peek().is_some() {
+            reader.expect('^')?;
+            let position = reader.position;
+            match reader.reference_type()? {
+                TypeSignature::Array(_) => return Err(SignatureError::Unexpected(position, '[')),
+                value => throws.push(value),
+            }
+        }
+        Ok(MethodSignature { type_parameters, parameters, return_type, throws })
+    }
+
+    /// The classes named by the bounds, parameters, return type and throws clause
+    pub fn class_paths(&self) -> Vec<ClassPath> {
+        self.type_parameters.iter()
+            .flat_map(TypeParameter::class_paths)
+            .chain(self.parameters.iter().flat_map(TypeSignature::class_paths))
+            .chain(self.return_type.class_paths())
+            .chain(self.throws.iter().flat_map(TypeSignature::class_paths))
+            .collect()
+    }
+}
+
+impl Display for MethodSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_type_parameters(f, &self.type_parameters)?;
+        f.write_char('(')?;
+        for parameter in &self.parameters {
+            write!(f, "{}", parameter)?;
+        }
+        write!(f, "){}", self.return_type)?;
+        for value in &self.throws {
+            write!(f, "^{}", value)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_type_parameters(f: &mut Formatter<'_>, values: &[TypeParameter]) -> std::fmt::Result {
+    if values.is_empty() {
+        return Ok(());
+    }
+    f.write_char('<')?;
+    for value in values {
+        write!(f, "{}:", value.name)?;
+        if let Some(bound) = &value.class_bound {
+            write!(f, "{}", bound)?;
+        }
+        for bound in &value.interface_bounds {
+            write!(f, ":{}", bound)?;
+        }
+    }
+    f.write_char('>')
+}
+
+impl Display for ClassTypeSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("L")?;
+        if !self.package.is_empty() {
+            write!(f, "{}/", self.package)?;
+        }
+        for (index, class) in self.classes.iter().enumerate() {
+            if index > 0 {
+                f.write_str(".")?;
+            }
+            f.write_str(&class.name)?;
+            if !class.type_arguments.is_empty() {
+                f.write_str("<")?;
+                for argument in &class.type_arguments {
+                    match argument {
+                        TypeArgument::Wildcard => f.write_str("*")?,
+                        TypeArgument::Exact(value) => write!(f, "{}", value)?,
+                        TypeArgument::Extends(value) => write!(f, "+{}", value)?,
+                        TypeArgument::Super(value) => write!(f, "-{}", value)?,
+                    }
+                }
+                f.write_str(">")?;
+            }
+        }
+        f.write_str(";")
+    }
+}
+
+/// Recursive descent reader over the characters of a signature. Signatures
+/// come from the class file so the recursion into type arguments is bounded
+/// by the nesting depth of the limits
+pub(crate) struct SignatureReader<'a> {
+    value: &'a str,
+    // Byte position of the next character
+    position: usize,
+    // The limits for the type arguments at the current depth
+    limits: ParseLimits,
+}
+
+impl<'a> SignatureReader<'a> {
+    pub(crate) fn new(value: &'a str, limits: &ParseLimits) -> SignatureReader<'a> {
+        SignatureReader { value, position: 0, limits: *limits }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.value[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Result<char, SignatureError> {
+        let value = self.peek().ok_or(SignatureError::UnexpectedEnd)?;
+        self.position += value.len_utf8();
+        Ok(value)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SignatureError> {
+        let position = self.position;
+        match self.next()? {
+            value if value == expected => Ok(()),
+            value => Err(SignatureError::Unexpected(position, value))
+        }
+    }
+
+    /// Fails if anything is left after the end of the signature
+    pub(crate) fn finish(&self) -> Result<(), SignatureError> {
+        match self.peek() {
+            Some(value) => Err(SignatureError::Unexpected(self.position, value)),
+            None => Ok(())
+        }
+    }
+
+    /// Reads an unqualified name which is terminated by any of the
+    /// characters that have meaning within a signature
+    fn identifier(&mut self) -> Result<&'a str, SignatureError> {
+        let start = self.position;
+        while let Some(value) = self.peek() {
+            if matches!(value, '.' | ';' | '[' | '/' | '<' | '>' | ':') {
+                break;
+            }
+            self.position += value.len_utf8();
+        }
+        if self.position == start {
+            return match self.peek() {
+                Some(_) => Err(SignatureError::EmptyIdentifier(start)),
+                None => Err(SignatureError::UnexpectedEnd)
+            };
+        }
+        Ok(&self.value[start..self.position])
+    }
+
+    pub(crate) fn type_signature(&mut self) -> Result<TypeSignature, SignatureError> {
+        let position = self.position;
+        Ok(match self.next()? {
+            'L' => TypeSignature::Class(self.class_type()?),
+            'T' => {
+                let name = self.identifier()?.to_string();
+                self.expect(';')?;
+                TypeSignature::TypeVariable(name)
+            }
+            '[' => {
+                // Dimensions are counted rather than recursed into like descriptors
+                while self.peek() == Some('[') {
+                    self.position += 1;
+                }
+                let dimensions = u8::try_from(self.position - position)
+                    .map_err(|_| SignatureError::TooManyDimensions(position))?;
+                let mut value = self.type_signature()?;
+                for _ in 0..dimensions {
+                    value = TypeSignature::Array(Box::new(value));
+                }
+                value
+            }
+            value @ ('B' | 'C' | 'D' | 'F' | 'I' | 'J' | 'S' | 'Z' | 'V') => {
+                TypeSignature::Base(Descriptor::parse(value.encode_utf8(&mut [0; 4])))
+            }
+            value => return Err(SignatureError::Unexpected(position, value))
+        })
+    }
+
+    /// Reads a class type signature including its L prefix
+    fn class_type_signature(&mut self) -> Result<ClassTypeSignature, SignatureError> {
+        self.expect('L')?;
+        self.class_type()
+    }
+
+    /// Reads the optional type parameters at the start of a class or method signature
+    fn type_parameters(&mut self) -> Result<Vec<TypeParameter>, SignatureError> {
+        let mut out = Vec::new();
+        if self.peek() != Some('<') {
+            return Ok(out);
+        }
+        self.position += 1;
+        loop {
+            let name = self.identifier()?.to_string();
+            self.expect(':')?;
+            let class_bound = match self.peek() {
+                Some(':') | Some('>') => None,
+                _ => Some(self.reference_type()?)
+            };
+            let mut interface_bounds = Vec::new();
+            while self.peek() == Some(':') {
+                self.position += 1;
+                interface_bounds.push(self.reference_type()?);
+            }
+            out.push(TypeParameter { name, class_bound, interface_bounds });
+            if self.peek() == Some('>') {
+                break;
+            }
+        }
+        self.position += 1;
+        Ok(out)
+    }
+
+    /// Reads the remainder of a class type signature after its L prefix
+    fn class_type(&mut self) -> Result<ClassTypeSignature, SignatureError> {
+        let mut package: Vec<&str> = Vec::new();
+        let mut name = self.identifier()?;
+        while self.peek() == Some('/') {
+            self.position += 1;
+            package.push(name);
+            name = self.identifier()?;
+        }
+        let mut classes = vec![SimpleClassTypeSignature {
+            name: name.to_string(),
+            type_arguments: self.type_arguments()?,
+        }];
+        while self.peek() == Some('.') {
+            self.position += 1;
+            classes.push(SimpleClassTypeSignature {
+                name: self.identifier()?.to_string(),
+                type_arguments: self.type_arguments()?,
+            });
+        }
+        self.expect(';')?;
+        Ok(ClassTypeSignature { package: package.join("/"), classes })
+    }
+
+    fn type_arguments(&mut self) -> Result<Vec<TypeArgument>, SignatureError> {
+        let mut out = Vec::new();
+        if self.peek() != Some('<') {
+            return Ok(out);
+        }
+        let limits = self.limits;
+        self.limits = limits.nested()
+            .map_err(|_| SignatureError::NestedTooDeep(self.position))?;
+        self.position += 1;
+        loop {
+            let argument = match self.peek() {
+                Some('>') if !out.is_empty() => break,
+                Some('*') => {
+                    self.position += 1;
+                    TypeArgument::Wildcard
+                }
+                Some('+') => {
+                    self.position += 1;
+                    TypeArgument::Extends(self.reference_type()?)
+                }
+                Some('-') => {
+                    self.position += 1;
+                    TypeArgument::Super(self.reference_type()?)
+                }
+                _ => TypeArgument::Exact(self.reference_type()?)
+            };
+            out.push(argument);
+        }
+        self.position += 1;
+        self.limits = limits;
+        Ok(out)
+    }
+
+    /// Reads a type signature which must not be a primitive type
+    pub(crate) fn reference_type(&mut self) -> Result<TypeSignature, SignatureError> {
+        let position = self.position;
+        match self.type_signature()? {
+            TypeSignature::Base(_) => {
+                Err(SignatureError::Unexpected(position, self.value[position..].chars().next().unwrap_or_default()))
+            }
+            value => Ok(value)
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! Validation of the constant pool against the cross-reference rules of
+//! JVMS 4.4. Reading the pool only checks that it is structurally valid so
+//! this can be used to find broken or hostile class files before using them
+use std::fmt::{Display, Formatter};
+
+use crate::class::constant::{Constant, ConstantPool, ConstantTag, PoolIndex, ReferenceKind};
+use crate::class::descriptor::{Descriptor, MethodDescriptor};
+use crate::mutf8;
+
+/// A rule from JVMS 4.4 that a constant breaks
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConstantRule {
+    // The field references an index with no constant. This includes index 0,
+    // indexes past the end of the pool and the second slot of a Long or Double
+    MissingReference { field: &'static str, target: PoolIndex },
+    // The field references a constant of the wrong type
+    WrongReferenceType { field: &'static str, target: PoolIndex, expected: &'static str, found: ConstantTag },
+    // The utf8 constant isn't well-formed modified utf-8
+    InvalidUtf8,
+    // The class constant doesn't name a class or array type
+    InvalidClassName(String),
+    // The name isn't a valid unqualified name for a field or method
+    InvalidMemberName(String),
+    ExpectedFieldDescriptor(String),
+    ExpectedMethodDescriptor(String),
+    // Methods named <init> must return void
+    InitNotVoid(String),
+    InvalidReferenceKind(u8),
+    // The method handle kind doesn't allow a reference to the named member
+    InvalidHandleMember { kind: ReferenceKind, name: String },
+}
+
+impl Display for ConstantRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstantRule::MissingReference { field, target } =>
+                write!(f, "{} references #{} which is not a usable constant", field, target),
+            ConstantRule::WrongReferenceType { field, target, expected, found } =>
+                write!(f, "{} references #{} which is {:?} but must be {}", field, target, found, expected),
+            ConstantRule::InvalidUtf8 => f.write_str("utf8 constant is not valid modified utf-8"),
+            ConstantRule::InvalidClassName(name) => write!(f, "{:?} is not a valid class name", name),
+            ConstantRule::InvalidMemberName(name) => write!(f, "{:?} is not a valid member name", name),
+            ConstantRule::ExpectedFieldDescriptor(value) => write!(f, "{:?} is not a field descriptor", value),
+            ConstantRule::ExpectedMethodDescriptor(value) => write!(f, "{:?} is not a method descriptor", value),
+            ConstantRule::InitNotVoid(value) => write!(f, "<init> has descriptor {:?} which doesn't return void", value),
+            ConstantRule::InvalidReferenceKind(kind) => write!(f, "unknown method handle reference kind {}", kind),
+            ConstantRule::InvalidHandleMember { kind, name } =>
+                write!(f, "{} method handle cannot reference {:?}", kind.name(), name),
+        }
+    }
+}
+
+/// A rule broken by the constant at `index`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConstantDiagnostic {
+    pub index: PoolIndex,
+    pub rule: ConstantRule,
+}
+
+impl Display for ConstantDiagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}: {}", self.index, self.rule)
+    }
+}
+
+/// Checks for an unqualified name (JVMS 4.2.2). Method names can't
+/// contain angle brackets other than the special <init> and <clinit>
+fn is_unqualified_name(value: &str, method: bool) -> bool {
+    if method && (value == "<init>" || value == "<clinit>") {
+        return true;
+    }
+    !value.is_empty() && !value.contains(|c| matches!(c, '.' | ';' | '[' | '/')
+        || (method && matches!(c, '<' | '>')))
+}
+
+/// Checks for a binary class name in internal form (JVMS 4.2.1) or an
+/// array type descriptor which class constants use for array classes
+fn is_class_name(value: &str) -> bool {
+    if value.starts_with('[') {
+        return is_field_descriptor(value);
+    }
+    value.split('/').all(|segment| is_unqualified_name(segment, false))
+}
+
+fn is_field_descriptor(value: &str) -> bool {
+    Descriptor::parse_field(value).is_ok()
+}
+
+fn is_method_descriptor(value: &str) -> bool {
+    MethodDescriptor::parse(value).is_ok()
+}
+
+/// Collects diagnostics for a single constant
+struct Validator<'a> {
+    pool: &'a ConstantPool,
+    index: PoolIndex,
+    diagnostics: &'a mut Vec<ConstantDiagnostic>,
+}
+
+impl<'a> Validator<'a> {
+    fn report(&mut self, rule: ConstantRule) {
+        self.diagnostics.push(ConstantDiagnostic { index: self.index, rule });
+    }
+
+    /// Obtains the constant referenced by a field reporting a diagnostic
+    /// if it is missing or isn't one of the expected tags
+    fn reference(
+        &mut self,
+        field: &'static str,
+        target: PoolIndex,
+        expected: &'static str,
+        tags: &[ConstantTag],
+    ) -> Option<&'a Constant> {
+        let pool = self.pool;
+        match pool.inner.get(&target) {
+            None => {
+                self.report(ConstantRule::MissingReference { field, target });
+                None
+            }
+            Some(constant) => {
+                let found = ConstantTag::from(constant);
+                if tags.contains(&found) {
+                    Some(constant)
+                } else {
+                    self.report(ConstantRule::WrongReferenceType { field, target, expected, found });
+                    None
+                }
+            }
+        }
+    }
+
+    fn utf8(&mut self, field: &'static str, target: PoolIndex) -> Option<&'a str> {
+        match self.reference(field, target, "Utf8", &[ConstantTag::Utf8])? {
+            Constant::Utf8(value) |
+            Constant::Utf8Lossy(value, _) => Some(value.as_str()),
+            _ => None
+        }
+    }
+
+    /// Validates a NameAndType referenced by another constant returning its
+    /// name and descriptor. The descriptor kind depends on the referencing
+    /// constant so is checked by the caller
+    fn name_and_type(&mut self, field: &'static str, target: PoolIndex) -> Option<(&'a str, &'a str)> {
+        match self.reference(field, target, "NameAndType", &[ConstantTag::NameAndType])? {
+            Constant::NameAndType(value) => {
+                let pool = self.pool;
+                let name = pool.get_utf8(&value.name_index).ok()?;
+                let descriptor = pool.get_utf8(&value.descriptor_index).ok()?;
+                Some((name.as_str(), descriptor.as_str()))
+            }
+            _ => None
+        }
+    }
+
+    fn field_descriptor(&mut self, value: &str) {
+        if !is_field_descriptor(value) {
+            self.report(ConstantRule::ExpectedFieldDescriptor(value.to_string()))
+        }
+    }
+
+    fn method_descriptor(&mut self, name: &str, value: &str) {
+        if !is_method_descriptor(value) {
+            self.report(ConstantRule::ExpectedMethodDescriptor(value.to_string()))
+        } else if name == "<init>" && !value.ends_with(")V") {
+            self.report(ConstantRule::InitNotVoid(value.to_string()))
+        }
+    }
+
+    fn validate(&mut self, constant: &'a Constant) {
+        match constant {
+            // Unpaired surrogates can't be decoded into a string but are allowed by the JVM
+            Constant::Utf8Lossy(_, bytes) => if mutf8::validate(bytes).is_err() {
+                self.report(ConstantRule::InvalidUtf8)
+            },
+            Constant::Utf8(_) |
+            Constant::Integer(_) |
+            Constant::Float(_) |
+            Constant::Long(_) |
+            Constant::Double(_) => {}
+            Constant::Class(name_index) => {
+                if let Some(name) = self.utf8("name_index", *name_index) {
+                    if !is_class_name(name) {
+                        self.report(ConstantRule::InvalidClassName(name.to_string()))
+                    }
+                }
+            }
+            Constant::String(string_index) => { self.utf8("string_index", *string_index); }
+            Constant::MethodType(descriptor_index) => {
+                if let Some(descriptor) = self.utf8("descriptor_index", *descriptor_index) {
+                    self.method_descriptor("", descriptor)
+                }
+            }
+            Constant::Module(name_index) |
+            Constant::Package(name_index) => { self.utf8("name_index", *name_index); }
+            Constant::NameAndType(value) => {
+                let name = self.utf8("name_index", value.name_index);
+                let descriptor = self.utf8("descriptor_index", value.descriptor_index);
+                if let (Some(name), Some(descriptor)) = (name, descriptor) {
+                    let method = descriptor.starts_with('(');
+                    if !is_unqualified_name(name, method) {
+                        self.report(ConstantRule::InvalidMemberName(name.to_string()))
+                    }
+                    if method {
+                        self.method_descriptor(name, descriptor)
+                    } else {
+                        self.field_descriptor(descriptor)
+                    }
+                }
+            }
+            Constant::FieldRef(value) |
+            Constant::MethodRef(value) |
+            Constant::InterfaceMethodRef(value) => {
+                self.reference("class_index", value.class_index, "Class", &[ConstantTag::Class]);
+                if let Some((name, descriptor)) = self.name_and_type("name_and_type_index", value.name_and_type_info) {
+                    // The descriptor itself is checked by the NameAndType so
+                    // only the kind of descriptor is checked here
+                    if let Constant::FieldRef(_) = constant {
+                        if descriptor.starts_with('(') {
+                            self.report(ConstantRule::ExpectedFieldDescriptor(descriptor.to_string()))
+                        }
+                    } else {
+                        if name == "<clinit>" {
+                            self.report(ConstantRule::InvalidMemberName(name.to_string()))
+                        }
+                        if !descriptor.starts_with('(') {
+                            self.report(ConstantRule::ExpectedMethodDescriptor(descriptor.to_string()))
+                        }
+                    }
+                }
+            }
+            Constant::MethodHandle(value) => {
+                let kind = match ReferenceKind::try_from(value.reference_kind) {
+                    Ok(kind) => kind,
+                    Err(_) => return self.report(ConstantRule::InvalidReferenceKind(value.reference_kind))
+                };
+                let (expected, tags): (&'static str, &[ConstantTag]) = match kind {
+                    _ if kind.is_field() => ("Fieldref", &[ConstantTag::FieldRef]),
+                    ReferenceKind::InvokeVirtual |
+                    ReferenceKind::NewInvokeSpecial => ("Methodref", &[ConstantTag::MethodRef]),
+                    ReferenceKind::InvokeStatic |
+                    ReferenceKind::InvokeSpecial => (
+                        "Methodref or InterfaceMethodref",
+                        &[ConstantTag::MethodRef, ConstantTag::InterfaceMethodRef]
+                    ),
+                    _ => ("InterfaceMethodref", &[ConstantTag::InterfaceMethodRef]),
+                };
+                let reference = self.reference("reference_index", value.reference_index, expected, tags);
+                if let Some(Constant::FieldRef(member) | Constant::MethodRef(member) | Constant::InterfaceMethodRef(member)) = reference {
+                    if kind.is_field() {
+                        return;
+                    }
+                    let name = self.pool.inner.get(&member.name_and_type_info)
+                        .and_then(|value| match value {
+                            Constant::NameAndType(value) => self.pool.get_utf8(&value.name_index).ok(),
+                            _ => None
+                        });
+                    if let Some(name) = name {
+                        // Only constructor handles can reference <init> and no handle can reference <clinit>
+                        let valid = match kind {
+                            ReferenceKind::NewInvokeSpecial => name == "<init>",
+                            _ => name != "<init>" && name != "<clinit>"
+                        };
+                        if !valid {
+                            self.report(ConstantRule::InvalidHandleMember { kind, name: name.clone() })
+                        }
+                    }
+                }
+            }
+            Constant::Dynamic(value) => {
+                if let Some((_, descriptor)) = self.name_and_type("name_and_type_index", value.name_and_type_index) {
+                    if descriptor.starts_with('(') {
+                        self.report(ConstantRule::ExpectedFieldDescriptor(descriptor.to_string()))
+                    }
+                }
+            }
+            Constant::InvokeDynamic(value) => {
+                if let Some((_, descriptor)) = self.name_and_type("name_and_type_index", value.name_and_type_index) {
+                    if !descriptor.starts_with('(') {
+                        self.report(ConstantRule::ExpectedMethodDescriptor(descriptor.to_string()))
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl ConstantPool {
+    /// Checks every constant in the pool against the rules of JVMS 4.4
+    /// returning a diagnostic for each rule broken ordered by index
+    pub fn validate(&self) -> Vec<ConstantDiagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut indexes: Vec<&PoolIndex> = self.inner.keys().collect();
+        indexes.sort();
+        for index in indexes {
+            let constant = &self.inner[index];
+            Validator { pool: self, index: *index, diagnostics: &mut diagnostics }
+                .validate(constant);
+        }
+        diagnostics
+    }
+}
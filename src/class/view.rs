@@ -0,0 +1,425 @@
+//! Zero-copy views over class file bytes. Instead of copying utf8 constants
+//! and attribute bodies into owned values the views borrow slices of the
+//! input which makes scanning large numbers of classes much cheaper. Views
+//! can be converted into the owned model using `ClassView::to_class`
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::class::access::AccessFlags;
+use crate::class::attribute::{Attribute, AttributeValue, ExceptionTableEntry};
+use crate::class::class::{Class, CLASS_SIGNATURE, ClassPath, SourceVersion};
+use crate::class::constant::{Constant, ConstantPool, ConstantTag, PoolIndex};
+use crate::class::descriptor::Descriptor;
+use crate::class::member::Member;
+use crate::class::nesting::ClassNesting;
+use crate::error::{ConstantError, ReadError};
+use crate::io::{MAX_PREALLOCATE, ParseLimits, Readable, ReadResult, VecReadableFn, VecReadableSize};
+use crate::mutf8;
+
+/// Takes `length` bytes from the front of the input slice
+fn take<'a>(i: &mut &'a [u8], length: usize) -> ReadResult<&'a [u8]> {
+    if i.len() < length {
+        return Err(ReadError::IO(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    let (value, rest) = i.split_at(length);
+    *i = rest;
+    Ok(value)
+}
+
+#[derive(Debug, Clone)]
+pub enum ConstantView<'a> {
+    // Raw modified utf-8 bytes of a utf8 constant
+    Utf8(&'a [u8]),
+    // Any other constant, none of which require allocation
+    Other(Constant),
+}
+
+/// Borrowed version of the constant pool. Constants are stored in a vec
+/// indexed by their pool index rather than a map.
+#[derive(Debug, Clone)]
+pub struct ConstantPoolView<'a> {
+    pub inner: Vec<Option<ConstantView<'a>>>,
+}
+
+impl<'a> ConstantPoolView<'a> {
+    pub fn parse(i: &mut &'a [u8], limits: &ParseLimits) -> ReadResult<Self> {
+        let size = u16::read(i)?;
+        ParseLimits::check("constant pool size", size as u64, limits.max_constant_pool_size as u64)?;
+        // The size can't be trusted until the constants are read so the
+        // entries are added as they are read. Index 0 is always empty
+        let mut inner = Vec::with_capacity((size as usize).min(MAX_PREALLOCATE));
+        inner.push(None);
+        // Wider than a pool index so that a trailing Long or Double can't overflow
+        let mut index: u32 = 1;
+        while index < size as u32 {
+            let tag_raw = u8::read(i)
+                .map_err(|err| err.in_element(index as usize))?;
+            let tag = ConstantTag::try_from(tag_raw)
+                .map_err(|_| ReadError::UnknownConstantTag(tag_raw).in_element(index as usize))?;
+            let value = ConstantPoolView::parse_constant(tag, i)
+                .map_err(|err| err.in_element(index as usize))?;
+            inner.push(Some(value));
+            // Long and Double constants consume two indexes worth of data
+            index += match tag {
+                ConstantTag::Long | ConstantTag::Double => {
+                    inner.push(None);
+                    2
+                }
+                _ => 1
+            }
+        }
+        // A trailing Long or Double has no second index within the pool
+        inner.truncate(size as usize);
+        Ok(ConstantPoolView { inner })
+    }
+
+    fn parse_constant(tag: ConstantTag, i: &mut &'a [u8]) -> ReadResult<ConstantView<'a>> {
+        Ok(match tag {
+            ConstantTag::Utf8 => {
+                let length = u16::read(i)?;
+                ConstantView::Utf8(take(i, length as usize)?)
+            }
+            _ => ConstantView::Other(Constant::read_with_tag(tag, i)?),
+        })
+    }
+
+    pub fn get(&self, index: &PoolIndex) -> Option<&ConstantView<'a>> {
+        self.inner.get(*index as usize)
+            .and_then(|value| value.as_ref())
+    }
+
+    /// Obtains the raw modified utf-8 bytes of the utf8 constant at the provided index
+    pub fn get_utf8_bytes(&self, index: &PoolIndex) -> Result<&'a [u8], ConstantError> {
+        match self.get(index) {
+            Some(ConstantView::Utf8(value)) => Ok(value),
+            Some(_) => Err(ConstantError::ExpectedUtf8(*index)),
+            None => Err(ConstantError::NotFound(*index))
+        }
+    }
+
+    /// Obtains the utf8 constant at the provided index. The value is only
+    /// copied when the bytes differ between modified utf-8 and utf-8
+    pub fn get_utf8(&self, index: &PoolIndex) -> Result<Cow<'a, str>, ConstantError> {
+        let bytes = self.get_utf8_bytes(index)?;
+        // Modified utf-8 is identical to utf-8 unless it contains an encoded
+        // null or surrogates, neither of which are valid utf-8
+        if let Ok(value) = std::str::from_utf8(bytes) {
+            if !bytes.iter().any(|b| *b == 0 || *b >= 0xF0) {
+                return Ok(Cow::Borrowed(value));
+            }
+        }
+        Ok(Cow::Owned(mutf8::decode_lossy(bytes)))
+    }
+
+    /// Obtains the internal name of the class constant at the provided index
+    pub fn get_class_name(&self, index: &PoolIndex) -> Result<Cow<'a, str>, ConstantError> {
+        match self.get(index) {
+            Some(ConstantView::Other(Constant::Class(name_index))) => self.get_utf8(name_index)
+                .map_err(|_| ConstantError::InvalidClassReference(*index)),
+            _ => Err(ConstantError::InvalidClassReference(*index))
+        }
+    }
+
+    /// Converts this view into an owned constant pool
+    pub fn to_pool(&self) -> ConstantPool {
+        let mut inner = HashMap::with_capacity(self.inner.len());
+        for (index, value) in self.inner.iter().enumerate() {
+            let value = match value {
+                Some(ConstantView::Utf8(bytes)) => Constant::from_utf8_bytes(bytes.to_vec()),
+                Some(ConstantView::Other(value)) => value.clone(),
+                None => continue,
+            };
+            inner.insert(index as PoolIndex, value);
+        }
+        ConstantPool { inner }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AttributeView<'a> {
+    pub name_index: PoolIndex,
+    pub data: &'a [u8],
+}
+
+impl<'a> AttributeView<'a> {
+    pub fn parse(i: &mut &'a [u8], limits: &ParseLimits) -> ReadResult<Self> {
+        let name_index = PoolIndex::read(i)?;
+        let length = u32::read(i)?;
+        ParseLimits::check("attribute length", length as u64, limits.max_attribute_length as u64)?;
+        let data = take(i, length as usize)?;
+        Ok(AttributeView { name_index, data })
+    }
+
+    pub fn name(&self, constant_pool: &ConstantPoolView<'a>) -> Result<Cow<'a, str>, ConstantError> {
+        constant_pool.get_utf8(&self.name_index)
+    }
+
+    /// Decodes this attribute into the owned attribute model. The offsets
+    /// of any errors are relative to the end of the attribute data
+    pub fn to_attribute(&self, constant_pool: &ConstantPool, limits: &ParseLimits) -> ReadResult<Attribute> {
+        let name = constant_pool.get_utf8(&self.name_index)
+            .map_err(|err| ReadError::from(err).in_field("attribute_name_index"))?
+            .clone();
+        let value = AttributeValue::from_name(&name, self.data, constant_pool, limits)
+            .map_err(|err| err.in_key(name.as_str()))?;
+        Ok(Attribute { name_index: self.name_index, name, value })
+    }
+}
+
+/// Borrowed version of the Code attribute where the bytecode
+/// is a slice into the class file
+#[derive(Debug, Clone)]
+pub struct CodeView<'a> {
+    pub max_stack: u16,
+    pub max_locals: u16,
+    pub code: &'a [u8],
+    pub exception_table: Vec<ExceptionTableEntry>,
+    pub attributes: Vec<AttributeView<'a>>,
+}
+
+impl<'a> CodeView<'a> {
+    /// Parses the contents of a Code attribute. The offsets of any
+    /// errors are relative to the start of the attribute data
+    pub fn parse(data: &'a [u8], limits: &ParseLimits) -> ReadResult<Self> {
+        let i = &mut &data[..];
+        CodeView::parse_inner(i, limits)
+            .map_err(|err| err.at_offset((data.len() - i.len()) as u64))
+    }
+
+    fn parse_inner(i: &mut &'a [u8], limits: &ParseLimits) -> ReadResult<Self> {
+        let max_stack = u16::read(i)?;
+        let max_locals = u16::read(i)?;
+        let code_length = u32::read(i)?;
+        ParseLimits::check("code length", code_length as u64, limits.max_code_length as u64)
+            .map_err(|err| err.in_field("code_length"))?;
+        let code = take(i, code_length as usize)
+            .map_err(|err| err.in_field("code"))?;
+        let exception_table = u16::read_vec(i)
+            .map_err(|err| err.in_field("exception_table"))?;
+        let nested_limits = limits.nested()?;
+        let attributes = u16::read_vec_closure(i, |r| AttributeView::parse(r, &nested_limits))
+            .map_err(|err| err.in_field("attributes"))?;
+        Ok(CodeView { max_stack, max_locals, code, exception_table, attributes })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MemberView<'a> {
+    pub access_flags: AccessFlags,
+    pub name_index: PoolIndex,
+    pub descriptor_index: PoolIndex,
+    pub attributes: Vec<AttributeView<'a>>,
+}
+
+impl<'a> MemberView<'a> {
+    pub fn parse(i: &mut &'a [u8], limits: &ParseLimits) -> ReadResult<Self> {
+        Ok(MemberView {
+            access_flags: AccessFlags::read(i)?,
+            name_index: PoolIndex::read(i)?,
+            descriptor_index: PoolIndex::read(i)?,
+            attributes: u16::read_vec_closure(i, |r| AttributeView::parse(r, limits))
+                .map_err(|err| err.in_field("attributes"))?,
+        })
+    }
+
+    pub fn name(&self, constant_pool: &ConstantPoolView<'a>) -> Result<Cow<'a, str>, ConstantError> {
+        constant_pool.get_utf8(&self.name_index)
+    }
+
+    pub fn descriptor(&self, constant_pool: &ConstantPoolView<'a>) -> Result<Cow<'a, str>, ConstantError> {
+        constant_pool.get_utf8(&self.descriptor_index)
+    }
+
+    /// Finds the first attribute with the provided name
+    pub fn attribute(&self, constant_pool: &ConstantPoolView<'a>, name: &str) -> Option<&AttributeView<'a>> {
+        self.attributes.iter()
+            .find(|attribute| constant_pool.get_utf8_bytes(&attribute.name_index)
+                .map(|value| value == name.as_bytes())
+                .unwrap_or(false))
+    }
+
+    /// Parses the Code attribute of this member if it has one
+    pub fn code(&self, constant_pool: &ConstantPoolView<'a>, limits: &ParseLimits) -> Option<ReadResult<CodeView<'a>>> {
+        self.attribute(constant_pool, "Code")
+            .map(|attribute| CodeView::parse(attribute.data, limits))
+    }
+
+    /// Converts this view into an owned member
+    pub fn to_member(&self, constant_pool: &ConstantPool, limits: &ParseLimits) -> ReadResult<Member> {
+        self.to_member_with(constant_pool, |attribute| attribute.to_attribute(constant_pool, limits))
+    }
+
+    fn to_member_with<F: Fn(&AttributeView<'a>) -> ReadResult<Attribute>>(
+        &self,
+        constant_pool: &ConstantPool,
+        to_attribute: F,
+    ) -> ReadResult<Member> {
+        let name = constant_pool.get_utf8(&self.name_index)
+            .map_err(|err| ReadError::from(err).in_field("name_index"))?
+            .clone();
+        let descriptor = constant_pool.get_utf8(&self.descriptor_index)
+            .map_err(|err| ReadError::from(err).in_field("descriptor_index"))?;
+        let descriptor = Descriptor::parse(descriptor);
+        let attributes = self.attributes.iter()
+            .enumerate()
+            .map(|(index, attribute)| to_attribute(attribute).map_err(|err| err.in_element(index)))
+            .collect::<ReadResult<Vec<Attribute>>>()
+            .map_err(|err| err.in_field("attributes"))?;
+        Ok(Member {
+            access_flags: self.access_flags,
+            name_index: self.name_index,
+            name,
+            descriptor_index: self.descriptor_index,
+            descriptor,
+            attributes,
+        })
+    }
+}
+
+/// Borrowed view of a class file. Class references are kept as pool
+/// indexes and can be resolved through the `constant_pool`
+#[derive(Debug)]
+pub struct ClassView<'a> {
+    pub version: SourceVersion,
+    pub constant_pool: ConstantPoolView<'a>,
+    pub access_flags: AccessFlags,
+    pub this_class: PoolIndex,
+    pub super_class: PoolIndex,
+    pub interfaces: Vec<PoolIndex>,
+    pub fields: Vec<MemberView<'a>>,
+    pub methods: Vec<MemberView<'a>>,
+    pub attributes: Vec<AttributeView<'a>>,
+    // The limits the view was parsed with, also used when decoding attributes
+    pub limits: ParseLimits,
+    // The class file bytes the view borrows from
+    pub data: &'a [u8],
+}
+
+impl<'a> ClassView<'a> {
+    pub fn parse(data: &'a [u8]) -> ReadResult<Self> {
+        ClassView::parse_with_limits(data, ParseLimits::default())
+    }
+
+    /// Parses a class view while enforcing the provided parse limits
+    pub fn parse_with_limits(data: &'a [u8], limits: ParseLimits) -> ReadResult<Self> {
+        let i = &mut &data[..];
+        ClassView::parse_inner(data, i, limits)
+            .map_err(|err| err.at_offset((data.len() - i.len()) as u64))
+    }
+
+    fn parse_inner(data: &'a [u8], i: &mut &'a [u8], limits: ParseLimits) -> ReadResult<Self> {
+        let magic_number = u32::read(i)?;
+        if magic_number != CLASS_SIGNATURE {
+            Err(ReadError::InvalidMagic(magic_number))?;
+        }
+        let version = SourceVersion::read(i)?;
+        let constant_pool = ConstantPoolView::parse(i, &limits)
+            .map_err(|err| err.in_field("constant_pool"))?;
+        let access_flags = AccessFlags::read(i)?;
+        let this_class = PoolIndex::read(i)?;
+        let super_class = PoolIndex::read(i)?;
+        let interfaces = u16::read_vec(i)
+            .map_err(|err| err.in_field("interfaces"))?;
+        let fields = u16::read_vec_closure(i, |r| MemberView::parse(r, &limits))
+            .map_err(|err| err.in_field("fields"))?;
+        let methods = u16::read_vec_closure(i, |r| MemberView::parse(r, &limits))
+            .map_err(|err| err.in_field("methods"))?;
+        let attributes = u16::read_vec_closure(i, |r| AttributeView::parse(r, &limits))
+            .map_err(|err| err.in_field("attributes"))?;
+        Ok(ClassView {
+            version,
+            constant_pool,
+            access_flags,
+            this_class,
+            super_class,
+            interfaces,
+            fields,
+            methods,
+            attributes,
+            limits,
+            data,
+        })
+    }
+
+    /// The internal name of this class (e.g. java/lang/Object)
+    pub fn class_name(&self) -> Result<Cow<'a, str>, ConstantError> {
+        self.constant_pool.get_class_name(&self.this_class)
+    }
+
+    /// The internal name of the super class if this class has one
+    pub fn super_class_name(&self) -> Result<Option<Cow<'a, str>>, ConstantError> {
+        if self.super_class == 0 {
+            return Ok(None);
+        }
+        self.constant_pool.get_class_name(&self.super_class).map(Some)
+    }
+
+    /// Converts an attribute of this class into the owned model resolving
+    /// the offset of any errors to their position in the class file
+    fn to_attribute(&self, attribute: &AttributeView<'a>, constant_pool: &ConstantPool) -> ReadResult<Attribute> {
+        // The attribute data is normally a sub slice of the class data but
+        // views can be constructed by hand so this isn't guaranteed
+        let start = (attribute.data.as_ptr() as usize)
+            .checked_sub(self.data.as_ptr() as usize)
+            .filter(|start| *start <= self.data.len());
+        attribute.to_attribute(constant_pool, &self.limits)
+            .map_err(|err| match start {
+                Some(start) => err.at_offset((start + attribute.data.len()) as u64),
+                None => err
+            })
+    }
+
+    fn to_members(&self, members: &[MemberView<'a>], constant_pool: &ConstantPool) -> ReadResult<Vec<Member>> {
+        members.iter()
+            .enumerate()
+            .map(|(index, member)| member
+                .to_member_with(constant_pool, |attribute| self.to_attribute(attribute, constant_pool))
+                .map_err(|err| err.in_element(index)))
+            .collect()
+    }
+
+    /// Converts this view into the owned class model decoding
+    /// all of the attributes in the process
+    pub fn to_class(&self) -> ReadResult<Class> {
+        let constant_pool = self.constant_pool.to_pool();
+        let class_path = constant_pool.get_class_path(&self.this_class)
+            .map_err(ReadError::from)
+            .and_then(|value| value.ok_or(ReadError::NoClassName))
+            .map_err(|err| err.in_field("this_class"))?;
+        let super_class_path = constant_pool.get_class_path(&self.super_class)
+            .map_err(|err| ReadError::from(err).in_field("super_class"))?;
+        let interfaces = self.interfaces.iter()
+            .enumerate()
+            .map(|(index, value)| constant_pool.get_class_path_required(value)
+                .map_err(|err| ReadError::from(err).in_element(index)))
+            .collect::<ReadResult<Vec<ClassPath>>>()
+            .map_err(|err| err.in_field("interfaces"))?;
+        let fields = self.to_members(&self.fields, &constant_pool)
+            .map_err(|err| err.in_field("fields"))?;
+        let methods = self.to_members(&self.methods, &constant_pool)
+            .map_err(|err| err.in_field("methods"))?;
+        let attributes = self.attributes.iter()
+            .enumerate()
+            .map(|(index, attribute)| self.to_attribute(attribute, &constant_pool)
+                .map_err(|err| err.in_element(index)))
+            .collect::<ReadResult<Vec<Attribute>>>()
+            .map_err(|err| err.in_field("attributes"))?;
+        let mut class = Class {
+            version: self.version,
+            constant_pool,
+            access_flags: self.access_flags,
+            this_class: self.this_class,
+            class_path,
+            super_class: self.super_class,
+            super_class_path,
+            interface_indexes: self.interfaces.clone(),
+            interfaces,
+            fields,
+            methods,
+            attributes,
+            nesting: ClassNesting::default(),
+        };
+        class.finish();
+        Ok(class)
+    }
+}
@@ -0,0 +1,488 @@
+//! Index of everything in a class that references constant pool entries.
+//! This answers which constants, members, attributes and instructions use
+//! a constant and which constants aren't used by anything at all
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use crate::class::annotation::{Annotation, ElementValue};
+use crate::class::attribute::{Attribute, AttributeValue};
+use crate::class::class::{Class, ClassPath};
+use crate::class::constant::{Constant, ConstantPool, PoolIndex};
+use crate::class::frame::VerificationType;
+use crate::class::member::Member;
+use crate::class::op::parse_code;
+use crate::error::DecompileError;
+
+/// What owns an attribute that references the pool
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum AttributeOwner {
+    Class,
+    Field(usize),
+    Method(usize),
+    // Attributes nested inside the Code attribute of a method
+    Code(usize),
+    // Attributes of a component in the Record attribute of the class
+    RecordComponent(usize),
+}
+
+/// Something that references a constant pool entry. Fields are named
+/// after the items of the class file structures in JVMS 4
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ReferenceSource {
+    // Another constant in the pool (e.g. the name_index of a Class)
+    Constant { index: PoolIndex, field: &'static str },
+    // this_class or super_class
+    Class { field: &'static str },
+    Interface(usize),
+    Field { index: usize, field: &'static str },
+    Method { index: usize, field: &'static str },
+    // A field of an attribute. Attributes with tables of entries include
+    // the index of the entry in `element`
+    Attribute {
+        owner: AttributeOwner,
+        name: String,
+        field: &'static str,
+        element: Option<usize>,
+    },
+    // An instruction in the code of a method at the byte offset
+    Instruction { method: usize, offset: u64 },
+    // A static argument of a bootstrap method
+    BootstrapArgument { bootstrap_method: usize, argument: usize },
+}
+
+impl Display for AttributeOwner {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttributeOwner::Class => f.write_str("class"),
+            AttributeOwner::Field(index) => write!(f, "fields[{}]", index),
+            AttributeOwner::Method(index) => write!(f, "methods[{}]", index),
+            AttributeOwner::Code(index) => write!(f, "methods[{}].attributes[\"Code\"]", index),
+            AttributeOwner::RecordComponent(index) => write!(f, "class.attributes[\"Record\"].components[{}]", index),
+        }
+    }
+}
+
+impl Display for ReferenceSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReferenceSource::Constant { index, field } => write!(f, "#{}.{}", index, field),
+            ReferenceSource::Class { field } => f.write_str(field),
+            ReferenceSource::Interface(index) => write!(f, "interfaces[{}]", index),
+            ReferenceSource::Field { index, field } => write!(f, "fields[{}].{}", index, field),
+            ReferenceSource::Method { index, field } => write!(f, "methods[{}].{}", index, field),
+            ReferenceSource::Attribute { owner, name, field, element } => {
+                write!(f, "{}.attributes[{:?}]", owner, name)?;
+                if let Some(element) = element {
+                    write!(f, "[{}]", element)?;
+                }
+                write!(f, ".{}", field)
+            }
+            ReferenceSource::Instruction { method, offset } =>
+                write!(f, "methods[{}].attributes[\"Code\"].code[{}]", method, offset),
+            ReferenceSource::BootstrapArgument { bootstrap_method, argument } =>
+                write!(f, "attributes[\"BootstrapMethods\"][{}].bootstrap_arguments[{}]", bootstrap_method, argument),
+        }
+    }
+}
+
+/// The pool indexes referenced by a constant along with the name
+/// of the field they are referenced from
+pub fn constant_references(constant: &Constant) -> Vec<(&'static str, PoolIndex)> {
+    match constant {
+        Constant::Utf8(_) | Constant::Utf8Lossy(..) |
+        Constant::Integer(_) | Constant::Float(_) |
+        Constant::Long(_) | Constant::Double(_) => vec![],
+        Constant::Class(index) |
+        Constant::Module(index) |
+        Constant::Package(index) => vec![("name_index", *index)],
+        Constant::String(index) => vec![("string_index", *index)],
+        Constant::MethodType(index) => vec![("descriptor_index", *index)],
+        Constant::FieldRef(value) |
+        Constant::MethodRef(value) |
+        Constant::InterfaceMethodRef(value) => vec![
+            ("class_index", value.class_index),
+            ("name_and_type_index", value.name_and_type_info),
+        ],
+        Constant::NameAndType(value) => vec![
+            ("name_index", value.name_index),
+            ("descriptor_index", value.descriptor_index),
+        ],
+        Constant::MethodHandle(value) => vec![("reference_index", value.reference_index)],
+        // The bootstrap method index points into the BootstrapMethods
+        // attribute rather than the pool
+        Constant::Dynamic(value) |
+        Constant::InvokeDynamic(value) => vec![("name_and_type_index", value.name_and_type_index)],
+    }
+}
+
+/// Cross-reference index over a class. Names and descriptors are keyed by
+/// the index they were read from, the same index the writer uses. Attributes
+/// that weren't decoded or whose contents aren't understood are only recorded
+/// as referencing their name, see `is_complete`
+#[derive(Debug, Default)]
+pub struct CrossReferences {
+    references: HashMap<PoolIndex, Vec<ReferenceSource>>,
+    // Every index present in the pool in ascending order
+    indexes: Vec<PoolIndex>,
+    complete: bool,
+    // The methods whose code couldn't be parsed along with the error
+    code_errors: Vec<(usize, DecompileError)>,
+}
+
+struct Indexer<'a> {
+    pool: &'a ConstantPool,
+    references: HashMap<PoolIndex, Vec<ReferenceSource>>,
+    complete: bool,
+    code_errors: Vec<(usize, DecompileError)>,
+}
+
+impl<'a> Indexer<'a> {
+    fn add(&mut self, index: PoolIndex, source: ReferenceSource) {
+        self.references.entry(index).or_default().push(source);
+    }
+
+    /// Adds the utf8 constant the writer would use for the value read from `index`
+    fn add_utf8(&mut self, index: PoolIndex, value: &str, source: ReferenceSource) {
+        if let Ok(index) = self.pool.utf8_index(index, value) {
+            self.add(index, source);
+        }
+    }
+
+    /// Adds the class constant the writer would use for the class read from `index`
+    fn add_class_index(&mut self, index: PoolIndex, class: &ClassPath, source: ReferenceSource) {
+        if let Ok(index) = self.pool.class_index(index, class) {
+            self.add(index, source);
+        }
+    }
+
+    fn add_member(&mut self, member: &Member, source: impl Fn(&'static str) -> ReferenceSource) {
+        self.add_utf8(member.name_index, &member.name, source("name_index"));
+        self.add_utf8(member.descriptor_index, &member.descriptor.to_internal_java(), source("descriptor_index"));
+    }
+
+    fn add_annotation(&mut self, annotation: &Annotation, source: &dyn Fn(&'static str) -> ReferenceSource) {
+        self.add(annotation.type_index, source("type_index"));
+        for (name_index, value) in &annotation.elements {
+            self.add(*name_index, source("element_name_index"));
+            self.add_element_value(value, source);
+        }
+    }
+
+    fn add_element_value(&mut self, value: &ElementValue, source: &dyn Fn(&'static str) -> ReferenceSource) {
+        match value {
+            ElementValue::Const { const_value_index, .. } => self.add(*const_value_index, source("const_value_index")),
+            ElementValue::Enum { type_name_index, const_name_index } => {
+                self.add(*type_name_index, source("type_name_index"));
+                self.add(*const_name_index, source("const_name_index"));
+            }
+            ElementValue::Class(index) => self.add(*index, source("class_info_index")),
+            ElementValue::Annotation(annotation) => self.add_annotation(annotation, source),
+            ElementValue::Array(values) => {
+                for value in values {
+                    self.add_element_value(value, source);
+                }
+            }
+        }
+    }
+
+    fn add_attributes(&mut self, attributes: &[Attribute], owner: AttributeOwner) {
+        for attribute in attributes {
+            self.add_attribute(attribute, owner);
+        }
+    }
+
+    fn add_attribute(&mut self, attribute: &Attribute, owner: AttributeOwner) {
+        let name = attribute.name.as_str();
+        let source = |field: &'static str, element: Option<usize>| ReferenceSource::Attribute {
+            owner,
+            name: name.to_string(),
+            field,
+            element,
+        };
+        self.add_utf8(attribute.name_index, name, source("attribute_name_index", None));
+        match &attribute.value {
+            AttributeValue::ConstantValue(index) => self.add(*index, source("constantvalue_index", None)),
+            AttributeValue::SourceFile(index) => self.add(*index, source("sourcefile_index", None)),
+            AttributeValue::ModuleMainClass(index) => self.add(*index, source("main_class_index", None)),
+            AttributeValue::NestHost(index) => self.add(*index, source("host_class_index", None)),
+            AttributeValue::NestMembers(values) |
+            AttributeValue::PermittedSubclasses(values) => {
+                for (element, index) in values.iter().enumerate() {
+                    self.add(*index, source("classes", Some(element)));
+                }
+            }
+            AttributeValue::Record(components) => {
+                for (element, component) in components.iter().enumerate() {
+                    self.add_utf8(component.name_index, &component.name, source("name_index", Some(element)));
+                    let descriptor = component.descriptor.to_internal_java();
+                    self.add_utf8(component.descriptor_index, &descriptor, source("descriptor_index", Some(element)));
+                    self.add_attributes(&component.attributes, AttributeOwner::RecordComponent(element));
+                }
+            }
+            AttributeValue::ModulePackages(values) => {
+                for (element, index) in values.iter().enumerate() {
+                    self.add(*index, source("package_index", Some(element)));
+                }
+            }
+            AttributeValue::Module(module) => {
+                self.add(module.module_name_index, source("module_name_index", None));
+                // Versions are optional so may be zero
+                if module.module_version_index != 0 {
+                    self.add(module.module_version_index, source("module_version_index", None));
+                }
+                // The element of directives with multiple modules or classes is the index of the directive
+                for (element, value) in module.requires.iter().enumerate() {
+                    self.add(value.requires_index, source("requires_index", Some(element)));
+                    if value.requires_version_index != 0 {
+                        self.add(value.requires_version_index, source("requires_version_index", Some(element)));
+                    }
+                }
+                for (element, value) in module.exports.iter().enumerate() {
+                    self.add(value.exports_index, source("exports_index", Some(element)));
+                    for index in &value.exports_to_index {
+                        self.add(*index, source("exports_to_index", Some(element)));
+                    }
+                }
+                for (element, value) in module.opens.iter().enumerate() {
+                    self.add(value.opens_index, source("opens_index", Some(element)));
+                    for index in &value.opens_to_index {
+                        self.add(*index, source("opens_to_index", Some(element)));
+                    }
+                }
+                for (element, index) in module.uses_index.iter().enumerate() {
+                    self.add(*index, source("uses_index", Some(element)));
+                }
+                for (element, value) in module.provides.iter().enumerate() {
+                    self.add(value.provides_index, source("provides_index", Some(element)));
+                    for index in &value.provides_with_index {
+                        self.add(*index, source("provides_with_index", Some(element)));
+                    }
+                }
+            }
+            AttributeValue::Signature(index, value) => self.add_utf8(*index, value, source("signature_index", None)),
+            AttributeValue::Exceptions(values) => {
+                for (element, index) in values.iter().enumerate() {
+                    self.add(*index, source("exception_index_table", Some(element)));
+                }
+            }
+            AttributeValue::InnerClasses(values) => {
+                for (element, value) in values.iter().enumerate() {
+                    self.add(value.inner_class_info_index, source("inner_class_info_index", Some(element)));
+                    // Zero is used when the class has no outer class or name
+                    if value.outer_class_info_index != 0 {
+                        self.add(value.outer_class_info_index, source("outer_class_info_index", Some(element)));
+                    }
+                    if value.inner_name_index != 0 {
+                        self.add(value.inner_name_index, source("inner_name_index", Some(element)));
+                    }
+                }
+            }
+            AttributeValue::EnclosingMethod(value) => {
+                self.add(value.class_index, source("class_index", None));
+                if value.method_index != 0 {
+                    self.add(value.method_index, source("method_index", None));
+                }
+            }
+            AttributeValue::LocalVariableTable(values) => {
+                for (element, value) in values.iter().enumerate() {
+                    self.add(value.name_index, source("name_index", Some(element)));
+                    self.add(value.descriptor_index, source("descriptor_index", Some(element)));
+                }
+            }
+            AttributeValue::LocalVariableTypeTable(values) => {
+                for (element, value) in values.iter().enumerate() {
+                    self.add(value.name_index, source("name_index", Some(element)));
+                    self.add(value.signature_index, source("signature_index", Some(element)));
+                }
+            }
+            AttributeValue::MethodParameters(values) => {
+                for (element, value) in values.iter().enumerate() {
+                    if value.name_index != 0 {
+                        self.add(value.name_index, source("name_index", Some(element)));
+                    }
+                }
+            }
+            AttributeValue::BootstrapMethods(values) => {
+                for (element, value) in values.iter().enumerate() {
+                    self.add(value.method_ref, source("bootstrap_method_ref", Some(element)));
+                    for (argument, index) in value.arguments.iter().enumerate() {
+                        self.add(*index, ReferenceSource::BootstrapArgument { bootstrap_method: element, argument });
+                    }
+                }
+            }
+            AttributeValue::RuntimeVisibleAnnotations(values) |
+            AttributeValue::RuntimeInvisibleAnnotations(values) => {
+                for (element, annotation) in values.iter().enumerate() {
+                    self.add_annotation(annotation, &|field| source(field, Some(element)));
+                }
+            }
+            // Parameter annotations are identified by the parameter they belong to
+            AttributeValue::RuntimeVisibleParameterAnnotations(values) |
+            AttributeValue::RuntimeInvisibleParameterAnnotations(values) => {
+                for (parameter, annotations) in values.iter().enumerate() {
+                    for annotation in annotations {
+                        self.add_annotation(annotation, &|field| source(field, Some(parameter)));
+                    }
+                }
+            }
+            AttributeValue::RuntimeVisibleTypeAnnotations(values) |
+            AttributeValue::RuntimeInvisibleTypeAnnotations(values) => {
+                for (element, value) in values.iter().enumerate() {
+                    self.add_annotation(&value.annotation, &|field| source(field, Some(element)));
+                }
+            }
+            AttributeValue::AnnotationDefault(value) => self.add_element_value(value, &|field| source(field, None)),
+            AttributeValue::Code(code) => {
+                let method = match owner {
+                    AttributeOwner::Method(index) | AttributeOwner::Code(index) => index,
+                    _ => 0,
+                };
+                for (element, entry) in code.exception_table.iter().enumerate() {
+                    // A catch type of zero catches everything (finally blocks)
+                    if entry.catch_type != 0 {
+                        self.add(entry.catch_type, source("catch_type", Some(element)));
+                    }
+                }
+                if !code.code.is_empty() {
+                    match parse_code(&code.code) {
+                        Ok(instructions) => for (offset, instr) in instructions {
+                            if let Some(index) = instr.pool_index() {
+                                self.add(index, ReferenceSource::Instruction { method, offset });
+                            }
+                        },
+                        // The instructions may reference constants which can't be seen
+                        Err(err) => {
+                            self.complete = false;
+                            self.code_errors.push((method, err));
+                        }
+                    }
+                }
+                self.add_attributes(&code.attributes, AttributeOwner::Code(method));
+            }
+            AttributeValue::StackMapTable(frames) => {
+                for (element, frame) in frames.iter().enumerate() {
+                    for value in frame.verification_types() {
+                        if let VerificationType::Object(index, class) = value {
+                            self.add_class_index(*index, class, source("cpool_index", Some(element)));
+                        }
+                    }
+                }
+            }
+            // The contents of these may reference constants which can't be seen
+            AttributeValue::Unknown(_) |
+            AttributeValue::Undecoded(_) => self.complete = false,
+            AttributeValue::Synthetic |
+            AttributeValue::Depreciated |
+            AttributeValue::LineNumberTable(_) |
+            AttributeValue::SourceDebugExtension(_) => {}
+        }
+    }
+}
+
+impl CrossReferences {
+    /// Builds the index over the constant pool, class structure, members,
+    /// their attributes and the instructions of every method. Methods whose
+    /// code can't be parsed are recorded in `code_errors` and the index is
+    /// marked as incomplete
+    pub fn build(class: &Class) -> CrossReferences {
+        let pool = &class.constant_pool;
+        let mut indexes: Vec<PoolIndex> = pool.inner.keys().copied().collect();
+        indexes.sort();
+
+        let mut indexer = Indexer {
+            pool,
+            references: HashMap::new(),
+            complete: true,
+            code_errors: Vec::new(),
+        };
+        for index in &indexes {
+            let constant = &pool.inner[index];
+            for (field, target) in constant_references(constant) {
+                indexer.add(target, ReferenceSource::Constant { index: *index, field });
+            }
+        }
+
+        indexer.add_class_index(class.this_class, &class.class_path, ReferenceSource::Class { field: "this_class" });
+        if let Some(super_class) = &class.super_class_path {
+            indexer.add_class_index(class.super_class, super_class, ReferenceSource::Class { field: "super_class" });
+        }
+        for (index, interface) in class.interfaces.iter().enumerate() {
+            let read_index = class.interface_indexes.get(index).copied().unwrap_or(0);
+            indexer.add_class_index(read_index, interface, ReferenceSource::Interface(index));
+        }
+        for (index, field) in class.fields.iter().enumerate() {
+            indexer.add_member(field, |name| ReferenceSource::Field { index, field: name });
+            indexer.add_attributes(&field.attributes, AttributeOwner::Field(index));
+        }
+        for (index, method) in class.methods.iter().enumerate() {
+            indexer.add_member(method, |name| ReferenceSource::Method { index, field: name });
+            indexer.add_attributes(&method.attributes, AttributeOwner::Method(index));
+        }
+        indexer.add_attributes(&class.attributes, AttributeOwner::Class);
+
+        CrossReferences {
+            references: indexer.references,
+            indexes,
+            complete: indexer.complete,
+            code_errors: indexer.code_errors,
+        }
+    }
+
+    /// Everything that references the constant at `index`
+    pub fn references_to(&self, index: PoolIndex) -> &[ReferenceSource] {
+        self.references.get(&index)
+            .map(|value| value.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Whether the contents of every attribute were indexed. When this is
+    /// false some references are missing so constants reported as unused
+    /// may still be referenced from an attribute that wasn't understood
+    pub fn is_complete(&self) -> bool { self.complete }
+
+    /// The methods whose code couldn't be parsed so their instructions
+    /// weren't indexed, along with the error for each
+    pub fn code_errors(&self) -> &[(usize, DecompileError)] { &self.code_errors }
+
+    pub fn is_used(&self, index: PoolIndex) -> bool {
+        self.references.contains_key(&index)
+    }
+
+    /// Constants that aren't referenced by anything
+    pub fn unreferenced(&self) -> Vec<PoolIndex> {
+        self.indexes.iter()
+            .copied()
+            .filter(|index| !self.is_used(*index))
+            .collect()
+    }
+
+    /// Constants that can't be reached from the class structure. Unlike
+    /// `unreferenced` this includes constants that are only referenced by
+    /// other unused constants, so all of these can be removed when
+    /// compacting the pool
+    pub fn unused(&self) -> Vec<PoolIndex> {
+        // Constants referencing each constant, inverted from the index
+        let mut edges: HashMap<PoolIndex, Vec<PoolIndex>> = HashMap::new();
+        let mut stack = Vec::new();
+        for (target, sources) in &self.references {
+            for source in sources {
+                match source {
+                    ReferenceSource::Constant { index, .. } => edges.entry(*index).or_default().push(*target),
+                    _ => stack.push(*target),
+                }
+            }
+        }
+        let mut reachable = HashSet::new();
+        while let Some(index) = stack.pop() {
+            if reachable.insert(index) {
+                if let Some(targets) = edges.get(&index) {
+                    stack.extend(targets.iter().copied());
+                }
+            }
+        }
+        self.indexes.iter()
+            .copied()
+            .filter(|index| !reachable.contains(index))
+            .collect()
+    }
+}
@@ -2,15 +2,15 @@ use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::io::Write;
 
-use crate::class::access::AccessFlag;
-use crate::class::attribute::CodeAttr;
-use crate::class::class::ClassPath;
-use crate::class::constant::{Constant, ConstantPool, MemberReference};
-use crate::class::descriptor::Descriptor;
+use crate::class::access::MethodAccessFlag;
+use crate::class::attribute::{BootstrapMethod, CodeAttr};
+use crate::class::class::{ClassPath, Feature, SourceVersion};
+use crate::class::constant::{ConstantPool, LoadableConstant, MemberReference, ReferenceKind, ResolvedBootstrapMethod, ResolvedDynamic, ResolvedMethodHandle};
+use crate::class::descriptor::{Descriptor, MethodDescriptor};
 use crate::class::member::Member;
 use crate::class::op::{ArrayType, BranchIndex, Instr, InstrSet};
 use crate::decomp::writer::WriteResult;
-use crate::error::{ConstantError, DecompileError, StackError};
+use crate::error::{DecompileError, StackError};
 
 #[derive(Debug, Clone)]
 pub enum VarType {
@@ -75,7 +75,7 @@ impl Stack {
 
     fn empty(&self) -> Result<(), StackError> {
         if !self.values.is_empty() {
-            Err(StackError::Remaining(self.values.len() as usize))
+            Err(StackError::Remaining(self.values.len()))
         } else {
             Ok(())
         }
@@ -119,7 +119,9 @@ pub struct Block {
 }
 
 impl Block {
-    pub fn decompile(&self, constant_pool: &ConstantPool) -> DecompileResult<ASTSet> {
+    /// Decompiles the instructions of the block. The version of the class
+    /// decides which language features the instructions can use
+    pub fn decompile(&self, constant_pool: &ConstantPool, bootstrap_methods: &[BootstrapMethod], version: SourceVersion) -> DecompileResult<ASTSet> {
         let mut statements = ASTSet::new();
         let mut stack = Stack::new();
 
@@ -171,16 +173,14 @@ impl Block {
                     }
                 }
                 Instr::InvokeDynamic(index) => {
-                    match constant_pool.inner.get(index) {
-                        None => Err(ConstantError::NotFound(*index))?,
-                        Some(value) => {
-                            match value {
-                                Constant::InvokeDynamic(_) => {}
-                                _ => Err(ConstantError::ExpectedInvokeDynamic(*index))?
-                            }
-                        }
+                    let dynamic = constant_pool.get_invoke_dynamic(index)?;
+                    let bootstrap = constant_pool.get_bootstrap_method(bootstrap_methods, dynamic.bootstrap_method_attr_index)?;
+                    // Pattern switches dispatch on the index returned by a SwitchBootstraps call site
+                    let is_pattern_switch = bootstrap.method_handle.reference.class.internal_path() == "java/lang/runtime/SwitchBootstraps";
+                    if is_pattern_switch && !version.supports(Feature::PatternSwitch) {
+                        Err(DecompileError::UnsupportedFeature(Feature::PatternSwitch, version.major()))?;
                     }
-                    unimplemented!("invoke dynamic not implemented yet")
+                    Err(DecompileError::Unimplemented("invokedynamic"))?;
                 }
                 Instr::Return => { statements.push(AST::VoidReturn); }
                 Instr::IStore(index) |
@@ -240,14 +240,22 @@ impl Block {
                     stack.push(AST::ArrayLength(reference));
                 }
                 Instr::LoadConst(index) => {
-                    stack.push(match constant_pool.inner.get(index)
-                        .ok_or(ConstantError::NotFound(*index))? {
-                        Constant::Integer(value) => AST::IntegerConstant(*value),
-                        Constant::Float(value) => AST::FloatConstant(*value),
-                        Constant::Long(value) => AST::LongConstant(*value),
-                        Constant::Double(value) => AST::DoubleConstant(*value),
-                        Constant::String(index) => AST::StringConst(constant_pool.get_utf8(index)?.clone()),
-                        _ => unimplemented!(),
+                    stack.push(match constant_pool.get_loadable(index)? {
+                        LoadableConstant::Integer(value) => AST::IntegerConstant(value),
+                        LoadableConstant::Float(value) => AST::FloatConstant(value),
+                        LoadableConstant::Long(value) => AST::LongConstant(value),
+                        LoadableConstant::Double(value) => AST::DoubleConstant(value),
+                        LoadableConstant::String(value) => AST::StringConst(value),
+                        LoadableConstant::Class(value) => AST::ClassConstant(value),
+                        LoadableConstant::MethodHandle(value) => AST::MethodHandleConstant(value),
+                        LoadableConstant::MethodType(value) => AST::MethodTypeConstant(value),
+                        LoadableConstant::Dynamic(constant) => {
+                            let bootstrap = constant_pool.get_bootstrap_method(
+                                bootstrap_methods,
+                                constant.bootstrap_method_attr_index,
+                            )?;
+                            AST::DynamicConstant { constant, bootstrap }
+                        }
                     });
                 }
                 Instr::CheckCast(index) => {
@@ -569,6 +577,13 @@ pub enum AST {
         offsets: Vec<u32>,
     },
     StringConst(String),
+    ClassConstant(Descriptor),
+    MethodHandleConstant(ResolvedMethodHandle),
+    MethodTypeConstant(MethodDescriptor),
+    DynamicConstant {
+        constant: ResolvedDynamic,
+        bootstrap: ResolvedBootstrapMethod,
+    },
     IntegerConstant(i32),
     FloatConstant(f32),
     LongConstant(i64),
@@ -605,23 +620,78 @@ pub enum AST {
     JSR(BranchIndex),
 }
 
+/// Writes the Java source form of a type (e.g. int[] or String)
+fn write_type<W: Write>(descriptor: &Descriptor, o: &mut W) -> WriteResult {
+    match descriptor {
+        Descriptor::Array(array) => {
+            write_type(&array.descriptor, o)?;
+            write!(o, "{}", "[]".repeat(array.dimensions as usize))?;
+        }
+        _ => write!(o, "{}", descriptor.to_java())?
+    }
+    Ok(())
+}
+
+/// Writes a loadable constant. Method handles, method types and dynamic
+/// constants have no literal syntax in Java so they are written using the
+/// closest equivalent
+fn write_loadable<W: Write>(constant: &LoadableConstant, o: &mut W) -> WriteResult {
+    match constant {
+        LoadableConstant::Integer(value) => write!(o, "{}", value)?,
+        LoadableConstant::Float(value) => write!(o, "{}F", value)?,
+        LoadableConstant::Long(value) => write!(o, "{}L", value)?,
+        LoadableConstant::Double(value) => write!(o, "{}D", value)?,
+        LoadableConstant::String(value) => write!(o, "\"{}\"", value)?,
+        LoadableConstant::Class(value) => {
+            write_type(value, o)?;
+            write!(o, ".class")?;
+        }
+        LoadableConstant::MethodHandle(handle) => {
+            let reference = &handle.reference;
+            let name = match handle.kind {
+                ReferenceKind::NewInvokeSpecial => "new",
+                _ => reference.name_and_type.name.as_str()
+            };
+            write!(o, "/* {} */ {}::{}", handle.kind.name(), reference.class.name, name)?;
+        }
+        LoadableConstant::MethodType(value) => {
+            write!(
+                o,
+                "java.lang.invoke.MethodType.fromMethodDescriptorString(\"{}\", null)",
+                Descriptor::Method(value.clone()).to_internal_java()
+            )?;
+        }
+        LoadableConstant::Dynamic(value) => {
+            write!(o, "/* dynamic constant #{} */ \"{}\"", value.bootstrap_method_attr_index, value.name_and_type.name)?;
+        }
+    }
+    Ok(())
+}
+
+/// The name of the local variable at the index. Parameters are named
+/// by their position to match the parameters of the method declaration
+fn local_name(member: &Member, index: u16) -> String {
+    let is_static = member.access_flags.is_set(MethodAccessFlag::Static);
+    if index == 0 && !is_static {
+        return String::from("this");
+    }
+    let parameter = match &member.descriptor {
+        Descriptor::Method(descriptor) => descriptor.parameter_at_slot(index, is_static),
+        _ => None
+    };
+    match parameter {
+        Some(parameter) => format!("p_{}", parameter),
+        None => format!("var{}", index),
+    }
+}
+
 impl AST {
+    #[allow(clippy::only_used_in_recursion)]
     pub fn write_java<W: Write>(&self, o: &mut W, member: &Member, code_attr: &CodeAttr) -> WriteResult {
-        let access = member.access_flags;
         match self {
-            AST::Variable(index, _) => {
-                if *index == 0 && !access.is_set(AccessFlag::Static) {
-                    write!(o, "this")?;
-                } else {
-                    write!(o, "var{}", index)?;
-                }
-            }
+            AST::Variable(index, _) => write!(o, "{}", local_name(member, *index))?,
             AST::Set(index, value) => {
-                if *index == 0 && !access.is_set(AccessFlag::Static) {
-                    write!(o, "this = ")?;
-                } else {
-                    write!(o, "var{} = ", index)?;
-                }
+                write!(o, "{} = ", local_name(member, *index))?;
                 value.write_java(o, member, code_attr)?;
                 write!(o, ";")?;
             }
@@ -781,6 +851,22 @@ impl AST {
                 write!(o, "))")?;
             }
             AST::StringConst(value) => { write!(o, "\"{}\"", value)?; }
+            AST::ClassConstant(value) => write_loadable(&LoadableConstant::Class(value.clone()), o)?,
+            AST::MethodHandleConstant(value) => write_loadable(&LoadableConstant::MethodHandle(value.clone()), o)?,
+            AST::MethodTypeConstant(value) => write_loadable(&LoadableConstant::MethodType(value.clone()), o)?,
+            AST::DynamicConstant { constant, bootstrap } => {
+                // Dynamic constants have no Java syntax so they are written as
+                // a call to their bootstrap method with the name and type
+                let handle = &bootstrap.method_handle.reference;
+                write!(o, "/* dynamic constant */ {}.{}(\"{}\", ", handle.class.name, handle.name_and_type.name, constant.name_and_type.name)?;
+                write_type(&constant.name_and_type.descriptor, o)?;
+                write!(o, ".class")?;
+                for argument in &bootstrap.arguments {
+                    write!(o, ", ")?;
+                    write_loadable(argument, o)?;
+                }
+                write!(o, ")")?;
+            }
             AST::IntegerConstant(value) |
             AST::Int(value) => { write!(o, "{}", value)?; }
             AST::Short(value) => { write!(o, "{}", value)?; }
@@ -847,7 +933,7 @@ pub fn split_at_multiple<T: Clone>(vec: Vec<T>, split_indices: Vec<usize>) -> Ve
     split_indices.dedup();
     let mut output = Vec::with_capacity(split_indices.len() + 1);
 
-    if split_indices.len() == 0 {
+    if split_indices.is_empty() {
         return vec![vec];
     } else {
         if split_indices[0] == 0 {
@@ -887,13 +973,13 @@ pub fn gen_control_flow_graph(instructions: &InstrSet) -> HashMap<u64, Block> {
             | Instr::IfICmpGe(branch)
             | Instr::IfICmpLt(branch)
             | Instr::IfICmpLe(branch) => {
-                let true_pos = get_index_for_pos(&instructions, *branch).unwrap();
+                let true_pos = get_index_for_pos(instructions, *branch).unwrap();
                 jump_indices.push(true_pos);
                 let false_pos = i + 1;
                 jump_indices.push(false_pos);
             }
             Instr::Goto(branch) => {
-                let jump_pos = get_index_for_pos(&instructions, *branch).unwrap();
+                let jump_pos = get_index_for_pos(instructions, *branch).unwrap();
                 jump_indices.push(jump_pos);
             }
             _ => {}
@@ -914,12 +1000,11 @@ pub fn gen_control_flow_graph(instructions: &InstrSet) -> HashMap<u64, Block> {
         })
         .collect();
 
-    for (_, block) in &mut blocks {
+    for block in blocks.values_mut() {
         let (last_pos, last_instr) = block.instructions.last().unwrap();
         let next = instructions
             .iter()
-            .skip_while(|el| el.0 <= *last_pos)
-            .next();
+            .find(|el| el.0 > *last_pos);
 
         match last_instr {
             Instr::IfNe(branch)
@@ -962,7 +1047,7 @@ pub fn find_paths(blocks: &HashMap<u64, Block>, node: u64, path_in: Vec<u64>) ->
     let mut path = path_in;
     path.push(node);
     let mut paths = Vec::new();
-    if block.branches.len() == 0 {
+    if block.branches.is_empty() {
         paths.push(start_vector);
     } else {
         for b in &block.branches {
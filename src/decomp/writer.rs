@@ -1,21 +1,33 @@
-use std::collections::HashMap;
 use std::io::Write;
+use std::sync::Arc;
 
-use crate::class::access::{AccessFlag, AccessFlags};
-use crate::class::attribute::{AttributeValue, CodeAttr};
+use crate::class::access::{ClassAccessFlag, FieldAccessFlag, MethodAccessFlag};
+use crate::class::annotation::{Annotation, annotations, ElementValue, SUPERCLASS_INDEX, TargetInfo, TargetType, type_annotations, TypeAnnotation};
+use crate::class::attribute::{Attribute, AttributeValue, CodeAttr};
 use crate::class::class::Class;
+use crate::class::constant::{Constant, ConstantPool};
 use crate::class::descriptor::Descriptor;
 use crate::class::member::Member;
-use crate::class::op::parse_code;
+use crate::class::module::Module;
+use crate::class::op::{Instr, parse_code};
+use crate::class::signature::{JavaNames, type_parameters_to_java, TypeSignature};
 use crate::decomp::ast::{AST, Block, find_paths, gen_control_flow_graph};
-use crate::error::WriteError;
+use crate::class::dump::{escape, java_float};
+use crate::error::{ConstantError, DecompileError, WriteError};
+use crate::provider::ClassProvider;
 
 pub struct JavaWriter;
 
 pub type WriteResult = Result<(), WriteError>;
 
 impl JavaWriter {
-    pub fn write_class<W: Write>(&self, class: &Class, o: &mut W) -> WriteResult {
+    /// Writes the class as Java source. The provider is used to look up
+    /// the super types of the class so methods which override one of
+    /// theirs are marked with @Override
+    pub fn write_class<W: Write>(&self, class: &Class, provider: &dyn ClassProvider, o: &mut W) -> WriteResult {
+        if let Some(module) = class.module().filter(|_| class.is_module()) {
+            return self.write_module(class, module, o);
+        }
         let class_path = &class.class_path;
         let package_str = class_path.package_str();
         if !package_str.is_empty() {
@@ -25,28 +37,84 @@ impl JavaWriter {
         let imports = class.collect_imports();
         if !imports.is_empty() {
             for import in imports {
-                write!(o, "import {};\n", import.full_path())?;
+                writeln!(o, "import {};", import.full_path())?;
             }
-            write!(o, "\n")?;
+            writeln!(o)?;
         }
 
+        self.write_annotations(class, &class.attributes, "", o)?;
         let access = class.access_flags;
-        self.write_access_psf(&access, o)?;
-        if access.is_set(AccessFlag::Enum) {
+        let is_record = class.is_record();
+        let mut modifiers = access;
+        // Interfaces are implicitly abstract while enums and records are
+        // implicitly final (or abstract for enums with constant bodies)
+        if access.is_set(ClassAccessFlag::Interface) {
+            modifiers.unset(ClassAccessFlag::Abstract);
+        }
+        if is_record || access.is_set(ClassAccessFlag::Enum) {
+            modifiers.unset(ClassAccessFlag::Final);
+            modifiers.unset(ClassAccessFlag::Abstract);
+        }
+        write!(o, "{}", modifiers.modifiers::<ClassAccessFlag>())?;
+        if class.is_sealed() {
+            write!(o, "sealed ")?;
+        }
+        if is_record {
+            write!(o, "record ")?;
+        } else if access.is_set(ClassAccessFlag::Enum) {
             write!(o, "enum ")?;
-        } else if access.is_set(AccessFlag::Interface) {
+        } else if access.is_set(ClassAccessFlag::Interface) {
             write!(o, "interface ")?;
-        } else if access.is_set(AccessFlag::Annotation) {
+        } else if access.is_set(ClassAccessFlag::Annotation) {
             write!(o, "@interface ")?;
         } else {
             write!(o, "class ")?;
         }
 
-        write!(o, "{} ", class_path.name)?;
+        // Invalid signatures are ignored in favour of the erased types
+        let signature = class.signature().ok().flatten();
+        write!(o, "{}", class_path.simple_name())?;
+        if let Some(signature) = &signature {
+            write!(o, "{}", type_parameters_to_java(&signature.type_parameters, JavaNames::Simple))?;
+        }
+        if let Some(components) = class.record_components().filter(|_| is_record) {
+            write!(o, "(")?;
+            for (i, component) in components.iter().enumerate() {
+                if i > 0 {
+                    write!(o, ", ")?;
+                }
+                for annotation in component.annotations() {
+                    self.write_annotation(&class.constant_pool, annotation, o)?;
+                    write!(o, " ")?;
+                }
+                match component.signature().ok().flatten() {
+                    Some(signature) => write!(o, "{}", signature.to_java(JavaNames::Simple))?,
+                    None => self.write_descriptor(&component.descriptor, o)?,
+                }
+                write!(o, " {}", component.name)?;
+            }
+            write!(o, ")")?;
+        }
+        write!(o, " ")?;
 
-        if let Some(x) = &class.super_class_path {
+        let pool = &class.constant_pool;
+        let supertype_annotations = |supertype: u16| -> Vec<&TypeAnnotation> {
+            type_annotations(&class.attributes)
+                .filter(|annotation| matches!(
+                    annotation.target_info,
+                    TargetInfo::Supertype { supertype_index } if supertype_index == supertype
+                ))
+                .collect()
+        };
+        if let Some(x) = class.super_class_path.as_ref().filter(|_| !is_record) {
             if !x.is_java_lang() || x.name != "Object" {
-                write!(o, "extends {} ", x.name)?;
+                write!(o, "extends ")?;
+                let annotations = supertype_annotations(SUPERCLASS_INDEX);
+                self.write_type_annotations(pool, &annotations, 0, &[], o)?;
+                match &signature {
+                    Some(signature) if annotations.is_empty() => write!(o, "{} ", signature.superclass.to_java(JavaNames::Simple))?,
+                    _ => write!(o, "{} ", x.simple_name())?,
+                }
             }
         }
 
@@ -54,26 +122,48 @@ impl JavaWriter {
             write!(o, "implements ")?;
             let last = class.interfaces.len() - 1;
             for (i, interface) in class.interfaces.iter().enumerate() {
-                write!(o, "{}", interface.full_path())?;
+                let annotations = supertype_annotations(i as u16);
+                self.write_type_annotations(pool, &annotations, 0, &[], o)?;
+                let generic = signature.as_ref()
+                    .and_then(|signature| signature.interfaces.get(i))
+                    .filter(|_| annotations.is_empty());
+                match generic {
+                    Some(generic) => write!(o, "{}", generic.to_java(JavaNames::Simple))?,
+                    None => write!(o, "{}", interface.full_path())?,
+                }
                 if i != last {
                     write!(o, ", ")?;
                 }
             }
-            write!(o, " {{\n")?;
-        } else {
-            write!(o, "{{\n")?;
+            write!(o, " ")?;
+        }
+
+        let permitted = class.permitted_subclasses().map_err(DecompileError::from)?;
+        if !permitted.is_empty() {
+            let permitted: Vec<String> = permitted.iter().map(|value| value.full_path()).collect();
+            write!(o, "permits {} ", permitted.join(", "))?;
         }
+        writeln!(o, "{{")?;
 
-        if !class.fields.is_empty() {
-            for field in class.fields.iter() {
-                self.write_field(field, o)?;
+        // The fields holding record components are declared by the record header
+        let fields: Vec<&Member> = class.fields.iter()
+            .filter(|field| !is_record || field.access_flags.is_set(FieldAccessFlag::Static)
+                || !class.record_components().unwrap_or(&[]).iter().any(|component| component.name == field.name))
+            .collect();
+        if !fields.is_empty() {
+            for field in fields {
+                self.write_field(class, field, o)?;
             }
-            write!(o, "\n")?;
+            writeln!(o)?;
         }
 
         if !class.methods.is_empty() {
+            let super_types = provider.super_types(class)?;
             for method in class.methods.iter() {
-                self.write_method(class, method, o)?;
+                if is_record && self.is_implicit_record_member(class, method) {
+                    continue;
+                }
+                self.write_method(class, &super_types, method, o)?;
             }
         }
 
@@ -81,6 +171,128 @@ impl JavaWriter {
         Ok(())
     }
 
+    /// Whether the method is one that the compiler generates for every
+    /// record. These are the component accessors which only return their
+    /// field and the toString, hashCode and equals methods which are
+    /// implemented using the ObjectMethods bootstrap method
+    fn is_implicit_record_member(&self, class: &Class, method: &Member) -> bool {
+        let pool = &class.constant_pool;
+        let code = method.attributes.iter()
+            .find_map(|attribute| match &attribute.value {
+                AttributeValue::Code(code) => Some(code),
+                _ => None
+            });
+        let instructions = match code.map(|code| parse_code(&code.code)) {
+            Some(Ok(instructions)) => instructions,
+            _ => return false,
+        };
+        let instructions: Vec<&Instr> = instructions.iter().map(|(_, instr)| instr).collect();
+        match instructions.as_slice() {
+            [Instr::ALoad(0), Instr::GetField(index), _] => {
+                let is_component = class.record_components().unwrap_or(&[]).iter()
+                    .any(|component| component.name == method.name);
+                is_component && pool.get_member_ref(index)
+                    .is_ok_and(|field| field.name_and_type.name == method.name)
+            }
+            _ => instructions.iter().any(|instr| match instr {
+                Instr::InvokeDynamic(index) => pool.get_invoke_dynamic(index)
+                    .and_then(|value| pool.get_bootstrap_method(class.bootstrap_methods(), value.bootstrap_method_attr_index))
+                    .is_ok_and(|value| value.method_handle.reference.class.internal_path() == "java/lang/runtime/ObjectMethods"),
+                _ => false
+            })
+        }
+    }
+
+    /// Writes the module declaration of a module-info class. Directives
+    /// of each kind are grouped together like they usually are in source
+    fn write_module<W: Write>(&self, class: &Class, module: &Module, o: &mut W) -> WriteResult {
+        let pool = &class.constant_pool;
+        self.write_annotations(class, &class.attributes, "", o)?;
+        if module.is_open() {
+            write!(o, "open ")?;
+        }
+        writeln!(o, "module {} {{", module.name(pool).map_err(DecompileError::from)?)?;
+
+        // Exports and opens directives name the package followed by the modules it is limited to
+        let package_directive = |keyword: &str, package: &String, to: Vec<&String>| -> String {
+            let mut directive = format!("{} {}", keyword, package.replace('/', "."));
+            if !to.is_empty() {
+                directive.push_str(" to ");
+                directive.push_str(&to.iter().map(|value| value.as_str()).collect::<Vec<_>>().join(", "));
+            }
+            directive
+        };
+        let mut groups: Vec<Vec<String>> = Vec::new();
+        let mut requires = Vec::new();
+        for value in &module.requires {
+            // Mandated modules (java.base) are required implicitly
+            if value.is_mandated() {
+                continue;
+            }
+            let mut directive = String::from("requires ");
+            if value.is_transitive() {
+                directive.push_str("transitive ");
+            }
+            if value.is_static_phase() {
+                directive.push_str("static ");
+            }
+            directive.push_str(value.module(pool).map_err(DecompileError::from)?);
+            requires.push(directive);
+        }
+        groups.push(requires);
+
+        let mut exports = Vec::new();
+        for value in &module.exports {
+            exports.push(package_directive(
+                "exports",
+                value.package(pool).map_err(DecompileError::from)?,
+                value.to_modules(pool).map_err(DecompileError::from)?,
+            ));
+        }
+        groups.push(exports);
+
+        let mut opens = Vec::new();
+        for value in &module.opens {
+            opens.push(package_directive(
+                "opens",
+                value.package(pool).map_err(DecompileError::from)?,
+                value.to_modules(pool).map_err(DecompileError::from)?,
+            ));
+        }
+        groups.push(opens);
+
+        groups.push(module.uses(pool).map_err(DecompileError::from)?
+            .iter()
+            .map(|service| format!("uses {}", service.full_path()))
+            .collect());
+
+        let mut provides = Vec::new();
+        for value in &module.provides {
+            let implementations: Vec<String> = value.implementations(pool).map_err(DecompileError::from)?
+                .iter()
+                .map(|implementation| implementation.full_path())
+                .collect();
+            provides.push(format!(
+                "provides {} with {}",
+                value.service(pool).map_err(DecompileError::from)?.full_path(),
+                implementations.join(", ")
+            ));
+        }
+        groups.push(provides);
+
+        groups.retain(|group| !group.is_empty());
+        for (index, group) in groups.iter().enumerate() {
+            if index > 0 {
+                writeln!(o)?;
+            }
+            for directive in group {
+                writeln!(o, "    {};", directive)?;
+            }
+        }
+        write!(o, "}}")?;
+        Ok(())
+    }
+
     fn i_write_descriptor<W: Write>(&self, descriptor: &Descriptor, o: &mut W) -> WriteResult {
         write!(o, "{}", match descriptor {
             Descriptor::Byte => "byte",
@@ -100,7 +312,7 @@ impl JavaWriter {
         match descriptor {
             Descriptor::Class(class) => write!(o, "{}", class.name.as_str())?,
             Descriptor::Array(array) => {
-                self.write_descriptor(&*array.descriptor, o)?;
+                self.write_descriptor(&array.descriptor, o)?;
                 write!(o, "{}", "[]".repeat(array.dimensions as usize).as_str())?;
             }
             Descriptor::Unknown(value) => write!(o, "/* unknown: {} */", value)?,
@@ -109,42 +321,201 @@ impl JavaWriter {
         Ok(())
     }
 
-    fn write_access_psf<W: Write>(&self, access: &AccessFlags, o: &mut W) -> WriteResult {
-        if access.is_set(AccessFlag::Public) {
-            write!(o, "public ")?;
-        } else if access.is_set(AccessFlag::Protected) {
-            write!(o, "protected ")?;
-        } else if access.is_set(AccessFlag::Private) {
-            write!(o, "private ")?;
+    /// Writes the annotations of a class or member each on their own line.
+    /// Old compilers only marked deprecation with the Deprecated attribute
+    /// so that is written as an annotation when the annotation is missing
+    fn write_annotations<W: Write>(&self, class: &Class, attributes: &[Attribute], indent: &str, o: &mut W) -> WriteResult {
+        let pool = &class.constant_pool;
+        let mut deprecated = false;
+        for annotation in annotations(attributes) {
+            if pool.get_utf8(&annotation.type_index).is_ok_and(|value| value == "Ljava/lang/Deprecated;") {
+                deprecated = true;
+            }
+            write!(o, "{}", indent)?;
+            self.write_annotation(pool, annotation, o)?;
+            writeln!(o)?;
         }
-        if access.is_set(AccessFlag::Static) {
-            write!(o, "static ")?;
+        let has_attribute = attributes.iter()
+            .any(|attribute| matches!(attribute.value, AttributeValue::Depreciated));
+        if has_attribute && !deprecated {
+            writeln!(o, "{}@Deprecated", indent)?;
         }
-        if access.is_set(AccessFlag::Final) {
-            write!(o, "final ")?;
+        Ok(())
+    }
+
+    fn write_annotation<W: Write>(&self, pool: &ConstantPool, annotation: &Annotation, o: &mut W) -> WriteResult {
+        write!(o, "@")?;
+        let descriptor = annotation.type_descriptor(pool)
+            .map_err(DecompileError::from)?;
+        self.write_descriptor(&descriptor, o)?;
+        if annotation.elements.is_empty() {
+            return Ok(());
+        }
+        write!(o, "(")?;
+        for (index, (name_index, value)) in annotation.elements.iter().enumerate() {
+            let name = pool.get_utf8(name_index)
+                .map_err(DecompileError::from)?;
+            if index > 0 {
+                write!(o, ", ")?;
+            }
+            // A single element named value can be written without its name
+            if annotation.elements.len() > 1 || name != "value" {
+                write!(o, "{} = ", name)?;
+            }
+            self.write_element_value(pool, value, o)?;
+        }
+        write!(o, ")")?;
+        Ok(())
+    }
+
+    fn write_element_value<W: Write>(&self, pool: &ConstantPool, value: &ElementValue, o: &mut W) -> WriteResult {
+        match value {
+            ElementValue::Const { tag, const_value_index } => {
+                let constant = pool.inner.get(const_value_index)
+                    .ok_or(ConstantError::NotFound(*const_value_index))
+                    .map_err(DecompileError::from)?;
+                match (tag, constant) {
+                    (b'Z', Constant::Integer(value)) => write!(o, "{}", *value != 0)?,
+                    (b'C', Constant::Integer(value)) => {
+                        let value = char::from_u32(*value as u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+                        write!(o, "'{}'", escape(&value.to_string()))?
+                    }
+                    (_, Constant::Integer(value)) => write!(o, "{}", value)?,
+                    (_, Constant::Long(value)) => write!(o, "{}L", value)?,
+                    (_, Constant::Float(value)) => match java_float(*value).as_str() {
+                        "NaN" => write!(o, "Float.NaN")?,
+                        "Infinity" => write!(o, "Float.POSITIVE_INFINITY")?,
+                        "-Infinity" => write!(o, "Float.NEGATIVE_INFINITY")?,
+                        value => write!(o, "{}f", value)?,
+                    },
+                    (_, Constant::Double(value)) => match java_float(*value).as_str() {
+                        "NaN" => write!(o, "Double.NaN")?,
+                        "Infinity" => write!(o, "Double.POSITIVE_INFINITY")?,
+                        "-Infinity" => write!(o, "Double.NEGATIVE_INFINITY")?,
+                        value => write!(o, "{}", value)?,
+                    },
+                    (_, Constant::Utf8(value)) | (_, Constant::Utf8Lossy(value, _)) => {
+                        write!(o, "\"{}\"", escape(value))?
+                    }
+                    _ => write!(o, "/* invalid constant #{} */", const_value_index)?,
+                }
+            }
+            ElementValue::Enum { type_name_index, const_name_index } => {
+                let descriptor = pool.get_utf8(type_name_index)
+                    .map(|value| Descriptor::parse(value))
+                    .map_err(DecompileError::from)?;
+                self.write_descriptor(&descriptor, o)?;
+                let name = pool.get_utf8(const_name_index)
+                    .map_err(DecompileError::from)?;
+                write!(o, ".{}", name)?;
+            }
+            ElementValue::Class(index) => {
+                let descriptor = pool.get_utf8(index)
+                    .map(|value| Descriptor::parse(value))
+                    .map_err(DecompileError::from)?;
+                self.write_descriptor(&descriptor, o)?;
+                write!(o, ".class")?;
+            }
+            ElementValue::Annotation(annotation) => self.write_annotation(pool, annotation, o)?,
+            ElementValue::Array(values) => {
+                write!(o, "{{")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(o, ", ")?;
+                    }
+                    self.write_element_value(pool, value, o)?;
+                }
+                write!(o, "}}")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a type along with its type annotations. Descriptors don't
+    /// include type arguments so only annotations on the type itself or
+    /// on the dimensions and element type of an array can be placed
+    fn write_annotated_type<W: Write>(
+        &self,
+        pool: &ConstantPool,
+        descriptor: &Descriptor,
+        annotations: &[&TypeAnnotation],
+        declaration: &[&Annotation],
+        o: &mut W,
+    ) -> WriteResult {
+        let (element, dimensions) = match descriptor {
+            Descriptor::Array(array) => (array.descriptor.as_ref(), array.dimensions as usize),
+            descriptor => (descriptor, 0),
+        };
+        self.write_type_annotations(pool, annotations, dimensions, declaration, o)?;
+        self.write_descriptor(element, o)?;
+        // Array dimensions are annotated in the same order as they appear (String @A [] @B [])
+        for depth in 0..dimensions {
+            for annotation in annotations.iter().filter(|annotation| annotation.is_array_depth(depth)) {
+                write!(o, " ")?;
+                self.write_annotation(pool, &annotation.annotation, o)?;
+                write!(o, " ")?;
+            }
+            write!(o, "[]")?;
         }
         Ok(())
     }
 
-    fn write_field<W: Write>(&self, field: &Member, o: &mut W) -> WriteResult {
-        let access = field.access_flags;
-        write!(o, "    ")?;
-        self.write_access_psf(&access, o)?;
-        if access.is_set(AccessFlag::Volatile) {
-            write!(o, "volatile ")?;
+    /// Writes the generic type from the signature unless there are type
+    /// annotations, whose type paths can't be placed on the generic type
+    /// yet, in which case the annotated erased type is written instead
+    fn write_generic_type<W: Write>(
+        &self,
+        pool: &ConstantPool,
+        descriptor: &Descriptor,
+        signature: Option<&TypeSignature>,
+        annotations: &[&TypeAnnotation],
+        declaration: &[&Annotation],
+        o: &mut W,
+    ) -> WriteResult {
+        match signature {
+            Some(signature) if annotations.is_empty() => write!(o, "{}", signature.to_java(JavaNames::Simple))?,
+            _ => self.write_annotated_type(pool, descriptor, annotations, declaration, o)?,
         }
-        if access.is_set(AccessFlag::Transient) {
-            write!(o, "transient ")?;
+        Ok(())
+    }
+
+    /// Writes the type annotations at the provided array depth followed by
+    /// a space. Annotations which are also declaration annotations are left
+    /// out as the source form of both is the same (e.g. @Both String field)
+    fn write_type_annotations<W: Write>(
+        &self,
+        pool: &ConstantPool,
+        annotations: &[&TypeAnnotation],
+        depth: usize,
+        declaration: &[&Annotation],
+        o: &mut W,
+    ) -> WriteResult {
+        for annotation in annotations {
+            if annotation.is_array_depth(depth) && !declaration.contains(&&annotation.annotation) {
+                self.write_annotation(pool, &annotation.annotation, o)?;
+                write!(o, " ")?;
+            }
         }
-        self.write_descriptor(&field.descriptor, o)?;
-        write!(o, " {};\n", field.name)?;
+        Ok(())
+    }
+
+    fn write_field<W: Write>(&self, class: &Class, field: &Member, o: &mut W) -> WriteResult {
+        self.write_annotations(class, &field.attributes, "    ", o)?;
+        write!(o, "    {}", field.access_flags.modifiers::<FieldAccessFlag>())?;
+        let type_annotations: Vec<&TypeAnnotation> = type_annotations(&field.attributes)
+            .filter(|annotation| annotation.target_type == TargetType::Field)
+            .collect();
+        let declaration: Vec<&Annotation> = field.annotations().collect();
+        let signature = field.field_signature().ok().flatten();
+        self.write_generic_type(&class.constant_pool, &field.descriptor, signature.as_ref(), &type_annotations, &declaration, o)?;
+        writeln!(o, " {};", field.name)?;
         Ok(())
     }
 
 
     fn write_code<W: Write>(&self, class: &Class, method: &Member, code_attr: &CodeAttr, o: &mut W) -> WriteResult {
-        write!(o, ") {{\n")?;
-        let instr = parse_code(code_attr.code.clone())
+        writeln!(o, ") {{")?;
+        let instr = parse_code(&code_attr.code)
             .map_err(|_| WriteError::BadCodeAttribute)?;
         let control_flow_graph = gen_control_flow_graph(&instr);
         let paths = find_paths(&control_flow_graph, 0, Vec::new());
@@ -156,14 +527,11 @@ impl JavaWriter {
             let block: &Block = control_flow_graph.get(key)
                 .expect("expected constant pool to contain index");
             println!("\x1b[93m{}: {:?}\x1b[0m", key, block);
-            let decompiled = block.decompile(&class.constant_pool)?;
+            let decompiled = block.decompile(&class.constant_pool, class.bootstrap_methods(), class.version)?;
             let length = decompiled.len();
             for (index, statement) in decompiled.iter().enumerate() {
                 if index == length - 1 {
-                    match statement {
-                        AST::VoidReturn => break,
-                        _ => {}
-                    }
+                    if let AST::VoidReturn = statement { break }
                 }
                 write!(o,"      ")?;
                 statement.write_java(o, method, code_attr)?;
@@ -182,25 +550,104 @@ impl JavaWriter {
     }
 
 
-    fn write_method<W: Write>(&self, class: &Class, method: &Member, o: &mut W) -> WriteResult {
-        write!(o, "    ")?;
-        self.write_access_psf(&method.access_flags, o)?;
+    /// Whether the method overrides or implements a method of one of the
+    /// super types. Methods of super types in other packages are only
+    /// overridden when they aren't package private
+    fn is_override(&self, class: &Class, super_types: &[Arc<Class>], method: &Member) -> bool {
+        let access = method.access_flags;
+        if method.is_init() || method.name == "<clinit>" || access.is_set(MethodAccessFlag::Static)
+            || access.is_set(MethodAccessFlag::Private) || access.is_set(MethodAccessFlag::Synthetic) {
+            return false;
+        }
+        // The return type can be narrowed by the overriding method
+        let descriptor = method.descriptor.to_internal_java();
+        let parameters = descriptor.split(')').next();
+        super_types.iter().any(|super_type| super_type.methods.iter().any(|candidate| {
+            let access = candidate.access_flags;
+            let is_package_private = !access.is_set(MethodAccessFlag::Public) && !access.is_set(MethodAccessFlag::Protected);
+            candidate.name == method.name
+                && candidate.descriptor.to_internal_java().split(')').next() == parameters
+                && !access.is_set(MethodAccessFlag::Static)
+                && !access.is_set(MethodAccessFlag::Private)
+                && (!is_package_private || super_type.class_path.package == class.class_path.package)
+        }))
+    }
+
+    fn write_method<W: Write>(&self, class: &Class, super_types: &[Arc<Class>], method: &Member, o: &mut W) -> WriteResult {
+        self.write_annotations(class, &method.attributes, "    ", o)?;
+        if self.is_override(class, super_types, method) {
+            writeln!(o, "    @Override")?;
+        }
+        let mut access = method.access_flags;
+        // Interface methods without a body are implicitly abstract and the
+        // other instance methods which aren't private are default methods
+        let mut is_default = false;
+        if class.access_flags.is_set(ClassAccessFlag::Interface) {
+            if access.is_set(MethodAccessFlag::Abstract) {
+                access.unset(MethodAccessFlag::Abstract);
+            } else {
+                is_default = !access.is_set(MethodAccessFlag::Static) && !access.is_set(MethodAccessFlag::Private);
+            }
+        }
+        write!(o, "    {}", access.modifiers::<MethodAccessFlag>())?;
+        if is_default {
+            write!(o, "default ")?;
+        }
         let desc = match &method.descriptor {
             Descriptor::Method(method) => method,
             _ => Err(WriteError::BadDescriptor)?
         };
+        let pool = &class.constant_pool;
+        let type_annotations: Vec<&TypeAnnotation> = type_annotations(&method.attributes).collect();
+        let targeting = |target_type: TargetType| -> Vec<&TypeAnnotation> {
+            type_annotations.iter()
+                .filter(|annotation| annotation.target_type == target_type)
+                .copied()
+                .collect()
+        };
+        // Signatures leave out synthetic parameters (e.g. the outer instance of
+        // inner class constructors) so are only used when the parameters line up
+        let signature = method.method_signature().ok().flatten()
+            .filter(|signature| signature.parameters.len() == desc.parameters.len());
+        if let Some(signature) = signature.as_ref().filter(|signature| !signature.type_parameters.is_empty()) {
+            write!(o, "{} ", type_parameters_to_java(&signature.type_parameters, JavaNames::Simple))?;
+        }
         let c = method.is_init();
         if c {
-            write!(o, "{}(", class.class_path.name)?;
+            write!(o, "{}(", class.class_path.simple_name())?;
         } else {
-            self.write_descriptor(&*desc.return_type, o)?;
+            let declaration: Vec<&Annotation> = method.annotations().collect();
+            let return_type = signature.as_ref().map(|signature| &signature.return_type);
+            self.write_generic_type(pool, &desc.return_type, return_type, &targeting(TargetType::MethodReturn), &declaration, o)?;
             write!(o, " {}(", method.name)?;
         }
+        // The receiver parameter only exists in source to hold annotations
+        let receiver = targeting(TargetType::MethodReceiver);
+        if !receiver.is_empty() {
+            self.write_type_annotations(pool, &receiver, 0, &[], o)?;
+            write!(o, "{} this", class.class_path.simple_name())?;
+            if !desc.parameters.is_empty() {
+                write!(o, ", ")?;
+            }
+        }
         let mut p_num = 0;
         if !desc.parameters.is_empty() {
             let last = desc.parameters.len() - 1;
             for (i, parameter) in desc.parameters.iter().enumerate() {
-                self.write_descriptor(parameter, o)?;
+                let declaration: Vec<&Annotation> = method.parameter_annotations(i).collect();
+                for annotation in &declaration {
+                    self.write_annotation(pool, annotation, o)?;
+                    write!(o, " ")?;
+                }
+                let type_annotations: Vec<&TypeAnnotation> = type_annotations.iter()
+                    .filter(|annotation| matches!(
+                        annotation.target_info,
+                        TargetInfo::FormalParameter { formal_parameter_index } if formal_parameter_index as usize == i
+                    ))
+                    .copied()
+                    .collect();
+                let generic = signature.as_ref().map(|signature| &signature.parameters[i]);
+                self.write_generic_type(pool, parameter, generic, &type_annotations, &declaration, o)?;
                 write!(o, " p_{}", p_num)?;
                 p_num += 1;
                 if i != last {
@@ -210,17 +657,10 @@ impl JavaWriter {
         }
 
 
-        let code_attr = &method.attributes
-            .iter()
-            .find(|a| a.name == "Code")
-            .unwrap()
-            .value;
-
-        match code_attr {
-            AttributeValue::Code(code_attr_value) => {
-                self.write_code(class, method, code_attr_value, o)?;
-            }
-            _ => Err(WriteError::BadCodeAttribute)?
+        // Abstract and native methods don't have a body
+        match method.code() {
+            Some(code_attr) => self.write_code(class, method, code_attr, o)?,
+            None => write!(o, ");\n\n")?,
         }
         Ok(())
     }
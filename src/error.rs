@@ -1,9 +1,13 @@
 // This file contains the all the possible errors that could be thrown
+use std::fmt::{Display, Formatter};
 use std::io;
 
 use thiserror::Error;
+use zip::result::ZipError;
 
+use crate::class::class::{Feature, MajorVersion};
 use crate::class::constant::PoolIndex;
+use crate::mutf8::Mutf8Error;
 
 #[derive(Error, Debug)]
 pub enum WriteError {
@@ -17,6 +21,16 @@ pub enum WriteError {
     BadDescriptor,
     #[error("code attribute wasn't code attribute")]
     BadCodeAttribute,
+    #[error("length {0} is too large to be written")]
+    LengthOverflow(usize),
+    #[error("no constant for {0} was found in the pool")]
+    MissingConstant(String),
+    #[error("constant pool has no constant at index {0}")]
+    PoolGap(PoolIndex),
+    #[error("constant pool is full")]
+    PoolFull,
+    #[error(transparent)]
+    Provider(#[from] ProviderError),
 }
 
 #[derive(Error, Debug)]
@@ -35,6 +49,10 @@ pub enum DecompileError {
     InvalidConstant(#[from] ConstantError),
     #[error(transparent)]
     StackError(#[from] StackError),
+    #[error("{0:?} isn't supported by classes for {1:?}")]
+    UnsupportedFeature(Feature, MajorVersion),
+    #[error("decompiling {0} isn't implemented yet")]
+    Unimplemented(&'static str),
 }
 
 #[derive(Error, Debug)]
@@ -52,15 +70,188 @@ pub enum ReadError {
     #[error(transparent)]
     IO(#[from] io::Error),
     #[error(transparent)]
-    InvalidUtf8(#[from] std::string::FromUtf8Error),
+    InvalidUtf8(#[from] Mutf8Error),
     #[error("unknown constant tag {0}")]
     UnknownConstantTag(u8),
     #[error("invalid class magic signature got 0x{0} expected 0xCAFEBABE")]
     InvalidMagic(u32),
     #[error(transparent)]
     InvalidConstant(#[from] ConstantError),
+    #[error("unknown annotation element value tag {0}")]
+    UnknownElementValueTag(u8),
+    #[error("unknown type annotation target type {0:#04x}")]
+    UnknownTargetType(u8),
+    #[error("unknown type path kind {0}")]
+    UnknownTypePathKind(u8),
+    #[error("reserved stack map frame type {0}")]
+    UnknownFrameType(u8),
+    #[error("unknown verification type tag {0}")]
+    UnknownVerificationType(u8),
+    #[error("invalid stack map frame: {0}")]
+    InvalidStackMapFrame(&'static str),
+    #[error(transparent)]
+    InvalidSignature(#[from] SignatureError),
     #[error("class name was not found in constant pool")]
     NoClassName,
+    #[error("{0} of {1} exceeds the limit of {2}")]
+    LimitExceeded(&'static str, u64, u64),
+    #[error("{0} bytes were left over after the attribute value")]
+    TrailingBytes(u64),
+    #[error("{source} ({location})")]
+    Located {
+        source: Box<ReadError>,
+        location: ErrorLocation,
+    },
+}
+
+/// A single step in the path to the value that failed to read
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PathSegment {
+    // A named field of a structure (e.g. methods)
+    Field(&'static str),
+    // An element of a table (e.g. [3])
+    Index(usize),
+    // An element identified by its name (e.g. ["Code"])
+    Key(String),
+}
+
+/// The logical path to the value being read when an error occurred
+/// for example methods[3].attributes["Code"].exception_table[2]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ErrorPath(pub Vec<PathSegment>);
+
+impl Display for ErrorPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (index, segment) in self.0.iter().enumerate() {
+            match segment {
+                PathSegment::Field(name) if index == 0 => write!(f, "{}", name)?,
+                PathSegment::Field(name) => write!(f, ".{}", name)?,
+                PathSegment::Index(value) => write!(f, "[{}]", value)?,
+                PathSegment::Key(value) => write!(f, "[{:?}]", value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where in the class file a read error occurred
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ErrorLocation {
+    // Byte offset from the start of the class file. Only known once the
+    // error has reached the reader that started reading the class
+    pub offset: Option<u64>,
+    pub path: ErrorPath,
+    // Number of bytes between the failure and the end of the nested
+    // buffers it was read from, subtracted once the offset is known
+    rewind: u64,
+}
+
+impl Display for ErrorLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "at offset {}", offset)?,
+            None => f.write_str("at unknown offset")?,
+        }
+        if !self.path.0.is_empty() {
+            write!(f, " in {}", self.path)?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadError {
+    fn into_located(self) -> (Box<ReadError>, ErrorLocation) {
+        match self {
+            ReadError::Located { source, location } => (source, location),
+            error => (Box::new(error), ErrorLocation::default())
+        }
+    }
+
+    fn map_location<F: FnOnce(&mut ErrorLocation)>(self, f: F) -> ReadError {
+        let (source, mut location) = self.into_located();
+        f(&mut location);
+        ReadError::Located { source, location }
+    }
+
+    /// Prefixes the error path with the name of the field being read
+    pub fn in_field(self, name: &'static str) -> ReadError {
+        self.map_location(|location| location.path.0.insert(0, PathSegment::Field(name)))
+    }
+
+    /// Prefixes the error path with the index of the table element being
+    /// read. Elements that already identify themselves by key (such as
+    /// attributes by name) are left as is
+    pub fn in_element(self, index: usize) -> ReadError {
+        self.map_location(|location| {
+            if !matches!(location.path.0.first(), Some(PathSegment::Key(_))) {
+                location.path.0.insert(0, PathSegment::Index(index))
+            }
+        })
+    }
+
+    /// Prefixes the error path with the key of the element being read
+    pub fn in_key<K: Into<String>>(self, key: K) -> ReadError {
+        self.map_location(|location| location.path.0.insert(0, PathSegment::Key(key.into())))
+    }
+
+    /// Records that the error occurred inside a nested buffer with
+    /// `remaining` bytes left unread after the failure
+    pub fn rewind(self, remaining: u64) -> ReadError {
+        self.map_location(|location| if location.offset.is_none() {
+            location.rewind += remaining
+        })
+    }
+
+    /// Resolves the byte offset of the error given the position of the
+    /// reader when the error reached it. Errors which already have an
+    /// offset are left unchanged
+    pub fn at_offset(self, position: u64) -> ReadError {
+        self.map_location(|location| if location.offset.is_none() {
+            location.offset = Some(position.saturating_sub(location.rewind));
+            location.rewind = 0;
+        })
+    }
+
+    /// The underlying error without any location information
+    pub fn kind(&self) -> &ReadError {
+        match self {
+            ReadError::Located { source, .. } => source.kind(),
+            error => error
+        }
+    }
+
+    pub fn location(&self) -> Option<&ErrorLocation> {
+        match self {
+            ReadError::Located { location, .. } => Some(location),
+            _ => None
+        }
+    }
+}
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum DescriptorError {
+    #[error("unexpected end of descriptor")]
+    UnexpectedEnd,
+    #[error("unexpected {1:?} at position {0} of descriptor")]
+    Unexpected(usize, char),
+    #[error("empty class name part at position {0} of descriptor")]
+    EmptyName(usize),
+    #[error("array at position {0} of descriptor has more than 255 dimensions")]
+    TooManyDimensions(usize),
+}
+
+#[derive(Error, Debug)]
+pub enum SignatureError {
+    #[error("unexpected end of signature")]
+    UnexpectedEnd,
+    #[error("unexpected {1:?} at position {0} of signature")]
+    Unexpected(usize, char),
+    #[error("expected identifier at position {0} of signature")]
+    EmptyIdentifier(usize),
+    #[error("array at position {0} of signature has more than 255 dimensions")]
+    TooManyDimensions(usize),
+    #[error("type arguments at position {0} of signature are nested too deeply")]
+    NestedTooDeep(usize),
 }
 
 #[derive(Error, Debug)]
@@ -77,4 +268,61 @@ pub enum ConstantError {
     ExpectedMethodRef(PoolIndex),
     #[error("expected value at index {0} to be invoke dynamic")]
     ExpectedInvokeDynamic(PoolIndex),
-}
\ No newline at end of file
+    #[error("expected value at index {0} to be method handle")]
+    ExpectedMethodHandle(PoolIndex),
+    #[error("expected value at index {0} to be method type")]
+    ExpectedMethodType(PoolIndex),
+    #[error("expected value at index {0} to be dynamic")]
+    ExpectedDynamic(PoolIndex),
+    #[error("expected value at index {0} to be module")]
+    ExpectedModule(PoolIndex),
+    #[error("expected value at index {0} to be package")]
+    ExpectedPackage(PoolIndex),
+    #[error("value at index {0} is not loadable")]
+    NotLoadable(PoolIndex),
+    #[error("unknown method handle reference kind {0}")]
+    InvalidReferenceKind(u8),
+    #[error("no bootstrap method found at index {0}")]
+    BootstrapMethodNotFound(PoolIndex),
+}
+
+#[derive(Error, Debug)]
+pub enum JarError {
+    #[error(transparent)]
+    IO(#[from] io::Error),
+    #[error(transparent)]
+    Zip(#[from] ZipError),
+    #[error("no entry named {0} was found in the jar")]
+    MissingEntry(String),
+    #[error("{name}: {source}")]
+    InvalidClass {
+        name: String,
+        source: ReadError,
+    },
+    #[error("{name}: entry of {size} bytes exceeds the limit of {max}")]
+    EntryTooLarge {
+        name: String,
+        size: u64,
+        max: u64,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum ProviderError {
+    #[error(transparent)]
+    IO(#[from] io::Error),
+    #[error(transparent)]
+    Jar(#[from] JarError),
+    #[error("class {0} was not found")]
+    NotFound(String),
+    #[error("{name}: {source}")]
+    InvalidClass {
+        name: String,
+        source: ReadError,
+    },
+    #[error("invalid jmod header {found:02x?} expected {expected:02x?}")]
+    InvalidJmodHeader {
+        found: [u8; 4],
+        expected: [u8; 4],
+    },
+}
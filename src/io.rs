@@ -1,15 +1,98 @@
-use std::io::Read;
+use std::io::{self, Read, Write};
 
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
-use crate::error::ReadError;
+use crate::error::{ReadError, WriteError};
+use crate::mutf8;
 
 pub type ReadResult<A> = Result<A, ReadError>;
+pub type WriteResult = Result<(), WriteError>;
 
 pub trait Readable: Send + Sync {
     fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized;
 }
 
+pub trait Writable: Send + Sync {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult;
+}
+
+/// The largest number of elements that will be allocated up front when
+/// reading a length prefixed value. Lengths come from the input so can't
+/// be trusted, larger values grow as their contents are actually read.
+pub(crate) const MAX_PREALLOCATE: usize = 4096;
+
+/// Limits applied while parsing class files to bound the amount of work
+/// and memory used by malformed or hostile input
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ParseLimits {
+    // The maximum size of the constant pool (the constant_pool_count value)
+    pub max_constant_pool_size: u16,
+    // The maximum length in bytes of a single attribute
+    pub max_attribute_length: u32,
+    // The maximum length in bytes of the code in a Code attribute
+    pub max_code_length: u32,
+    // The maximum depth of attributes nested inside other attributes, of
+    // annotation element values nested inside other element values and of
+    // type arguments nested inside other type arguments in signatures
+    pub max_nesting_depth: u8,
+}
+
+impl Default for ParseLimits {
+    /// The default limits accept any class file produced by a compiler. The
+    /// code length limit is the maximum allowed by the JVM specification
+    fn default() -> Self {
+        ParseLimits {
+            max_constant_pool_size: u16::MAX,
+            max_attribute_length: 16 * 1024 * 1024,
+            max_code_length: 65535,
+            max_nesting_depth: 8,
+        }
+    }
+}
+
+impl ParseLimits {
+    /// Checks that the provided value doesn't exceed the provided limit
+    pub fn check(name: &'static str, value: u64, max: u64) -> ReadResult<()> {
+        if value > max {
+            Err(ReadError::LimitExceeded(name, value, max))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Obtains the limits to use for values nested one level deeper than
+    /// the current value. Fails if the maximum nesting depth is reached
+    pub fn nested(&self) -> ReadResult<ParseLimits> {
+        if self.max_nesting_depth == 0 {
+            return Err(ReadError::LimitExceeded("nesting depth", 1, 0));
+        }
+        Ok(ParseLimits { max_nesting_depth: self.max_nesting_depth - 1, ..*self })
+    }
+}
+
+/// Reader which keeps track of how many bytes have been read so that the
+/// offset of read errors can be determined
+pub struct PositionReader<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> PositionReader<R> {
+    pub fn new(inner: R) -> Self {
+        PositionReader { inner, position: 0 }
+    }
+
+    pub fn position(&self) -> u64 { self.position }
+}
+
+impl<R: Read> Read for PositionReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
 impl Readable for u8 {
     fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized {
         i.read_u8().map_err(ReadError::from)
@@ -22,6 +105,29 @@ impl Readable for i8 {
     }
 }
 
+impl Writable for u8 {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        o.write_u8(*self).map_err(WriteError::from)
+    }
+}
+
+impl Writable for i8 {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        o.write_i8(*self).map_err(WriteError::from)
+    }
+}
+
+/// Reads exactly `length` bytes. The buffer is filled incrementally so that
+/// it only grows as large as the data that is actually present
+pub fn read_bytes_exact<R: Read>(r: &mut R, length: usize) -> ReadResult<Vec<u8>> {
+    let mut buffer = Vec::with_capacity(length.min(MAX_PREALLOCATE));
+    r.by_ref().take(length as u64).read_to_end(&mut buffer)?;
+    if buffer.len() != length {
+        return Err(ReadError::IO(io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(buffer)
+}
+
 // Trait for reading vec of a runtime known size
 pub trait VecReadableSize: Sized {
     fn read_vec<C: Readable, R: Read>(r: &mut R) -> ReadResult<Vec<C>>;
@@ -43,9 +149,9 @@ macro_rules! impl_vec_readable {
             impl VecReadableSize for $type {
                  fn read_vec<C: Readable, R: Read>(r: &mut R) -> ReadResult<Vec<C>> {
                     let length = <$type>::read(r)? as usize;
-                    let mut out = Vec::with_capacity(length);
-                    for _ in 0..length {
-                        out.push(C::read(r)?)
+                    let mut out = Vec::with_capacity(length.min(MAX_PREALLOCATE));
+                    for index in 0..length {
+                        out.push(C::read(r).map_err(|err| err.in_element(index))?)
                     }
                     Ok(out)
                  }
@@ -54,19 +160,16 @@ macro_rules! impl_vec_readable {
             impl VecReadableBytesSize for $type {
                  fn read_bytes<R: Read>(r: &mut R) -> ReadResult<Vec<u8>> {
                     let length = <$type>::read(r)? as usize;
-                    let mut buffer = Vec::with_capacity(length);
-                    unsafe { buffer.set_len(length) }
-                    r.read_exact(&mut buffer).map_err(ReadError::from)?;
-                    Ok(buffer)
+                    read_bytes_exact(r, length)
                  }
             }
 
             impl VecReadableFn for $type {
                 fn read_vec_closure<C, R: Read, F: Fn(&mut R) -> ReadResult<C>>(r: &mut R, f: F) -> ReadResult<Vec<C>> {
                     let length = <$type>::read(r)? as usize;
-                    let mut out = Vec::with_capacity(length);
-                    for _ in 0..length {
-                        out.push(f(r)?)
+                    let mut out = Vec::with_capacity(length.min(MAX_PREALLOCATE));
+                    for index in 0..length {
+                        out.push(f(r).map_err(|err| err.in_element(index))?)
                     }
                     Ok(out)
                 }
@@ -77,6 +180,62 @@ macro_rules! impl_vec_readable {
 
 impl_vec_readable!(u8,u16,u32);
 
+// Trait for writing a vec of values prefixed with its length
+pub trait VecWritableSize: Sized {
+    fn write_vec<C: Writable, W: Write>(o: &mut W, values: &[C]) -> WriteResult;
+}
+
+// Trait for writing a slice of bytes prefixed with its length
+pub trait VecWritableBytesSize: Sized {
+    fn write_bytes<W: Write>(o: &mut W, bytes: &[u8]) -> WriteResult;
+}
+
+// Trait for writing a vec of values prefixed with its length using a closure for writing
+pub trait VecWritableFn: Sized {
+    fn write_vec_closure<C, W: Write, F: Fn(&mut W, &C) -> WriteResult>(o: &mut W, values: &[C], f: F) -> WriteResult;
+}
+
+macro_rules! impl_vec_writable {
+    ($($type:ty),*) => {
+        $(
+            impl VecWritableSize for $type {
+                fn write_vec<C: Writable, W: Write>(o: &mut W, values: &[C]) -> WriteResult {
+                    <$type>::try_from(values.len())
+                        .map_err(|_| WriteError::LengthOverflow(values.len()))?
+                        .write(o)?;
+                    for value in values {
+                        value.write(o)?;
+                    }
+                    Ok(())
+                }
+            }
+
+            impl VecWritableBytesSize for $type {
+                fn write_bytes<W: Write>(o: &mut W, bytes: &[u8]) -> WriteResult {
+                    <$type>::try_from(bytes.len())
+                        .map_err(|_| WriteError::LengthOverflow(bytes.len()))?
+                        .write(o)?;
+                    o.write_all(bytes).map_err(WriteError::from)
+                }
+            }
+
+            impl VecWritableFn for $type {
+                fn write_vec_closure<C, W: Write, F: Fn(&mut W, &C) -> WriteResult>(o: &mut W, values: &[C], f: F) -> WriteResult {
+                    <$type>::try_from(values.len())
+                        .map_err(|_| WriteError::LengthOverflow(values.len()))?
+                        .write(o)?;
+                    for value in values {
+                        f(o, value)?;
+                    }
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_vec_writable!(u8,u16,u32);
+
 
 // Macro for implementing the readable trait on numbers
 // that support the BigEndian encoding.
@@ -101,13 +260,40 @@ be_readable!(
     f32 (read_f32), f64 (read_f64)
 );
 
+// Macro for implementing the writable trait on numbers
+// that support the BigEndian encoding.
+macro_rules! be_writable {
+    (
+        $($type:ident ($fn:ident)),*
+    ) => {
+        $(
+            impl Writable for $type {
+                fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+                     o.$fn::<BigEndian>(*self).map_err(WriteError::from)
+                }
+            }
+        )*
+    };
+}
+
+be_writable!(
+    i16 (write_i16), u16 (write_u16),
+    u32 (write_u32), i32 (write_i32),
+    i64 (write_i64), u64 (write_u64),
+    f32 (write_f32), f64 (write_f64)
+);
+
 impl Readable for String {
     fn read<R: Read>(i: &mut R) -> ReadResult<Self> where Self: Sized {
         let bytes = u16::read_bytes(i)?;
-        Ok(
-            String::from_utf8(bytes)
-                .map_err(ReadError::from)?
-        )
+        mutf8::decode(&bytes)
+            .map_err(ReadError::from)
+    }
+}
+
+impl Writable for String {
+    fn write<W: Write>(&self, o: &mut W) -> WriteResult {
+        u16::write_bytes(o, &mutf8::encode(self))
     }
 }
 
@@ -135,6 +321,13 @@ macro_rules! readable_struct {
                 }
             }
 
+            impl $crate::io::Writable for $name {
+                fn write<W: std::io::Write>(&self, o: &mut W) -> $crate::io::WriteResult {
+                    $($crate::io::Writable::write(&self.$field, o)?;)*
+                    Ok(())
+                }
+            }
+
         )*
     };
 }
\ No newline at end of file
@@ -0,0 +1,316 @@
+//! Reading classes and resources from jar files. Entries are only listed
+//! when the jar is opened and classes are parsed when they are requested
+//! so a single broken entry doesn't stop the rest of the jar being read.
+//! Multi-release jars (JEP 238) are resolved for a target release so that
+//! the entries in META-INF/versions/N/ replace the base entries
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+
+use zip::read::ZipFile;
+use zip::ZipArchive;
+
+use crate::class::class::Class;
+use crate::error::JarError;
+use crate::io::{ParseLimits, MAX_PREALLOCATE};
+
+pub const MANIFEST_NAME: &str = "META-INF/MANIFEST.MF";
+pub const VERSIONS_PREFIX: &str = "META-INF/versions/";
+
+// The largest entry that will be read into memory. The size recorded in
+// the archive can't be trusted so this is also enforced while reading
+const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+
+// Versioned entries are only allowed for releases after the first one
+// which supported multi-release jars
+const MIN_VERSIONED_RELEASE: u16 = 9;
+
+/// The attributes of a jar manifest (see the JAR File Specification)
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    // The attributes of the main section in the order they were written
+    pub main_attributes: Vec<(String, String)>,
+    // The per-entry sections keyed by their Name attribute
+    pub entries: BTreeMap<String, Vec<(String, String)>>,
+}
+
+impl Manifest {
+    /// Parses the text of a manifest. Lines which aren't attributes are
+    /// ignored rather than failing the whole manifest
+    pub fn parse(text: &str) -> Manifest {
+        let mut manifest = Manifest::default();
+        let mut sections: Vec<Vec<(String, String)>> = vec![Vec::new()];
+        for line in text.split("\r\n").flat_map(|line| line.split(['\r', '\n'])) {
+            if line.is_empty() {
+                // A blank line ends the current section
+                if !sections.last().is_none_or(Vec::is_empty) {
+                    sections.push(Vec::new());
+                }
+            } else if let Some(continuation) = line.strip_prefix(' ') {
+                if let Some((_, value)) = sections.last_mut().and_then(|section| section.last_mut()) {
+                    value.push_str(continuation);
+                }
+            } else if let Some((name, value)) = line.split_once(':') {
+                let value = value.strip_prefix(' ').unwrap_or(value);
+                if let Some(section) = sections.last_mut() {
+                    section.push((name.to_string(), value.to_string()));
+                }
+            }
+        }
+        let mut sections = sections.into_iter();
+        manifest.main_attributes = sections.next().unwrap_or_default();
+        for section in sections {
+            let name = section.iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case("Name"))
+                .map(|(_, value)| value.clone());
+            if let Some(name) = name {
+                manifest.entries.insert(name, section);
+            }
+        }
+        manifest
+    }
+
+    /// The value of a main attribute. Attribute names are case insensitive
+    pub fn get(&self, name: &str) -> Option<&str> {
+        find_attribute(&self.main_attributes, name)
+    }
+
+    /// The value of an attribute in the section for an entry
+    pub fn get_entry(&self, entry: &str, name: &str) -> Option<&str> {
+        find_attribute(self.entries.get(entry)?, name)
+    }
+
+    pub fn main_class(&self) -> Option<&str> {
+        self.get("Main-Class")
+    }
+
+    pub fn is_multi_release(&self) -> bool {
+        self.get("Multi-Release").is_some_and(|value| value.trim().eq_ignore_ascii_case("true"))
+    }
+}
+
+fn find_attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attributes.iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// An entry of the jar after the multi-release overlays are applied
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct JarEntry {
+    // The name the entry is found by (e.g. com/example/Main.class)
+    pub name: String,
+    // The name of the entry in the archive. Differs from the name when it
+    // comes from META-INF/versions/N/
+    pub path: String,
+    // The release of the versioned directory the entry comes from
+    pub release: Option<u16>,
+    // The uncompressed size recorded in the archive
+    pub size: u64,
+    index: usize,
+}
+
+impl JarEntry {
+    pub fn is_class(&self) -> bool {
+        self.name.ends_with(".class") && !self.name.starts_with("META-INF/")
+    }
+
+    /// The internal name of the class in the entry (e.g. com/example/Main)
+    pub fn class_name(&self) -> Option<&str> {
+        if self.is_class() { self.name.strip_suffix(".class") } else { None }
+    }
+}
+
+pub struct JarReader<R: Read + Seek> {
+    archive: ZipArchive<R>,
+    manifest: Manifest,
+    // The release multi-release entries are resolved for. None only uses
+    // the base entries
+    release: Option<u16>,
+    limits: ParseLimits,
+    // Keyed by the entry name
+    entries: BTreeMap<String, JarEntry>,
+    // The index in the archive of the entries that couldn't be listed
+    entry_errors: Vec<(usize, JarError)>,
+}
+
+impl JarReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, JarError> {
+        JarReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> JarReader<R> {
+    /// Opens the archive and reads its manifest. Only the base entries
+    /// are used until a release is chosen with `with_release`
+    pub fn new(reader: R) -> Result<Self, JarError> {
+        let archive = ZipArchive::new(reader)?;
+        let mut jar = JarReader {
+            archive,
+            manifest: Manifest::default(),
+            release: None,
+            limits: ParseLimits::default(),
+            entries: BTreeMap::new(),
+            entry_errors: Vec::new(),
+        };
+        if jar.archive.file_names().any(|name| name == MANIFEST_NAME) {
+            let bytes = read_file(jar.archive.by_name(MANIFEST_NAME)?)?;
+            jar.manifest = Manifest::parse(&String::from_utf8_lossy(&bytes));
+        }
+        jar.resolve_entries();
+        Ok(jar)
+    }
+
+    /// Resolves the entries of a multi-release jar for the release. The
+    /// release is ignored if the manifest doesn't declare the jar as
+    /// multi-release
+    pub fn with_release(mut self, release: u16) -> Self {
+        self.release = Some(release);
+        self.resolve_entries();
+        self
+    }
+
+    /// The limits used when parsing the classes of the jar
+    pub fn with_limits(mut self, limits: ParseLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn release(&self) -> Option<u16> { self.release }
+
+    pub fn manifest(&self) -> &Manifest { &self.manifest }
+
+    /// Every file in the jar ordered by name
+    pub fn entries(&self) -> impl Iterator<Item = &JarEntry> {
+        self.entries.values()
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&JarEntry> {
+        self.entries.get(name)
+    }
+
+    /// The entries whose headers couldn't be read along with their index
+    /// in the archive. These are left out of `entries` so the rest of the
+    /// jar can still be read
+    pub fn entry_errors(&self) -> &[(usize, JarError)] {
+        &self.entry_errors
+    }
+
+    /// The internal names of the classes in the jar
+    pub fn class_names(&self) -> impl Iterator<Item = &str> {
+        self.entries.values().filter_map(JarEntry::class_name)
+    }
+
+    /// The entries which aren't classes including the manifest
+    pub fn resources(&self) -> impl Iterator<Item = &JarEntry> {
+        self.entries.values().filter(|entry| !entry.is_class())
+    }
+
+    /// Reads the contents of the entry with the name
+    pub fn read(&mut self, name: &str) -> Result<Vec<u8>, JarError> {
+        let index = self.entries.get(name)
+            .ok_or_else(|| JarError::MissingEntry(name.to_string()))?
+            .index;
+        self.read_index(index)
+    }
+
+    /// Reads and parses the class with the internal name
+    pub fn read_class(&mut self, class_name: &str) -> Result<Class, JarError> {
+        let name = format!("{}.class", class_name);
+        let bytes = self.read(&name)?;
+        Class::read_with_limits(&mut bytes.as_slice(), &self.limits)
+            .map_err(|source| JarError::InvalidClass { name, source })
+    }
+
+    /// Parses each class of the jar in turn. Every class is reported with
+    /// its own result so a broken class doesn't stop the others being read
+    pub fn classes(&mut self) -> JarClasses<'_, R> {
+        let names = self.class_names().map(str::to_string).collect::<Vec<_>>();
+        JarClasses { jar: self, names: names.into_iter() }
+    }
+
+    // Resolves which archive entry each name refers to. The entry from the
+    // highest versioned directory not above the release replaces the base
+    // entry (JEP 238)
+    fn resolve_entries(&mut self) {
+        let release = self.release.filter(|_| self.manifest.is_multi_release());
+        let mut entries: BTreeMap<String, JarEntry> = BTreeMap::new();
+        let mut entry_errors = Vec::new();
+        for index in 0..self.archive.len() {
+            let file = match self.archive.by_index_raw(index) {
+                Ok(file) => file,
+                Err(err) => {
+                    entry_errors.push((index, err.into()));
+                    continue;
+                }
+            };
+            if file.is_dir() {
+                continue;
+            }
+            let path = file.name().to_string();
+            let size = file.size();
+            let (name, entry_release) = match versioned_name(&path) {
+                Some((version, name)) => match release {
+                    Some(release) if version <= release => (name.to_string(), Some(version)),
+                    // Versioned entries above the release are hidden while
+                    // jars which aren't resolved keep them under their path
+                    Some(_) => continue,
+                    None => (path.clone(), None),
+                },
+                None => (path.clone(), None),
+            };
+            let replaces = entries.get(&name)
+                .is_none_or(|existing| existing.release < entry_release);
+            if replaces {
+                entries.insert(name.clone(), JarEntry { name, path, release: entry_release, size, index });
+            }
+        }
+        self.entries = entries;
+        self.entry_errors = entry_errors;
+    }
+
+    fn read_index(&mut self, index: usize) -> Result<Vec<u8>, JarError> {
+        read_file(self.archive.by_index(index)?)
+    }
+}
+
+fn read_file(file: ZipFile<'_>) -> Result<Vec<u8>, JarError> {
+    let name = file.name().to_string();
+    let size = file.size();
+    if size > MAX_ENTRY_SIZE {
+        return Err(JarError::EntryTooLarge { name, size, max: MAX_ENTRY_SIZE });
+    }
+    // The recorded size is only a hint so the buffer grows as it is read
+    let mut bytes = Vec::with_capacity((size as usize).min(MAX_PREALLOCATE));
+    file.take(MAX_ENTRY_SIZE + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > MAX_ENTRY_SIZE {
+        return Err(JarError::EntryTooLarge { name, size: bytes.len() as u64, max: MAX_ENTRY_SIZE });
+    }
+    Ok(bytes)
+}
+
+// Splits META-INF/versions/N/name into the release and name
+fn versioned_name(path: &str) -> Option<(u16, &str)> {
+    let (version, name) = path.strip_prefix(VERSIONS_PREFIX)?.split_once('/')?;
+    let version = version.parse::<u16>().ok()
+        .filter(|version| *version >= MIN_VERSIONED_RELEASE)?;
+    Some((version, name)).filter(|(_, name)| !name.is_empty())
+}
+
+/// Iterator over the parsed classes of a jar, see `JarReader::classes`
+pub struct JarClasses<'a, R: Read + Seek> {
+    jar: &'a mut JarReader<R>,
+    names: std::vec::IntoIter<String>,
+}
+
+impl<R: Read + Seek> Iterator for JarClasses<'_, R> {
+    // The internal name of the class and the result of parsing it
+    type Item = (String, Result<Class, JarError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let name = self.names.next()?;
+        let class = self.jar.read_class(&name);
+        Some((name, class))
+    }
+}
@@ -1,17 +1,41 @@
-///! This is a rust library for working with Java class files
+//! This is a rust library for working with Java class files
 pub mod io;
+pub mod mutf8;
 pub mod error;
 pub mod class;
 pub mod decomp;
+pub mod jar;
+pub mod provider;
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
     use std::io::{Cursor, stdout};
+    use std::path::Path;
+    use std::sync::Arc;
 
-    use crate::class::access::{AccessFlag, AccessFlags};
-    use crate::class::class::Class;
+    use crate::class::access::{AccessFlag, AccessFlags, ClassAccessFlag, FieldAccessFlag, MethodAccessFlag, ModuleAccessFlag, ParameterAccessFlag};
+    use crate::class::annotation::{ElementValue, SUPERCLASS_INDEX, TargetInfo, TargetType, type_annotations, TypeAnnotation, TypePathEntry, TypePathKind};
+    use crate::class::attribute::{Attribute, AttributeValue, BootstrapMethod, CodeAttr};
+    use crate::class::builder::ConstantPoolBuilder;
+    use crate::class::class::{Class, ClassHeader, ClassPath, Feature, MajorVersion, NestingKind, SourceVersion};
+    use crate::class::constant::{Constant, ConstantPool, ConstantTag, DynamicConstant, LoadableConstant, MemberReferenceU, MethodHandle, NameAndTypeIndex, ReferenceKind};
+    use crate::class::descriptor::{ArrayDescriptor, Descriptor, MethodDescriptor};
+    use crate::class::dump::ClassDumper;
+    use crate::class::frame::{expand_frames, Frame, StackMapFrame, VerificationType};
+    use crate::class::member::Member;
+    use crate::class::op::parse_code;
+    use crate::class::signature::{ClassSignature, JavaNames, MethodSignature, type_parameters_to_java, TypeArgument, TypeSignature};
+    use crate::class::validate::{ConstantDiagnostic, ConstantRule};
+    use crate::class::view::ClassView;
+    use crate::class::xref::{AttributeOwner, CrossReferences, ReferenceSource};
+    use crate::decomp::ast::gen_control_flow_graph;
     use crate::decomp::writer::JavaWriter;
-    use crate::io::Readable;
+    use crate::error::{ConstantError, DecompileError, DescriptorError, JarError, ProviderError, ReadError, SignatureError, WriteError};
+    use crate::io::{ParseLimits, Readable, Writable};
+    use crate::jar::JarReader;
+    use crate::provider::{ClassProvider, CompositeProvider, DirectoryProvider, JarProvider, JmodProvider, MemoryProvider};
+    use crate::mutf8;
 
     #[test]
     fn io_works() {
@@ -30,11 +54,1287 @@ mod tests {
         }
     }
 
+    #[test]
+    fn write_round_trip() {
+        for arr in [&include_bytes!("../Test.class")[..], &include_bytes!("../Main.class")[..]] {
+            let class = Class::read(&mut Cursor::new(arr)).unwrap();
+            let mut out = Vec::new();
+            class.write(&mut out).unwrap();
+            assert_eq!(out.as_slice(), arr);
+        }
+        for entry in std::fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|value| value == "class") {
+                let arr = std::fs::read(&path).unwrap();
+                let class = Class::read(&mut Cursor::new(&arr)).unwrap();
+                let mut out = Vec::new();
+                class.write(&mut out).unwrap();
+                assert_eq!(out, arr, "{:?}", path);
+            }
+        }
+    }
+
+    #[test]
+    fn write_duplicate_constants() {
+        let mut class = Class::read(&mut Cursor::new(include_bytes!("../Test.class"))).unwrap();
+        // A second copy of the name of the first method which the method refers to instead
+        let index = class.constant_pool.inner.iter().map(|(index, value)| index + value.width()).max().unwrap();
+        let name = class.methods[0].name.clone();
+        class.constant_pool.inner.insert(index, Constant::Utf8(name));
+        class.methods[0].name_index = index;
+        // And a second copy of the class constant of the class itself
+        let class_constant = class.constant_pool.inner[&class.this_class].clone();
+        class.constant_pool.inner.insert(index + 1, class_constant);
+        class.this_class = index + 1;
+        let mut out = Vec::new();
+        class.write(&mut out).unwrap();
+
+        let class = Class::read(&mut Cursor::new(&out)).unwrap();
+        assert_eq!(class.methods[0].name_index, index);
+        assert_eq!(class.this_class, index + 1);
+        let mut rewritten = Vec::new();
+        class.write(&mut rewritten).unwrap();
+        assert_eq!(rewritten, out);
+        // The dump shows the indexes that were read like javap
+        let dump = ClassDumper::new().dump_string(&class).unwrap();
+        assert!(dump.contains(&format!("this_class: #{} ", index + 1)));
+    }
+
+    #[test]
+    fn class_view() {
+        let arr = include_bytes!("../Test.class");
+        let view = ClassView::parse(arr).unwrap();
+        assert_eq!(view.class_name().unwrap(), "test/Test");
+        assert_eq!(view.constant_pool.inner.len(), u16::from_be_bytes([arr[8], arr[9]]) as usize);
+        // The pool size isn't trusted before the constants are read
+        assert!(ClassView::parse(&[&arr[..8], &[0xFF, 0xFF]].concat()).is_err());
+        assert_eq!(view.super_class_name().unwrap().unwrap(), "java/lang/Object");
+
+        let main = view.methods.iter()
+            .find(|method| method.name(&view.constant_pool).unwrap() == "main")
+            .unwrap();
+        let code = main.code(&view.constant_pool, &view.limits).unwrap().unwrap();
+        assert!(!parse_code(code.code).unwrap().is_empty());
+
+        // Converting the view should produce the same class as reading it
+        let mut out = Vec::new();
+        view.to_class().unwrap().write(&mut out).unwrap();
+        assert_eq!(out.as_slice(), &arr[..]);
+    }
+
+    #[test]
+    fn lazy_read() {
+        let arr = include_bytes!("../Test.class");
+        let header = ClassHeader::read(&mut Cursor::new(arr)).unwrap();
+        assert_eq!(header.class_path.internal_path(), "test/Test");
+        assert!(header.interfaces.is_empty());
+
+        let mut class = Class::read_lazy(&mut Cursor::new(arr), &ParseLimits::default()).unwrap();
+        let code = class.methods[0].attributes.iter()
+            .find(|attribute| attribute.name == "Code")
+            .unwrap();
+        assert!(matches!(code.value, AttributeValue::Undecoded(_)));
+
+        // Undecoded attributes are written back out as is
+        let mut out = Vec::new();
+        class.write(&mut out).unwrap();
+        assert_eq!(out.as_slice(), &arr[..]);
+
+        class.decode_attributes(&ParseLimits::default()).unwrap();
+        let code = class.methods[0].attributes.iter()
+            .find(|attribute| attribute.name == "Code")
+            .unwrap();
+        assert!(matches!(code.value, AttributeValue::Code(_)));
+    }
+
+    #[test]
+    fn malformed_input() {
+        let arr = include_bytes!("../Test.class");
+        // Truncated and corrupted input must fail with an error rather than panic
+        for length in 0..arr.len() {
+            assert!(Class::read(&mut Cursor::new(&arr[..length])).is_err());
+            assert!(ClassView::parse(&arr[..length]).is_err());
+        }
+        for index in 0..arr.len() {
+            for value in [0x00, 0xc4, 0xff] {
+                let mut data = arr.to_vec();
+                data[index] = value;
+                let _ = Class::read(&mut Cursor::new(&data));
+                let _ = ClassView::parse(&data);
+                let _ = parse_code(&data[index..]);
+            }
+        }
+
+        let limits = ParseLimits { max_code_length: 4, ..ParseLimits::default() };
+        let result = Class::read_with_limits(&mut Cursor::new(arr), &limits);
+        assert!(matches!(result.unwrap_err().kind(), ReadError::LimitExceeded("code length", _, 4)));
+
+        let limits = ParseLimits { max_nesting_depth: 0, ..ParseLimits::default() };
+        let result = ClassView::parse_with_limits(arr, limits).unwrap().to_class();
+        assert!(matches!(result.unwrap_err().kind(), ReadError::LimitExceeded("nesting depth", _, _)));
+    }
+
+    #[test]
+    fn read_error_location() {
+        let arr = include_bytes!("../Test.class");
+
+        // The tag of the first constant follows the magic, version and pool size
+        let mut data = arr.to_vec();
+        data[10] = 0xEE;
+        let err = Class::read(&mut Cursor::new(&data)).unwrap_err();
+        assert!(matches!(err.kind(), ReadError::UnknownConstantTag(0xEE)));
+        let location = err.location().unwrap();
+        assert_eq!(location.offset, Some(11));
+        assert_eq!(location.path.to_string(), "constant_pool[1]");
+        let err = ClassView::parse(&data).unwrap_err();
+        assert_eq!(err.location(), Some(location));
+
+        // Corrupt the exception table length of the first method so that
+        // reading runs off the end of the Code attribute
+        let view = ClassView::parse(arr).unwrap();
+        let code = view.methods[0].attribute(&view.constant_pool, "Code").unwrap();
+        let start = code.data.as_ptr() as usize - arr.as_ptr() as usize;
+        let code_length = u32::from_be_bytes(code.data[4..8].try_into().unwrap()) as usize;
+        let table_length = start + 8 + code_length;
+        let end = start + code.data.len();
+        let mut data = arr.to_vec();
+        data[table_length] = 0xFF;
+        data[table_length + 1] = 0xFF;
+
+        let err = Class::read(&mut Cursor::new(&data)).unwrap_err();
+        let location = err.location().unwrap();
+        assert_eq!(location.offset, Some(end as u64));
+        assert!(location.path.to_string()
+            .starts_with("methods[0].attributes[\"Code\"].exception_table["));
+        let err = ClassView::parse(&data).unwrap().to_class().unwrap_err();
+        assert_eq!(err.location(), Some(location));
+
+        // Bytes left over after the SourceFile attribute at the end of the class
+        let mut data = arr.to_vec();
+        let length = data.len() - 6;
+        assert_eq!(data[length..length + 4], [0, 0, 0, 2]);
+        data[length + 3] = 3;
+        data.push(0);
+        let err = Class::read(&mut Cursor::new(&data)).unwrap_err();
+        assert!(matches!(err.kind(), ReadError::TrailingBytes(1)));
+        let location = err.location().unwrap();
+        assert_eq!(location.offset, Some(arr.len() as u64));
+        assert_eq!(location.path.to_string(), "attributes[\"SourceFile\"]");
+    }
+
+    #[test]
+    fn resolve_constants() {
+        let mut pool = ConstantPool { inner: HashMap::new() };
+        for (index, constant) in [
+            Constant::Utf8("java/lang/String".to_string()),
+            Constant::Class(1),
+            Constant::Utf8("valueOf".to_string()),
+            Constant::Utf8("(I)Ljava/lang/String;".to_string()),
+            Constant::NameAndType(NameAndTypeIndex { name_index: 3, descriptor_index: 4 }),
+            Constant::MethodRef(MemberReferenceU { class_index: 2, name_and_type_info: 5 }),
+            Constant::MethodHandle(MethodHandle { reference_kind: 6, reference_index: 6 }),
+            Constant::MethodType(4),
+            Constant::Utf8("[I".to_string()),
+            Constant::Class(9),
+            Constant::Utf8("value".to_string()),
+            Constant::Utf8("I".to_string()),
+            Constant::NameAndType(NameAndTypeIndex { name_index: 11, descriptor_index: 12 }),
+            Constant::Dynamic(DynamicConstant { bootstrap_method_attr_index: 0, name_and_type_index: 13 }),
+            Constant::Module(11),
+            Constant::Package(1),
+        ].into_iter().enumerate() {
+            pool.inner.insert(index as u16 + 1, constant);
+        }
+        let bootstrap_methods = [BootstrapMethod { method_ref: 7, arguments: vec![10, 8] }];
+
+        let handle = pool.get_method_handle(&7).unwrap();
+        assert_eq!(handle.kind, ReferenceKind::InvokeStatic);
+        assert_eq!(handle.reference.name_and_type.name, "valueOf");
+        assert_eq!(pool.get_method_type(&8).unwrap().parameters.len(), 1);
+        assert!(matches!(pool.get_loadable(&10).unwrap(), LoadableConstant::Class(Descriptor::Array(_))));
+        assert_eq!(pool.get_dynamic(&14).unwrap().name_and_type.name, "value");
+        assert_eq!(pool.get_bootstrap_method(&bootstrap_methods, 0).unwrap().arguments.len(), 2);
+        assert!(pool.get_bootstrap_method(&bootstrap_methods, 1).is_err());
+        assert_eq!(pool.get_module_name(&15).unwrap(), "value");
+        assert_eq!(pool.get_package_name(&16).unwrap(), "java/lang/String");
+        assert!(matches!(pool.get_loadable(&5), Err(ConstantError::NotLoadable(5))));
+
+        let member = Member {
+            access_flags: AccessFlags::default(),
+            name_index: 0,
+            name: "test".to_string(),
+            descriptor_index: 0,
+            descriptor: Descriptor::parse("()Ljava/lang/Object;"),
+            attributes: Vec::new(),
+        };
+        for (index, expected) in [
+            (10, "return int[].class;"),
+            (7, "return /* REF_invokeStatic */ String::valueOf;"),
+            (8, "return java.lang.invoke.MethodType.fromMethodDescriptorString(\"(I)Ljava/lang/String;\", null);"),
+            (14, "return /* dynamic constant */ String.valueOf(\"value\", int.class, int[].class, \
+                java.lang.invoke.MethodType.fromMethodDescriptorString(\"(I)Ljava/lang/String;\", null));"),
+        ] {
+            // ldc index; areturn
+            let code = CodeAttr {
+                max_stack: 1,
+                max_locals: 0,
+                code: vec![0x12, index, 0xb0],
+                exception_table: Vec::new(),
+                attributes: Vec::new(),
+            };
+            let blocks = gen_control_flow_graph(&parse_code(&code.code).unwrap());
+            let statements = blocks[&0].decompile(&pool, &bootstrap_methods, SourceVersion::new(MajorVersion::Java17, 0)).unwrap();
+            let mut out = Vec::new();
+            statements[0].write_java(&mut out, &member, &code).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn validate_constant_pool() {
+        let class = Class::read(&mut Cursor::new(include_bytes!("../Test.class"))).unwrap();
+        assert!(class.constant_pool.validate().is_empty());
+
+        let mut pool = ConstantPool { inner: HashMap::new() };
+        for (index, constant) in [
+            (1, Constant::Integer(5)),
+            (2, Constant::Class(1)),
+            (3, Constant::Long(7)),
+            (5, Constant::String(4)),
+            (6, Constant::Utf8("value".to_string())),
+            (7, Constant::Utf8("()V".to_string())),
+            (8, Constant::NameAndType(NameAndTypeIndex { name_index: 6, descriptor_index: 7 })),
+            (9, Constant::Utf8("test/Test".to_string())),
+            (10, Constant::Class(9)),
+            (11, Constant::FieldRef(MemberReferenceU { class_index: 10, name_and_type_info: 8 })),
+            (12, Constant::MethodHandle(MethodHandle { reference_kind: 8, reference_index: 11 })),
+        ] {
+            pool.inner.insert(index, constant);
+        }
+        let diagnostics = pool.validate();
+        assert_eq!(diagnostics, vec![
+            ConstantDiagnostic {
+                index: 2,
+                rule: ConstantRule::WrongReferenceType {
+                    field: "name_index",
+                    target: 1,
+                    expected: "Utf8",
+                    found: ConstantTag::Integer,
+                },
+            },
+            ConstantDiagnostic { index: 5, rule: ConstantRule::MissingReference { field: "string_index", target: 4 } },
+            ConstantDiagnostic { index: 11, rule: ConstantRule::ExpectedFieldDescriptor("()V".to_string()) },
+            ConstantDiagnostic {
+                index: 12,
+                rule: ConstantRule::WrongReferenceType {
+                    field: "reference_index",
+                    target: 11,
+                    expected: "Methodref",
+                    found: ConstantTag::FieldRef,
+                },
+            },
+        ]);
+        assert_eq!(diagnostics[1].to_string(), "#5: string_index references #4 which is not a usable constant");
+    }
+
+    #[test]
+    fn pool_builder() {
+        let mut builder = ConstantPoolBuilder::new();
+        let string = builder.string("hello").unwrap();
+        assert_eq!(string, 2);
+        assert_eq!(builder.string("hello").unwrap(), string);
+        // Long and Double take up two indexes
+        assert_eq!(builder.long(5).unwrap(), 3);
+        assert_eq!(builder.double(1.5).unwrap(), 5);
+        assert_eq!(builder.integer(1).unwrap(), 7);
+        assert_eq!(builder.long(5).unwrap(), 3);
+        let method = builder.method_ref("java/io/PrintStream", "println", "(Ljava/lang/String;)V").unwrap();
+        let handle = builder.method_handle(ReferenceKind::InvokeVirtual, method).unwrap();
+        assert_eq!(builder.method_handle(ReferenceKind::InvokeVirtual, method).unwrap(), handle);
+        assert_eq!(builder.pool().get_method_handle(&handle).unwrap().reference.name_and_type.name, "println");
+        assert!(builder.pool().validate().is_empty());
+
+        // Constants can't be added past the limit of the pool
+        while builder.size() < 65534 {
+            builder.integer(builder.size() as i32).unwrap();
+        }
+        assert!(matches!(builder.long(-1), Err(WriteError::PoolFull)));
+        assert_eq!(builder.integer(-1).unwrap(), 65534);
+        assert!(matches!(builder.integer(-2), Err(WriteError::PoolFull)));
+        // Groups of constants are only added when they all fit
+        let mut builder = ConstantPoolBuilder::new();
+        while builder.size() < 65533 {
+            builder.integer(builder.size() as i32).unwrap();
+        }
+        assert!(matches!(builder.method_ref("a/B", "c", "()V"), Err(WriteError::PoolFull)));
+        assert_eq!(builder.integer(-1).unwrap(), 65533);
+        assert!(matches!(builder.class("a/B"), Err(WriteError::PoolFull)));
+        assert_eq!(builder.size(), 65534);
+        assert!(matches!(builder.utf8(&"a".repeat(65536)), Err(WriteError::LengthOverflow(65536))));
+        assert!(matches!(builder.string(&"a".repeat(65536)), Err(WriteError::LengthOverflow(65536))));
+        assert_eq!(builder.size(), 65534);
+        // Values shared within a group only need a single utf8 constant
+        let mut builder = ConstantPoolBuilder::new();
+        while builder.size() < 65531 {
+            builder.integer(builder.size() as i32).unwrap();
+        }
+        assert_eq!(builder.method_ref("a", "a", "a").unwrap(), 65534);
+        assert!(matches!(builder.field_ref("a", "b", "a"), Err(WriteError::PoolFull)));
+
+        // Existing pools keep their indexes and are extended
+        let arr = include_bytes!("../Test.class");
+        let mut class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let object = class.constant_pool.find_class("java/lang/Object").unwrap();
+        let mut builder = ConstantPoolBuilder::from(class.constant_pool);
+        let size = builder.size();
+        assert_eq!(builder.class("java/lang/Object").unwrap(), object);
+        let added = builder.string("added").unwrap();
+        assert_eq!(added as u32, size + 1);
+        class.constant_pool = builder.build();
+        let mut out = Vec::new();
+        class.write(&mut out).unwrap();
+        let class = Class::read(&mut Cursor::new(&out)).unwrap();
+        assert!(matches!(class.constant_pool.get_loadable(&added), Ok(LoadableConstant::String(value)) if value == "added"));
+    }
+
+    #[test]
+    fn cross_references() {
+        let arr = include_bytes!("../Test.class");
+        let mut class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let references = CrossReferences::build(&class);
+        // The "Hello" string is only loaded by an ldc instruction
+        let hello = references.references_to(31);
+        assert_eq!(hello.len(), 1);
+        assert!(matches!(hello[0], ReferenceSource::Instruction { .. }));
+        assert_eq!(references.references_to(32), &[ReferenceSource::Constant { index: 31, field: "string_index" }]);
+        assert!(references.references_to(10).contains(&ReferenceSource::Class { field: "this_class" }));
+        // The array class is only used by the StackMapTable
+        assert!(references.is_complete());
+        assert_eq!(references.references_to(86), &[ReferenceSource::Attribute {
+            owner: AttributeOwner::Code(3),
+            name: "StackMapTable".to_string(),
+            field: "cpool_index",
+            element: Some(0),
+        }]);
+        assert!(references.unused().is_empty());
+
+        let mut builder = ConstantPoolBuilder::from(class.constant_pool);
+        let string = builder.string("unused").unwrap();
+        let value = builder.pool().find_utf8("unused").unwrap();
+        class.constant_pool = builder.build();
+        let references = CrossReferences::build(&class);
+        assert_eq!(references.unreferenced(), vec![string]);
+        assert_eq!(references.unused(), vec![value, string]);
+
+        // A duplicate name used by a method is referenced by the index it was read from
+        let duplicate = class.constant_pool.inner.iter().map(|(index, value)| index + value.width()).max().unwrap();
+        class.constant_pool.inner.insert(duplicate, Constant::Utf8(class.methods[0].name.clone()));
+        class.methods[0].name_index = duplicate;
+        let references = CrossReferences::build(&class);
+        assert_eq!(references.references_to(duplicate), &[ReferenceSource::Method { index: 0, field: "name_index" }]);
+        assert!(!references.unused().contains(&duplicate));
+
+        // Code which can't be parsed is recorded without losing the other references
+        let code = class.methods.iter_mut().position(|method| method.code().is_some()).unwrap();
+        for attribute in &mut class.methods[code].attributes {
+            if let AttributeValue::Code(value) = &mut attribute.value {
+                value.code = vec![0xFF];
+            }
+        }
+        let references = CrossReferences::build(&class);
+        assert!(!references.is_complete());
+        assert_eq!(references.code_errors().len(), 1);
+        assert_eq!(references.code_errors()[0].0, code);
+        assert!(references.references_to(10).contains(&ReferenceSource::Class { field: "this_class" }));
+    }
+
+    #[test]
+    fn class_dump() {
+        let arr = include_bytes!("../Test.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let dump = ClassDumper::new().dump_string(&class).unwrap();
+        assert_eq!(dump.lines().collect::<Vec<_>>(), include_str!("../testjp").lines().collect::<Vec<_>>());
+
+        let dumper = ClassDumper { include_private: true };
+        let dump = dumper.dump_string(&class).unwrap();
+        assert!(dump.contains("  private final int[][][] x;\n    descriptor: [[[I\n    flags: (0x0012) ACC_PRIVATE, ACC_FINAL\n"));
+    }
+
+    #[test]
+    fn annotations() {
+        let arr = include_bytes!("../tests/Annotated.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let pool = &class.constant_pool;
+        let annotations: Vec<_> = class.annotations().collect();
+        assert_eq!(annotations.len(), 2);
+        let values = annotations[0];
+        assert_eq!(values.type_descriptor(pool).unwrap().to_internal_java(), "LAnnotated$Values;");
+        match values.element(pool, "kind").unwrap() {
+            ElementValue::Enum { type_name_index, const_name_index } => {
+                assert_eq!(pool.get_utf8(type_name_index).unwrap(), "Ljava/lang/annotation/ElementType;");
+                assert_eq!(pool.get_utf8(const_name_index).unwrap(), "TYPE");
+            }
+            value => panic!("expected enum value got {:?}", value)
+        }
+        match values.element(pool, "type").unwrap() {
+            ElementValue::Class(index) => assert_eq!(pool.get_utf8(index).unwrap(), "[Ljava/lang/String;"),
+            value => panic!("expected class value got {:?}", value)
+        }
+        match values.element(pool, "tags").unwrap() {
+            ElementValue::Array(values) => assert_eq!(values.len(), 2),
+            value => panic!("expected array value got {:?}", value)
+        }
+        match values.element(pool, "nested").unwrap() {
+            ElementValue::Annotation(nested) => match nested.element(pool, "value").unwrap() {
+                ElementValue::Const { tag: b's', const_value_index } => {
+                    assert_eq!(pool.get_utf8(const_value_index).unwrap(), "inner")
+                }
+                value => panic!("expected string value got {:?}", value)
+            },
+            value => panic!("expected annotation value got {:?}", value)
+        }
+        assert!(values.element(pool, "count").is_none());
+
+        // Marker has class retention so is stored in the invisible annotations
+        let field: Vec<_> = class.fields[0].annotations()
+            .map(|annotation| annotation.type_descriptor(pool).unwrap().to_internal_java())
+            .collect();
+        assert_eq!(field, ["Ljava/lang/Deprecated;", "LAnnotated$Marker;"]);
+        let method = &class.methods[1];
+        assert_eq!(method.parameter_annotations(0).count(), 1);
+        assert_eq!(method.parameter_annotations(1).next().unwrap().type_descriptor(pool).unwrap().to_internal_java(), "LAnnotated$Tag;");
+
+        let mut out = Vec::new();
+        JavaWriter {}.write_class(&class, &MemoryProvider::new(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("@Values(name = \"class\", kind = ElementType.TYPE, type = String[].class, tags = {\"a\", \"b\"}, nested = @Tag(\"inner\"))\n@Deprecated\npublic class Annotated"));
+        assert!(out.contains("    @Deprecated\n    @Marker\n    public int field;"));
+        assert!(out.contains("    @Tag(\"method\")\n    public void method(@Marker String p_0, @Tag(\"b\") int p_1)"));
+
+        let dump = ClassDumper::new().dump_string(&class).unwrap();
+        assert!(dump.contains("      parameter 1:\n        0: #20(#21=s#23)\n          Annotated$Tag(\n            value=\"b\"\n          )\n"));
+
+        let arr = include_bytes!("../tests/Annotated$Values.class");
+        let defaults = Class::read(&mut Cursor::new(arr)).unwrap();
+        let method = defaults.methods.iter().find(|method| method.name == "d").unwrap();
+        match &method.attributes[0].value {
+            AttributeValue::AnnotationDefault(ElementValue::Const { tag: b'D', const_value_index }) => {
+                assert!(matches!(defaults.constant_pool.inner[const_value_index], Constant::Double(value) if value == 1.5))
+            }
+            value => panic!("expected double default got {:?}", value)
+        }
+        let dump = ClassDumper::new().dump_string(&defaults).unwrap();
+        assert!(dump.contains("    AnnotationDefault:\n      default_value: @#48(#49=s#50)\n        @Annotated$Tag(\n          value=\"default\"\n        )\n"));
+
+        // Annotations are written back out unchanged
+        for arr in [&include_bytes!("../tests/Annotated.class")[..], &arr[..]] {
+            let class = Class::read(&mut Cursor::new(arr)).unwrap();
+            let mut out = Vec::new();
+            class.write(&mut out).unwrap();
+            assert_eq!(out.as_slice(), arr);
+        }
+    }
+
+    #[test]
+    fn type_annotation() {
+        let arr = include_bytes!("../tests/TypeAnnotated.class");
+        let mut class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let pool = &class.constant_pool;
+        let name = |annotation: &TypeAnnotation| annotation.annotation.type_descriptor(pool).unwrap().to_internal_java();
+
+        let header: Vec<_> = type_annotations(&class.attributes).collect();
+        assert_eq!(header[0].target_type, TargetType::ClassExtends);
+        assert!(matches!(header[0].target_info, TargetInfo::Supertype { supertype_index: SUPERCLASS_INDEX }));
+        assert!(header.iter().any(|annotation| annotation.target_type == TargetType::ClassTypeParameterBound));
+
+        let values = class.fields.iter().find(|field| field.name == "values").unwrap();
+        let values: Vec<_> = type_annotations(&values.attributes).collect();
+        assert_eq!(values.iter().map(|annotation| name(annotation)).collect::<Vec<_>>(), ["LNonNull;", "LNullable;"]);
+        assert!(values[0].is_array_depth(0));
+        assert!(values[1].is_array_depth(1));
+
+        let list = class.fields.iter().find(|field| field.name == "list").unwrap();
+        let list = type_annotations(&list.attributes).next().unwrap();
+        assert_eq!(list.type_path, [TypePathEntry { kind: TypePathKind::TypeArgument, type_argument_index: 0 }]);
+
+        // Annotations on locals, casts and catch clauses are stored on the code attribute
+        let body = class.methods.iter().find(|method| method.name == "body").unwrap();
+        let code = body.attributes.iter()
+            .find_map(|attribute| match &attribute.value {
+                AttributeValue::Code(code) => Some(code),
+                _ => None
+            })
+            .unwrap();
+        let code: Vec<_> = type_annotations(&code.attributes).collect();
+        assert!(code.iter().any(|annotation| matches!(
+            &annotation.target_info,
+            TargetInfo::LocalVariable(table) if annotation.target_type == TargetType::ResourceVariable && table[0].index == 3
+        )));
+        assert!(code.iter().any(|annotation| matches!(annotation.target_info, TargetInfo::Catch { exception_table_index: 3 })));
+        assert!(code.iter().any(|annotation| annotation.target_type == TargetType::Cast
+            && matches!(annotation.target_info, TargetInfo::TypeArgument { type_argument_index: 0, .. })));
+
+        let dump = ClassDumper::new().dump_string(&class).unwrap();
+        assert!(dump.contains("    RuntimeVisibleTypeAnnotations:\n      0: #43(): FIELD, location=[TYPE_ARGUMENT(0)]\n        NonNull\n"));
+        assert!(dump.contains("        0: #47(): EXCEPTION_PARAMETER, exception_index=3\n"));
+        assert!(dump.contains("  0: #43(): CLASS_EXTENDS, type_index=65535\n"));
+
+        // Type annotations are written back out unchanged
+        let mut out = Vec::new();
+        class.write(&mut out).unwrap();
+        assert_eq!(out.as_slice(), &arr[..]);
+
+        // The try-with-resources body can't be decompiled yet
+        class.methods.retain(|method| method.name != "body");
+        let mut out = Vec::new();
+        JavaWriter {}.write_class(&class, &MemoryProvider::new(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("public class TypeAnnotated<T> implements @Nullable java.lang.Runnable {"));
+        assert!(out.contains("    public @NonNull String name;"));
+        assert!(out.contains("    public String @NonNull [] @Nullable [] values;"));
+        // Annotations on both the declaration and the type are only written once
+        assert!(out.contains("    @Both\n    public String both;"));
+        assert!(out.contains("    public @NonNull String method(@NonNull TypeAnnotated this, @Nullable String p_0)"));
+    }
+
+    #[test]
+    fn stack_map_frames() {
+        let arr = include_bytes!("../tests/Frames.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let method = |name: &str| class.methods.iter().find(|method| method.name == name).unwrap();
+        let object = |name: &str| VerificationType::object(ClassPath::from(name));
+
+        // this is uninitialized until the other constructor is called
+        let constructor = &class.methods[0];
+        assert_eq!(Frame::initial(&class.class_path, constructor).locals, [VerificationType::UninitializedThis, VerificationType::Integer]);
+        let frames = constructor.stack_map_frames(&class.class_path).unwrap();
+        assert_eq!(frames[0].stack, [VerificationType::UninitializedThis]);
+        assert_eq!(frames[1].stack, [VerificationType::UninitializedThis, VerificationType::Integer]);
+        assert_eq!(frames[1].offset, frames[0].offset + 1);
+        // Constructors without branches have no frames
+        assert!(class.methods[1].stack_map_frames(&class.class_path).unwrap().is_empty());
+
+        let frames = method("uninitialized").stack_map_frames(&class.class_path).unwrap();
+        assert_eq!(frames[1].stack, [VerificationType::Uninitialized(0), VerificationType::Uninitialized(0), object("java/lang/String")]);
+
+        // Frames are expanded using the deltas of the frames before them
+        let frames = method("wide").stack_map_frames(&class.class_path).unwrap();
+        assert_eq!(frames.iter().map(|frame| frame.offset).collect::<Vec<_>>(), [15, 45, 51]);
+        assert_eq!(frames[0].locals, frames[1].locals);
+        assert_eq!(frames[2].locals, frames[0].locals[..4]);
+        assert_eq!(frames[0].locals_size(), 10);
+        assert_eq!(frames[0].local(0), Some(&VerificationType::Long));
+        assert_eq!(frames[0].local(1), Some(&VerificationType::Top));
+        assert_eq!(frames[0].local(2), Some(&VerificationType::Double));
+        assert_eq!(frames[0].local(4), Some(&object("[I")));
+        assert_eq!(frames[0].local(9), Some(&VerificationType::Integer));
+        assert_eq!(frames[0].local(10), None);
+
+        let frames = method("nulls").stack_map_frames(&class.class_path).unwrap();
+        assert_eq!(frames[0].locals, [object("java/lang/Object"), object("java/lang/String")]);
+
+        let dump = ClassDumper::new().dump_string(&class).unwrap();
+        assert!(dump.contains("        frame_type = 73 /* same_locals_1_stack_item */\n          stack = [ this ]\n"));
+        assert!(dump.contains("          locals = [ long, double, class \"[I\", double, class \"[I\", int, int ]\n          stack = []\n"));
+
+        // Frames are written back out unchanged
+        let mut out = Vec::new();
+        class.write(&mut out).unwrap();
+        assert_eq!(out.as_slice(), &arr[..]);
+
+        // Extended forms are used when the offset delta doesn't fit the short form
+        let pool = &class.constant_pool;
+        for (frame, frame_type) in [
+            (StackMapFrame::Same { offset_delta: 64 }, 251),
+            (StackMapFrame::SameLocals1StackItem { offset_delta: 100, stack: VerificationType::Null }, 247),
+            (StackMapFrame::SameLocals1StackItemExtended { offset_delta: 3, stack: object("java/lang/String") }, 247),
+        ] {
+            let mut out = Vec::new();
+            frame.write(&mut out, pool).unwrap();
+            assert_eq!(out[0], frame_type);
+            let read = StackMapFrame::read(&mut Cursor::new(&out), pool).unwrap();
+            assert_eq!(read.offset_delta(), frame.offset_delta());
+            assert_eq!(read.verification_types(), frame.verification_types());
+        }
+        assert!(matches!(StackMapFrame::read(&mut Cursor::new([128u8]), pool), Err(ReadError::UnknownFrameType(128))));
+        let initial = Frame { offset: 0, locals: Vec::new(), stack: Vec::new() };
+        assert!(expand_frames(&initial, &[StackMapFrame::Chop { offset_delta: 0, count: 1 }]).is_err());
+
+        // Types keep the class constant they were read from even if it is a duplicate
+        let mut class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let string = class.constant_pool.find_class("java/lang/String").unwrap();
+        let duplicate = class.constant_pool.inner.iter().map(|(index, value)| index + value.width()).max().unwrap();
+        let constant = class.constant_pool.inner[&string].clone();
+        class.constant_pool.inner.insert(duplicate, constant);
+        let mut out = Vec::new();
+        VerificationType::Object(duplicate, ClassPath::from("java/lang/String")).write(&mut out, &class.constant_pool).unwrap();
+        assert_eq!(out, [7, (duplicate >> 8) as u8, duplicate as u8]);
+        let read = VerificationType::read(&mut Cursor::new(&out), &class.constant_pool).unwrap();
+        assert!(matches!(read, VerificationType::Object(index, _) if index == duplicate));
+        assert_eq!(read, object("java/lang/String"));
+        // Types built in code use the first matching constant
+        let mut out = Vec::new();
+        object("java/lang/String").write(&mut out, &class.constant_pool).unwrap();
+        assert_eq!(out, [7, (string >> 8) as u8, string as u8]);
+    }
+
+    #[test]
+    fn module_info() {
+        let arr = include_bytes!("../tests/module-info.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let pool = &class.constant_pool;
+        assert!(class.is_module());
+        let module = class.module().unwrap();
+        assert_eq!(module.name(pool).unwrap(), "com.example.app");
+        assert_eq!(module.version(pool).unwrap().unwrap(), "1.2");
+        assert!(!module.is_open());
+
+        // java.base is always required but is mandated rather than declared
+        let requires: Vec<_> = module.requires.iter()
+            .map(|value| (value.module(pool).unwrap().as_str(), value.is_mandated(), value.is_transitive(), value.is_static_phase()))
+            .collect();
+        assert_eq!(requires, [
+            ("java.base", true, false, false),
+            ("java.logging", false, true, false),
+            ("java.sql", false, false, true),
+            ("java.net.http", false, false, false),
+        ]);
+        assert!(module.requires[0].version(pool).unwrap().is_some());
+        assert_eq!(module.exports[1].package(pool).unwrap(), "com/example/app/spi");
+        assert_eq!(module.exports[1].to_modules(pool).unwrap(), ["java.logging", "java.sql"]);
+        assert!(module.exports[0].to_modules(pool).unwrap().is_empty());
+        assert_eq!(module.opens[0].to_modules(pool).unwrap(), ["java.logging"]);
+        assert_eq!(module.uses(pool).unwrap()[0].full_path(), "com.example.app.spi.Service");
+        let implementations: Vec<_> = module.provides[0].implementations(pool).unwrap().iter()
+            .map(|value| value.full_path())
+            .collect();
+        assert_eq!(implementations, ["com.example.app.Main", "com.example.app.Other"]);
+        assert_eq!(class.module_packages().unwrap(), ["com/example/app", "com/example/app/spi"]);
+        assert_eq!(class.module_main_class().unwrap().unwrap().full_path(), "com.example.app.Main");
+
+        let mut out = Vec::new();
+        JavaWriter {}.write_class(&class, &MemoryProvider::new(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "@Deprecated
+module com.example.app {
+    requires transitive java.logging;
+    requires static java.sql;
+    requires java.net.http;
+
+    exports com.example.app;
+    exports com.example.app.spi to java.logging, java.sql;
+
+    opens com.example.app.spi to java.logging;
+
+    uses com.example.app.spi.Service;
+
+    provides com.example.app.spi.Service with com.example.app.Main, com.example.app.Other;
+}");
+
+        let dump = ClassDumper::new().dump_string(&class).unwrap();
+        assert!(dump.starts_with("module com.example.app@1.2\n"));
+        assert!(dump.contains("    #12,0                                   // com/example/app/spi to ... 2\n      #17                                     // ... to \"java.logging\"\n"));
+        assert!(dump.contains("ModuleMainClass: #8                     // com.example.app.Main\n"));
+
+        let references = CrossReferences::build(&class);
+        assert!(references.unused().is_empty());
+
+        let mut out = Vec::new();
+        class.write(&mut out).unwrap();
+        assert_eq!(out.as_slice(), &arr[..]);
+    }
+
+    #[test]
+    fn records() {
+        let arr = include_bytes!("../tests/Shapes$Group.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        assert!(class.is_record());
+        let components = class.record_components().unwrap();
+        let names: Vec<_> = components.iter()
+            .map(|value| value.name.as_str())
+            .collect();
+        assert_eq!(names, ["name", "shapes"]);
+        assert_eq!(class.nest_host().unwrap().unwrap().full_path(), "Shapes");
+        assert!(!class.is_sealed());
+
+        let mut out = Vec::new();
+        JavaWriter {}.write_class(&class, &MemoryProvider::new(), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("public record Group(String name, List<Shape> shapes) implements Shapes.Shape {\n"));
+        // Accessors, equals, hashCode and toString are generated by javac
+        assert!(!text.contains("shapes()"));
+        assert!(!text.contains("hashCode"));
+
+        let dump = ClassDumper::new().dump_string(&class).unwrap();
+        assert!(dump.contains("NestHost: class Shapes\n"));
+        assert!(dump.contains("Record:\n  java.lang.String name;\n    descriptor: Ljava/lang/String;\n\n"));
+
+        let references = CrossReferences::build(&class);
+        assert!(references.unused().is_empty());
+
+        let mut out = Vec::new();
+        class.write(&mut out).unwrap();
+        assert_eq!(out.as_slice(), &arr[..]);
+
+        let arr = include_bytes!("../tests/Shapes$Shape.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        assert!(class.is_sealed());
+        let permitted: Vec<_> = class.permitted_subclasses().unwrap().iter()
+            .map(|value| value.full_path())
+            .collect();
+        assert_eq!(permitted, ["Shapes.Circle", "Shapes.Square", "Shapes.Group"]);
+        let mut out = Vec::new();
+        JavaWriter {}.write_class(&class, &MemoryProvider::new(), &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("public sealed interface Shape permits Shapes.Circle, Shapes.Square, Shapes.Group {"));
+
+        let arr = include_bytes!("../tests/Shapes.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        assert!(class.nest_host().unwrap().is_none());
+        assert_eq!(class.nest_members().unwrap().len(), 4);
+        let dump = ClassDumper::new().dump_string(&class).unwrap();
+        assert!(dump.contains("NestMembers:\n  Shapes$Group\n  Shapes$Square\n"));
+    }
+
+    #[test]
+    fn local_variables() {
+        let arr = include_bytes!("../tests/Locals.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let pool = &class.constant_pool;
+        let method = class.methods.iter().find(|method| method.name == "first").unwrap();
+        let code = method.code().unwrap();
+        // Each table keeps its own variant rather than being read as a variable table
+        let types = code.attributes.iter()
+            .find_map(|attribute| match &attribute.value {
+                AttributeValue::LocalVariableTypeTable(values) => Some(values),
+                _ => None
+            })
+            .unwrap();
+        assert_eq!(types.len(), 4);
+        assert_eq!(types[2].name(pool).unwrap(), "map");
+        assert_eq!(types[2].signature(pool).unwrap().to_string(), "Ljava/util/Map<Ljava/lang/String;+TT;>;");
+
+        let locals = method.local_variables(pool).unwrap();
+        let names: Vec<_> = locals.variables.iter()
+            .map(|local| local.name.as_str())
+            .collect();
+        assert_eq!(names, ["this", "map", "keys", "empty", "key", "found"]);
+        let keys = locals.get(2, 0).unwrap();
+        assert_eq!(keys.descriptor.to_internal_java(), "[Ljava/lang/String;");
+        assert!(keys.signature.is_none());
+        let found = locals.get(7, 32).unwrap();
+        assert!(matches!(&found.signature, Some(TypeSignature::TypeVariable(name)) if name == "T"));
+        assert!(locals.get(7, 40).is_none());
+        let empty = locals.get(3, 48).unwrap();
+        assert_eq!(empty.signature.as_ref().unwrap().erasure().to_internal_java(), "[Ljava/util/List;");
+        match &empty.signature {
+            Some(TypeSignature::Array(component)) => match component.as_ref() {
+                TypeSignature::Class(class) => {
+                    assert_eq!(class.class_path().full_path(), "java.util.List");
+                    assert!(matches!(class.classes[0].type_arguments[..], [TypeArgument::Exact(TypeSignature::TypeVariable(_))]));
+                }
+                value => panic!("expected class signature got {:?}", value)
+            },
+            value => panic!("expected array signature got {:?}", value)
+        }
+
+        // Slots are reused by variables in different scopes
+        let method = class.methods.iter().find(|method| method.name == "sum").unwrap();
+        let locals = method.local_variables(pool).unwrap();
+        assert_eq!(locals.in_slot(4).count(), 1);
+        assert_eq!(locals.get(1, 10).unwrap().signature.as_ref().unwrap().to_string(), "Ljava/util/List<Ljava/lang/Integer;>;");
+
+        assert!(matches!(TypeSignature::parse("Ljava/util/List<I>;"), Err(SignatureError::Unexpected(16, 'I'))));
+        assert!(matches!(TypeSignature::parse("Ljava/util/List;;"), Err(SignatureError::Unexpected(16, ';'))));
+        assert!(matches!(TypeSignature::parse("Ljava/util/List"), Err(SignatureError::UnexpectedEnd)));
+        // Arrays are limited to 255 dimensions and type arguments to the nesting depth
+        let array = format!("{}I", "[".repeat(255));
+        assert_eq!(TypeSignature::parse(&array).unwrap().erasure().to_internal_java(), array);
+        let deep = format!("{}I", "[".repeat(20000));
+        assert!(matches!(TypeSignature::parse(&deep), Err(SignatureError::TooManyDimensions(0))));
+        let nested = |depth: usize| format!("{}TT;{};", "Ljava/util/List<".repeat(depth), ">;".repeat(depth).trim_end_matches(';'));
+        assert!(TypeSignature::parse(&nested(8)).is_ok());
+        assert!(matches!(TypeSignature::parse(&nested(20000)), Err(SignatureError::NestedTooDeep(_))));
+        let limits = ParseLimits { max_nesting_depth: 1, ..ParseLimits::default() };
+        assert!(matches!(TypeSignature::parse_with_limits(&nested(2), &limits), Err(SignatureError::NestedTooDeep(31))));
+
+        let mut out = Vec::new();
+        class.write(&mut out).unwrap();
+        assert_eq!(out.as_slice(), &arr[..]);
+    }
+
+    #[test]
+    fn generic_signatures() {
+        let arr = include_bytes!("../tests/Generics.class");
+        let mut class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let signature = class.signature().unwrap().unwrap();
+        let names: Vec<_> = signature.type_parameters.iter()
+            .map(|parameter| parameter.name.as_str())
+            .collect();
+        assert_eq!(names, ["K", "V"]);
+        // Interface only bounds leave the class bound empty
+        assert!(signature.type_parameters[0].class_bound.is_none());
+        assert_eq!(signature.type_parameters[0].to_java(JavaNames::Simple), "K extends Comparable<K>");
+        assert_eq!(signature.type_parameters[1].to_java(JavaNames::Simple), "V");
+        assert_eq!(signature.type_parameters[1].to_java(JavaNames::Qualified), "V extends java.lang.Object");
+        assert_eq!(signature.superclass.to_java(JavaNames::Qualified), "java.util.AbstractMap<K, V>");
+        assert_eq!(signature.to_string(), "<K::Ljava/lang/Comparable<TK;>;V:Ljava/lang/Object;>Ljava/util/AbstractMap<TK;TV;>;Ljava/io/Serializable;");
+
+        let field = |name: &str| class.fields.iter()
+            .find(|field| field.name == name)
+            .and_then(|field| field.field_signature().unwrap())
+            .unwrap();
+        assert_eq!(field("index").to_java(JavaNames::Simple), "Map<String, ? extends List<K>>");
+        assert_eq!(field("buckets").to_java(JavaNames::Simple), "List<? super V>[]");
+        assert_eq!(field("head").to_java(JavaNames::Qualified), "Generics<K, V>.Node<?>");
+        assert_eq!(field("head").erasure().to_internal_java(), "LGenerics$Node;");
+
+        let method = class.methods.iter().find(|method| method.name == "attempt").unwrap();
+        let signature = method.method_signature().unwrap().unwrap();
+        assert_eq!(signature.parameters.len(), 2);
+        assert!(matches!(&signature.return_type, TypeSignature::TypeVariable(name) if name == "T"));
+        assert!(matches!(&signature.throws[..], [TypeSignature::TypeVariable(name)] if name == "E"));
+        assert_eq!(type_parameters_to_java(&signature.type_parameters, JavaNames::Simple), "<T, E extends Exception>");
+        assert_eq!(signature.to_string(), "<T:Ljava/lang/Object;E:Ljava/lang/Exception;>(Ljava/util/function/Supplier<TT;>;TE;)TT;^TE;");
+
+        assert!(matches!(MethodSignature::parse("()V^[Ljava/lang/Exception;"), Err(SignatureError::Unexpected(4, '['))));
+        assert!(matches!(ClassSignature::parse("<T:I>Ljava/lang/Object;"), Err(SignatureError::Unexpected(3, 'I'))));
+        assert!(matches!(ClassSignature::parse("<>Ljava/lang/Object;"), Err(SignatureError::EmptyIdentifier(1))));
+
+        let dump = ClassDumper::new().dump_string(&class).unwrap();
+        assert!(dump.starts_with("public class Generics<K extends java.lang.Comparable<K>, V extends java.lang.Object> extends java.util.AbstractMap<K, V> implements java.io.Serializable\n"));
+        assert!(dump.contains("\n  public java.util.List<? super V>[] buckets;\n"));
+        assert!(dump.contains("\n  public java.util.Set<java.util.Map$Entry<K, V>> entrySet();\n"));
+        assert!(dump.contains("\n  public final <T extends java.lang.Object> int count(java.util.List<? extends T>...);\n"));
+
+        // The bodies of these can't be decompiled yet
+        class.methods.retain(|method| method.name != "attempt" && method.name != "count");
+        let mut out = Vec::new();
+        JavaWriter {}.write_class(&class, &MemoryProvider::new(), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("import java.util.Map.Entry;\n"));
+        assert!(!text.contains("import Generics;"));
+        assert!(text.contains("public class Generics<K extends Comparable<K>, V> extends AbstractMap<K, V> implements Serializable {\n"));
+        assert!(text.contains("    public Map<String, ? extends List<K>> index;\n"));
+        assert!(text.contains("    public Generics<K, V>.Node<?> head;\n"));
+        assert!(text.contains("    public Set<Entry<K, V>> entrySet() {\n"));
+    }
+
+    #[test]
+    fn hostile_signatures() {
+        let mut class = Class::read(&mut Cursor::new(include_bytes!("../Test.class"))).unwrap();
+        let pool = std::mem::replace(&mut class.constant_pool, ConstantPool { inner: HashMap::new() });
+        let mut builder = ConstantPoolBuilder::from(pool);
+        let name_index = builder.utf8("Signature").unwrap();
+        let deep = format!("{}I", "[".repeat(20000));
+        let nested = format!("Ljava/lang/Object;{}TT;{};", "Ljava/util/List<".repeat(3000), ">;".repeat(3000));
+        let mut signature = |value: &str| Attribute {
+            name_index,
+            name: "Signature".to_string(),
+            value: AttributeValue::Signature(builder.utf8(value).unwrap(), value.to_string()),
+        };
+        class.attributes.push(signature(&nested));
+        class.fields[0].attributes.push(signature(&deep));
+        class.methods[0].attributes.push(signature(&format!("(){}", deep)));
+        class.constant_pool = builder.build();
+
+        // Signatures which can't be parsed fall back to the descriptors
+        assert!(class.signature().is_err());
+        assert!(class.fields[0].field_signature().is_err());
+        assert!(!class.collect_imports().is_empty());
+        assert!(ClassDumper::new().dump_string(&class).is_ok());
+        let mut out = Vec::new();
+        assert!(JavaWriter {}.write_class(&class, &MemoryProvider::new(), &mut out).is_ok());
+    }
+
+    #[test]
+    fn descriptors() {
+        let descriptor = MethodDescriptor::parse("(Ljava/lang/String;ILjava/lang/Object;[[J)V").unwrap();
+        let parameters: Vec<_> = descriptor.parameters.iter()
+            .map(Descriptor::to_internal_java)
+            .collect();
+        assert_eq!(parameters, ["Ljava/lang/String;", "I", "Ljava/lang/Object;", "[[J"]);
+        assert!(matches!(*descriptor.return_type, Descriptor::Void));
+        assert!(matches!(Descriptor::parse_field("[Ljava/util/Map$Entry;"), Ok(Descriptor::Array(ArrayDescriptor { dimensions: 1, .. }))));
+
+        assert_eq!(MethodDescriptor::parse("(IX)V").unwrap_err(), DescriptorError::Unexpected(2, 'X'));
+        assert_eq!(MethodDescriptor::parse("(I").unwrap_err(), DescriptorError::UnexpectedEnd);
+        assert_eq!(MethodDescriptor::parse("()VV").unwrap_err(), DescriptorError::Unexpected(3, 'V'));
+        assert_eq!(MethodDescriptor::parse("(V)V").unwrap_err(), DescriptorError::Unexpected(1, 'V'));
+        assert_eq!(Descriptor::parse_field("V").unwrap_err(), DescriptorError::Unexpected(0, 'V'));
+        assert_eq!(Descriptor::parse_field("Ljava//Object;").unwrap_err(), DescriptorError::EmptyName(6));
+        assert_eq!(Descriptor::parse_field("Ljava.lang.Object;").unwrap_err(), DescriptorError::Unexpected(5, '.'));
+        assert_eq!(Descriptor::parse_field("Ljava/lang/Object").unwrap_err(), DescriptorError::UnexpectedEnd);
+
+        // Arrays can have at most 255 dimensions
+        let widest = format!("{}I", "[".repeat(255));
+        assert!(matches!(Descriptor::parse_field(&widest), Ok(Descriptor::Array(ArrayDescriptor { dimensions: 255, .. }))));
+        let too_wide = format!("{}I", "[".repeat(256));
+        assert_eq!(Descriptor::parse_field(&too_wide).unwrap_err(), DescriptorError::TooManyDimensions(0));
+
+        // Malformed descriptors are kept as is by the lenient parser
+        let descriptor = Descriptor::parse("(Ljava/lang/String)V");
+        assert!(matches!(&descriptor, Descriptor::Unknown(value) if value == "(Ljava/lang/String)V"));
+        assert_eq!(descriptor.to_internal_java(), "(Ljava/lang/String)V");
+    }
+
+    #[test]
+    fn descriptor_slots() {
+        // static double wide(long, double, int[])
+        let descriptor = MethodDescriptor::parse("(JD[I)D").unwrap();
+        assert_eq!(descriptor.arguments_size(true), 5);
+        assert_eq!(descriptor.arguments_size(false), 6);
+        assert_eq!(descriptor.parameter_slot(1, true), Some(2));
+        assert_eq!(descriptor.parameter_slot(2, true), Some(4));
+        assert_eq!(descriptor.parameter_slot(2, false), Some(5));
+        assert_eq!(descriptor.parameter_slot(3, true), None);
+        assert_eq!(descriptor.parameter_at_slot(4, true), Some(2));
+        // The second slot of the long and the locals after the parameters
+        assert_eq!(descriptor.parameter_at_slot(1, true), None);
+        assert_eq!(descriptor.parameter_at_slot(5, true), None);
+        assert_eq!(descriptor.parameter_at_slot(0, false), None);
+        assert_eq!(descriptor.return_size(), 2);
+        assert_eq!(descriptor.stack_delta(true), -3);
+        assert_eq!(MethodDescriptor::parse("()V").unwrap().stack_delta(false), -1);
+        assert!(Descriptor::Long.is_wide());
+        assert_eq!(Descriptor::Void.size(), 0);
+
+        let arr = include_bytes!("../tests/Locals.class");
+        let mut class = Class::read(&mut Cursor::new(arr)).unwrap();
+        class.methods.retain(|method| method.name == "pick");
+        let mut out = Vec::new();
+        JavaWriter {}.write_class(&class, &MemoryProvider::new(), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("    public static int pick(long p_0, int p_1) {\n      return p_1;\n"));
+    }
+
     #[test]
     fn access_flag() {
         let ac = &mut AccessFlags::new();
-        ac.set(AccessFlag::Public);
-        assert!(ac.is_set(AccessFlag::Public))
+        ac.set(MethodAccessFlag::Public);
+        assert!(ac.is_set(MethodAccessFlag::Public));
+
+        // The same bit has a different meaning for each kind of structure
+        let flags = AccessFlags(0x0041);
+        assert_eq!(flags.flags::<FieldAccessFlag>(), vec![FieldAccessFlag::Public, FieldAccessFlag::Volatile]);
+        assert_eq!(flags.flags::<MethodAccessFlag>(), vec![MethodAccessFlag::Public, MethodAccessFlag::Bridge]);
+        assert_eq!(flags.modifiers::<MethodAccessFlag>(), "public ");
+        assert_eq!(AccessFlags(0x8000).flags::<ParameterAccessFlag>(), vec![ParameterAccessFlag::Mandated]);
+        assert_eq!(ModuleAccessFlag::Mandated.name(), "ACC_MANDATED");
+        assert!(AccessFlags(0x8000).is_set(ClassAccessFlag::Module));
+    }
+
+    #[test]
+    fn access_modifiers() {
+        let arr = include_bytes!("../tests/Modifiers.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let mut out = Vec::new();
+        JavaWriter {}.write_class(&class, &MemoryProvider::new(), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("public abstract class Modifiers {\n"));
+        assert!(text.contains("    protected transient int cached;\n"));
+        assert!(text.contains("    private volatile boolean running;\n"));
+        assert!(text.contains("    public abstract void run();\n"));
+        assert!(text.contains("    public static native long address();\n"));
+        assert!(text.contains("    public synchronized void stop() {\n"));
+        assert!(text.contains("    public strictfp double half(double p_0) {\n"));
+
+        let arr = include_bytes!("../tests/Defaults.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let mut out = Vec::new();
+        JavaWriter {}.write_class(&class, &MemoryProvider::new(), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("\ninterface Defaults {\n"));
+        assert!(text.contains("    public void required();\n"));
+        assert!(text.contains("    public default int optional() {\n"));
+        assert!(text.contains("    public static Defaults none() {\n"));
+
+        // Record constructors name their parameters in MethodParameters
+        let arr = include_bytes!("../tests/Shapes$Group.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let dump = ClassDumper::new().dump_string(&class).unwrap();
+        assert!(dump.contains(&format!("    MethodParameters:\n      {:<30} Flags\n      name\n      shapes\n", "Name")));
+    }
+
+    #[test]
+    fn nested_class_paths() {
+        // Without the InnerClasses attribute the nesting is guessed from the name
+        let guessed = ClassPath::from("a/b/Outer$1Local");
+        assert_eq!(guessed.outer_classes, vec!["Outer"]);
+        assert_eq!(guessed.nesting, vec![NestingKind::TopLevel, NestingKind::Local]);
+        assert_eq!(guessed.simple_name(), "Local");
+        assert_eq!(ClassPath::from("Outer$1").kind(), NestingKind::Anonymous);
+        assert_eq!(ClassPath::from("scala/Foo$").name, "Foo$");
+
+        let arr = include_bytes!("../tests/Nesting.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        let top = class.resolve_class_path("Top$Level");
+        assert_eq!(top.name, "Top$Level");
+        assert_eq!(top.nesting, vec![NestingKind::TopLevel]);
+        assert!(matches!(&class.fields[1].descriptor, Descriptor::Class(path) if path.name == "Top$Level"));
+        let member = class.resolve_class_path("Nesting$Member");
+        assert_eq!(member.nesting, vec![NestingKind::TopLevel, NestingKind::Member]);
+        assert_eq!(member.full_path(), "Nesting.Member");
+        let local = class.resolve_class_path("Nesting$1Local");
+        assert_eq!((local.kind(), local.simple_name()), (NestingKind::Local, "Local"));
+        assert_eq!(class.resolve_class_path("Nesting$1").kind(), NestingKind::Anonymous);
+        // Classes named by signatures are resolved through the same nesting
+        let imports = class.collect_imports();
+        assert!(imports.iter().any(|path| path.name == "Top$Level" && path.outer_classes.is_empty()));
+
+        let arr = include_bytes!("../tests/Top$Level.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        assert_eq!(class.class_path.name, "Top$Level");
+        assert!(class.class_path.outer_classes.is_empty());
+        assert!(matches!(&class.fields[0].descriptor, Descriptor::Class(path) if path.kind() == NestingKind::Member));
+
+        // Local and anonymous classes name their enclosing class in EnclosingMethod
+        let arr = include_bytes!("../tests/Nesting$1Local.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        assert_eq!(class.class_path.kind(), NestingKind::Local);
+        assert_eq!(class.class_path.outer_classes, vec!["Nesting"]);
+        // Views are resolved the same way once converted
+        let view = ClassView::parse(arr).unwrap().to_class().unwrap();
+        assert_eq!(view.class_path.nesting, class.class_path.nesting);
+        assert_eq!(view.class_path.simple_name(), "Local");
+        let mut out = Vec::new();
+        JavaWriter {}.write_class(&class, &MemoryProvider::new(), &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("\nclass Local {\n"));
+        let arr = include_bytes!("../tests/Nesting$1.class");
+        let class = Class::read(&mut Cursor::new(arr)).unwrap();
+        assert_eq!(class.class_path.nesting, vec![NestingKind::TopLevel, NestingKind::Anonymous]);
+        assert_eq!(class.class_path.simple_name(), "");
+    }
+
+    #[test]
+    fn class_versions() {
+        assert_eq!(MajorVersion::from(65), MajorVersion::Java21);
+        assert_eq!(MajorVersion::Java25.feature_level(), 25);
+        assert_eq!(MajorVersion::JavaLE4.feature_level(), 4);
+        assert_eq!(format!("{:?}", MajorVersion::Java21), "Java 21");
+        // Newer releases are kept so they can be written back out
+        assert_eq!(MajorVersion::from(70), MajorVersion::Unknown(70));
+        assert_eq!(MajorVersion::from(70).feature_level(), 26);
+        assert_eq!(u16::from(MajorVersion::Unknown(70)), 70);
+
+        let with_version = |bytes: &[u8], minor: u16, major: u16| -> Vec<u8> {
+            let mut bytes = bytes.to_vec();
+            bytes[4..6].copy_from_slice(&minor.to_be_bytes());
+            bytes[6..8].copy_from_slice(&major.to_be_bytes());
+            bytes
+        };
+        let group = include_bytes!("../tests/Shapes$Group.class");
+        let bytes = with_version(group, 0, 70);
+        let class = Class::read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(class.version.major(), MajorVersion::Unknown(70));
+        let mut out = Vec::new();
+        class.write(&mut out).unwrap();
+        assert_eq!(out, bytes);
+
+        // Records were a preview feature in Java 15 so the Record attribute
+        // is ignored unless the class was compiled with preview features
+        let bytes = with_version(group, 0, 59);
+        let class = Class::read(&mut Cursor::new(&bytes)).unwrap();
+        assert!(!class.is_record());
+        assert!(class.attributes.iter().any(|attribute| attribute.name == "Record"
+            && matches!(attribute.value, AttributeValue::Unknown(_))));
+        let mut out = Vec::new();
+        class.write(&mut out).unwrap();
+        assert_eq!(out, bytes);
+        let view = ClassView::parse(&bytes).unwrap().to_class().unwrap();
+        assert!(!view.is_record());
+        let class = Class::read(&mut Cursor::new(&with_version(group, 0xFFFF, 59))).unwrap();
+        assert!(class.version.is_preview());
+        assert!(class.version.supports(Feature::Records));
+        assert!(!class.version.supports(Feature::PatternSwitch));
+        assert!(class.is_record());
+
+        // Pattern switches were a preview in Java 17 and became standard in 21
+        let patterns = include_bytes!("../tests/Patterns.class");
+        // aload_0; iconst_0; invokedynamic typeSwitch; ireturn
+        let code = CodeAttr {
+            max_stack: 2,
+            max_locals: 1,
+            code: vec![0x2a, 0x03, 0xba, 0, 13, 0, 0, 0xac],
+            exception_table: Vec::new(),
+            attributes: Vec::new(),
+        };
+        let decompile = |bytes: &[u8]| {
+            let class = Class::read(&mut Cursor::new(bytes)).unwrap();
+            let blocks = gen_control_flow_graph(&parse_code(&code.code).unwrap());
+            blocks[&0].decompile(&class.constant_pool, class.bootstrap_methods(), class.version)
+        };
+        assert!(matches!(
+            decompile(&with_version(patterns, 0, 61)),
+            Err(DecompileError::UnsupportedFeature(Feature::PatternSwitch, MajorVersion::Java17))
+        ));
+        // Supported versions get past the gate to the call site itself
+        for bytes in [with_version(patterns, 0, 65), with_version(patterns, 0xFFFF, 61)] {
+            assert!(matches!(decompile(&bytes), Err(DecompileError::Unimplemented("invokedynamic"))));
+        }
+    }
+
+    #[test]
+    fn mutf8_round_trip() {
+        let value = "nul\0 \u{e9} \u{20ac} \u{1F600}";
+        let encoded = mutf8::encode(value);
+        // Null must not be encoded as a raw zero byte and the
+        // supplementary character must be a 6 byte surrogate pair
+        assert!(!encoded.contains(&0));
+        assert_eq!(&encoded[..5], &[b'n', b'u', b'l', 0xC0, 0x80]);
+        assert_eq!(&encoded[encoded.len() - 6..], &[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]);
+        assert_eq!(mutf8::decode(&encoded).unwrap(), value);
+    }
+
+    #[test]
+    fn mutf8_overlong() {
+        // Overlong forms of 'A' and of U+00E9 are rejected but null keeps its two byte form
+        assert_eq!(mutf8::decode(&[0xC1, 0x81]), Err(mutf8::Mutf8Error::Overlong(0)));
+        assert_eq!(mutf8::decode(&[b'a', 0xE0, 0x83, 0xA9]), Err(mutf8::Mutf8Error::Overlong(1)));
+        assert_eq!(mutf8::decode(&[0xC0, 0x80]).unwrap(), "\0");
+
+        let pool = ConstantPool::read(&mut Cursor::new(vec![0, 2, 1, 0, 2, 0xC1, 0x81])).unwrap();
+        assert!(matches!(pool.inner.get(&1), Some(Constant::Utf8Lossy(_, raw)) if raw.as_slice() == [0xC1, 0x81]));
+    }
+
+    #[test]
+    fn mutf8_unpaired_surrogate() {
+        // Lone high surrogate followed by an ascii character
+        let bytes = [0xED, 0xA0, 0xBD, b'a'];
+        assert!(mutf8::decode(&bytes).is_err());
+        assert_eq!(mutf8::decode_lossy(&bytes), "\u{FFFD}a");
+
+        // Pool containing the single utf8 entry
+        let mut data = vec![0, 2, 1, 0, 4];
+        data.extend_from_slice(&bytes);
+        let pool = ConstantPool::read(&mut Cursor::new(data)).unwrap();
+        assert_eq!(pool.get_utf8(&1).unwrap(), "\u{FFFD}a");
+        match pool.inner.get(&1).unwrap() {
+            Constant::Utf8Lossy(value, raw) => {
+                assert_eq!(value, "\u{FFFD}a");
+                assert_eq!(raw.as_slice(), &bytes);
+            }
+            value => panic!("expected lossy utf8 constant got {:?}", value)
+        }
+        assert!(pool.validate().is_empty());
+
+        // Malformed bytes after the surrogate are still reported
+        let mut data = vec![0, 2, 1, 0, 5];
+        data.extend_from_slice(&bytes);
+        data.push(0xFF);
+        let pool = ConstantPool::read(&mut Cursor::new(data)).unwrap();
+        assert_eq!(mutf8::validate(&bytes), Ok(()));
+        assert!(matches!(pool.validate().as_slice(), [ConstantDiagnostic { index: 1, rule: ConstantRule::InvalidUtf8 }]));
+    }
+
+    #[test]
+    fn jar_reader() {
+        let bytes = include_bytes!("../tests/Library.jar");
+        let mut jar = JarReader::new(Cursor::new(&bytes[..])).unwrap();
+        let manifest = jar.manifest();
+        assert_eq!(manifest.main_class(), Some("lib.Greeter"));
+        assert!(manifest.is_multi_release());
+        // Continuation lines are joined and names are case insensitive
+        assert_eq!(manifest.get("implementation-title"), Some("A library with a title long enough to be wrapped"));
+        assert_eq!(manifest.get_entry("lib/Greeter.class", "Sealed"), Some("true"));
+
+        // Without a release only the base entries are used
+        assert!(jar.entry("lib/Greeter.class").unwrap().release.is_none());
+        assert_eq!(jar.class_names().collect::<Vec<_>>(), vec!["lib/Broken", "lib/Greeter", "lib/Helper"]);
+        let resources = jar.resources().map(|entry| entry.name.as_str()).collect::<Vec<_>>();
+        assert!(resources.contains(&"lib/messages.properties"));
+        assert!(resources.contains(&"META-INF/versions/21/lib/Extra.class"));
+        assert_eq!(jar.read("lib/messages.properties").unwrap(), b"greeting=Hello\n");
+        assert!(matches!(jar.read("lib/Missing.class"), Err(JarError::MissingEntry(_))));
+
+        // A broken class is reported without stopping the others
+        let classes = jar.classes().collect::<Vec<_>>();
+        assert_eq!(classes.len(), 3);
+        assert!(matches!(&classes[0], (name, Err(JarError::InvalidClass { .. })) if name == "lib/Broken"));
+        assert!(classes[1..].iter().all(|(_, class)| class.is_ok()));
+        assert_eq!(jar.read_class("lib/Greeter").unwrap().version.major(), MajorVersion::Java8);
+
+        // The highest versioned entry not above the release replaces the base
+        let mut jar = jar.with_release(17);
+        let entry = jar.entry("lib/Greeter.class").unwrap();
+        assert_eq!((entry.path.as_str(), entry.release), ("META-INF/versions/11/lib/Greeter.class", Some(11)));
+        assert_eq!(jar.read_class("lib/Greeter").unwrap().version.major(), MajorVersion::Java11);
+        assert!(jar.entry("lib/Extra.class").is_none());
+        assert!(jar.entry("META-INF/versions/21/lib/Extra.class").is_none());
+        let mut jar = jar.with_release(21);
+        assert!(jar.entry("lib/Extra.class").is_some());
+        assert!(matches!(jar.read_class("lib/Greeter"), Err(JarError::InvalidClass { .. })));
+        let jar = jar.with_release(8);
+        assert!(jar.entry("lib/Greeter.class").unwrap().release.is_none());
+        assert!(jar.entry_errors().is_empty());
+
+        // An entry with a broken local header is reported and the rest are still listed
+        let mut bytes = bytes.to_vec();
+        bytes[605] = 0;
+        let mut jar = JarReader::new(Cursor::new(bytes)).unwrap();
+        assert!(matches!(jar.entry_errors(), [(4, JarError::Zip(_))]));
+        assert!(jar.entry("lib/Helper.class").is_none());
+        assert_eq!(jar.class_names().collect::<Vec<_>>(), vec!["lib/Broken", "lib/Greeter"]);
+        assert!(jar.read_class("lib/Greeter").is_ok());
+    }
+
+    #[test]
+    fn class_providers() {
+        let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+        let directory = DirectoryProvider::new(&tests);
+        let derived = directory.require("Derived").unwrap();
+        assert!(Arc::ptr_eq(&derived, &directory.find("Derived").unwrap().unwrap()));
+        assert!(directory.find("base/Base").unwrap().is_none());
+        assert!(directory.find("../tests/Derived").unwrap().is_none());
+        assert!(matches!(directory.require("Missing"), Err(ProviderError::NotFound(_))));
+        assert!(directory.class_names().unwrap().iter().any(|name| name == "Derived"));
+
+        let jmod = JmodProvider::open(tests.join("Base.jmod")).unwrap();
+        assert_eq!(jmod.class_names().unwrap(), vec!["base/Base", "lib/Greeter", "module-info"]);
+        assert_eq!(jmod.require("lib/Greeter").unwrap().version.major(), MajorVersion::Java11);
+        assert!(matches!(JmodProvider::new(Cursor::new(&include_bytes!("../tests/Library.jar")[..])),
+            Err(ProviderError::InvalidJmodHeader { found: [b'P', b'K', 3, 4], .. })));
+
+        // Every provider parses with the limits it was given
+        let limits = ParseLimits { max_constant_pool_size: 8, ..ParseLimits::default() };
+        let limited = JarProvider::open(tests.join("Library.jar")).unwrap().with_limits(limits);
+        assert!(matches!(limited.find("lib/Greeter"), Err(ProviderError::InvalidClass { .. })));
+        let limited = DirectoryProvider::new(&tests).with_limits(limits);
+        assert!(matches!(limited.find("Derived"), Err(ProviderError::InvalidClass { .. })));
+        let limited = JmodProvider::open(tests.join("Base.jmod")).unwrap().with_limits(limits);
+        assert!(matches!(limited.find("base/Base"), Err(ProviderError::InvalidClass { name, .. }) if name == "base/Base"));
+
+        // Earlier providers shadow the classes of later ones
+        let classpath = CompositeProvider::new()
+            .with(JarProvider::open(tests.join("Library.jar")).unwrap())
+            .with(jmod)
+            .with(directory);
+        assert_eq!(classpath.require("lib/Greeter").unwrap().version.major(), MajorVersion::Java8);
+        assert!(matches!(classpath.find("lib/Broken"), Err(ProviderError::InvalidClass { name, .. }) if name == "lib/Broken"));
+        let names = classpath.class_names().unwrap();
+        assert!(names.contains(&"base/Base".to_string()) && names.contains(&"lib/Helper".to_string()));
+        let super_types: Vec<String> = classpath.super_types(&classpath.require("Derived").unwrap()).unwrap().iter()
+            .map(|class| class.class_path.internal_path())
+            .collect();
+        assert_eq!(super_types, ["base/Base"]);
+
+        // Classes can be looked up from several threads at once
+        let classpath = Arc::new(classpath);
+        let threads: Vec<_> = (0..4).map(|_| {
+            let classpath = classpath.clone();
+            std::thread::spawn(move || classpath.require("base/Base").unwrap())
+        }).collect();
+        let classes: Vec<Arc<Class>> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+        assert!(classes.iter().all(|class| Arc::ptr_eq(class, &classes[0])));
+
+        // Overriding methods are only found when the super types are available
+        let mut out = Vec::new();
+        JavaWriter {}.write_class(&classpath.require("Derived").unwrap(), &*classpath, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("    @Override\n    public String name() {\n"));
+        assert!(!text.contains("@Override\n    void hidden()"));
+        assert!(!text.contains("@Override\n    public static void main("));
+        let mut out = Vec::new();
+        JavaWriter {}.write_class(&derived, &MemoryProvider::new(), &mut out).unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains("@Override"));
     }
 
     #[test]
@@ -47,7 +1347,7 @@ mod tests {
         match v {
             Ok(value) => {
                 let writer = JavaWriter {};
-                match writer.write_class(&value, &mut stdout()) {
+                match writer.write_class(&value, &MemoryProvider::new(), &mut stdout()) {
                     Ok(_) => { println!() }
                     Err(err) => { println!("{:?}",err) }
                 }
@@ -0,0 +1,170 @@
+//! Encoding and decoding of the "modified UTF-8" (MUTF-8) format used by
+//! CONSTANT_Utf8 entries in class files (JVMS 4.4.7).
+//!
+//! Modified UTF-8 differs from standard UTF-8 in two ways: the null character
+//! is encoded using two bytes (0xC0 0x80) and supplementary characters are
+//! encoded as a surrogate pair with each surrogate taking three bytes.
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum Mutf8Error {
+    #[error("invalid modified utf-8 byte 0x{byte:02x} at offset {offset}")]
+    InvalidByte { offset: usize, byte: u8 },
+    #[error("truncated modified utf-8 sequence at offset {0}")]
+    Truncated(usize),
+    #[error("unpaired surrogate 0x{value:04x} at offset {offset}")]
+    UnpairedSurrogate { offset: usize, value: u16 },
+    #[error("overlong modified utf-8 sequence at offset {0}")]
+    Overlong(usize),
+}
+
+/// Decodes a single UTF-16 code unit from the bytes starting at `offset`
+/// returning the code unit and the number of bytes consumed.
+fn decode_unit(bytes: &[u8], offset: usize) -> Result<(u16, usize), Mutf8Error> {
+    let first = bytes[offset];
+    let continuation = |index: usize| -> Result<u16, Mutf8Error> {
+        let byte = *bytes.get(offset + index)
+            .ok_or(Mutf8Error::Truncated(offset))?;
+        if byte & 0xC0 != 0x80 {
+            return Err(Mutf8Error::InvalidByte { offset: offset + index, byte });
+        }
+        Ok((byte & 0x3F) as u16)
+    };
+    match first {
+        // A raw null byte is never present in modified utf-8
+        0x00 => Err(Mutf8Error::InvalidByte { offset, byte: first }),
+        0x01..=0x7F => Ok((first as u16, 1)),
+        0xC0..=0xDF => {
+            let second = continuation(1)?;
+            let unit = ((first & 0x1F) as u16) << 6 | second;
+            // Null (0xC0 0x80) is the only character allowed a longer encoding
+            if unit < 0x80 && unit != 0 {
+                return Err(Mutf8Error::Overlong(offset));
+            }
+            Ok((unit, 2))
+        }
+        0xE0..=0xEF => {
+            let second = continuation(1)?;
+            let third = continuation(2)?;
+            let unit = ((first & 0x0F) as u16) << 12 | second << 6 | third;
+            if unit < 0x800 {
+                return Err(Mutf8Error::Overlong(offset));
+            }
+            Ok((unit, 3))
+        }
+        _ => Err(Mutf8Error::InvalidByte { offset, byte: first }),
+    }
+}
+
+fn is_high_surrogate(unit: u16) -> bool { (0xD800..=0xDBFF).contains(&unit) }
+
+fn is_low_surrogate(unit: u16) -> bool { (0xDC00..=0xDFFF).contains(&unit) }
+
+/// Attempts to decode a low surrogate at `offset` to pair with the high
+/// surrogate `high` returning the combined character and the number of
+/// bytes used by the low surrogate
+fn pair_surrogate(bytes: &[u8], offset: usize, high: u16) -> Option<(char, usize)> {
+    if offset >= bytes.len() {
+        return None;
+    }
+    let (low, length) = decode_unit(bytes, offset).ok()?;
+    if !is_low_surrogate(low) {
+        return None;
+    }
+    let value = 0x10000 + (((high as u32) - 0xD800) << 10) + ((low as u32) - 0xDC00);
+    char::from_u32(value).map(|c| (c, length))
+}
+
+/// Decodes the provided modified utf-8 bytes into a string. Fails if the
+/// bytes are malformed, use a longer encoding than required or contain
+/// surrogates that cannot be paired.
+pub fn decode(bytes: &[u8]) -> Result<String, Mutf8Error> {
+    let mut out = String::with_capacity(bytes.len());
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let (unit, length) = decode_unit(bytes, offset)?;
+        offset += length;
+        if is_high_surrogate(unit) {
+            if let Some((c, low_length)) = pair_surrogate(bytes, offset, unit) {
+                out.push(c);
+                offset += low_length;
+                continue;
+            }
+        }
+        match char::from_u32(unit as u32) {
+            Some(c) => out.push(c),
+            // Only surrogates fail to convert into characters
+            None => return Err(Mutf8Error::UnpairedSurrogate { offset: offset - length, value: unit }),
+        }
+    }
+    Ok(out)
+}
+
+/// Checks that every sequence of the bytes is well formed as the JVM
+/// requires. Unlike `decode` unpaired surrogates are allowed
+pub fn validate(bytes: &[u8]) -> Result<(), Mutf8Error> {
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let (_, length) = decode_unit(bytes, offset)?;
+        offset += length;
+    }
+    Ok(())
+}
+
+/// Decodes the provided modified utf-8 bytes replacing any malformed
+/// sequences or unpaired surrogates with U+FFFD. This never fails
+/// but is lossy so the original bytes must be kept if they need to
+/// be reproduced.
+pub fn decode_lossy(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let (unit, length) = match decode_unit(bytes, offset) {
+            Ok(value) => value,
+            Err(_) => {
+                out.push(char::REPLACEMENT_CHARACTER);
+                offset += 1;
+                continue;
+            }
+        };
+        offset += length;
+        if is_high_surrogate(unit) {
+            if let Some((c, low_length)) = pair_surrogate(bytes, offset, unit) {
+                out.push(c);
+                offset += low_length;
+                continue;
+            }
+        }
+        out.push(char::from_u32(unit as u32).unwrap_or(char::REPLACEMENT_CHARACTER));
+    }
+    out
+}
+
+/// Encodes the provided string as modified utf-8
+pub fn encode(value: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(value.len());
+    for c in value.chars() {
+        match c as u32 {
+            0x01..=0x7F => out.push(c as u8),
+            // Null and anything below 0x800 are encoded using two bytes
+            value @ (0x00 | 0x80..=0x7FF) => {
+                out.push(0xC0 | (value >> 6) as u8);
+                out.push(0x80 | (value & 0x3F) as u8);
+            }
+            value @ 0x800..=0xFFFF => encode_unit(&mut out, value as u16),
+            value => {
+                // Supplementary characters are written as a surrogate pair
+                let value = value - 0x10000;
+                encode_unit(&mut out, 0xD800 | (value >> 10) as u16);
+                encode_unit(&mut out, 0xDC00 | (value & 0x3FF) as u16);
+            }
+        }
+    }
+    out
+}
+
+fn encode_unit(out: &mut Vec<u8>, unit: u16) {
+    out.push(0xE0 | (unit >> 12) as u8);
+    out.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+    out.push(0x80 | (unit & 0x3F) as u8);
+}
@@ -0,0 +1,348 @@
+//! Finding classes by their internal name (e.g. java/util/Map$Entry) for
+//! anything which needs more than the class being decompiled such as
+//! looking up super types. Providers cache the classes they parse so they
+//! can be shared between threads and looked up repeatedly
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::class::class::Class;
+use crate::error::{JarError, ProviderError};
+use crate::io::ParseLimits;
+use crate::jar::JarReader;
+
+/// The magic number and version at the start of a jmod file. The rest of
+/// the file is a zip archive
+pub const JMOD_HEADER: [u8; 4] = [b'J', b'M', 1, 0];
+
+// The directory of a jmod holding the classes of the module
+const JMOD_CLASSES: &str = "classes/";
+
+pub trait ClassProvider: Send + Sync {
+    /// Finds the class with the internal name. Ok(None) when the provider
+    /// doesn't have the class and an error if it has it but can't read it
+    fn find(&self, internal_name: &str) -> Result<Option<Arc<Class>>, ProviderError>;
+
+    /// The internal names of every class the provider can find
+    fn class_names(&self) -> Result<Vec<String>, ProviderError>;
+
+    /// Like `find` but a missing class is an error
+    fn require(&self, internal_name: &str) -> Result<Arc<Class>, ProviderError> {
+        self.find(internal_name)?
+            .ok_or_else(|| ProviderError::NotFound(internal_name.to_string()))
+    }
+
+    /// The super classes and interfaces of the class including indirect
+    /// ones. Each is listed once with the closest first and super classes
+    /// before interfaces. Super types the provider doesn't have are left
+    /// out along with their own super types
+    fn super_types(&self, class: &Class) -> Result<Vec<Arc<Class>>, ProviderError> {
+        let mut out = Vec::new();
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        let direct_super_types = |class: &Class| class.super_class_path.iter()
+            .chain(class.interfaces.iter())
+            .map(|path| path.internal_path())
+            .collect::<Vec<_>>();
+        visited.insert(class.class_path.internal_path());
+        queue.extend(direct_super_types(class));
+        while let Some(name) = queue.pop_front() {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            if let Some(super_type) = self.find(&name)? {
+                queue.extend(direct_super_types(&super_type));
+                out.push(super_type);
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl<P: ClassProvider + ?Sized> ClassProvider for Arc<P> {
+    fn find(&self, internal_name: &str) -> Result<Option<Arc<Class>>, ProviderError> {
+        (**self).find(internal_name)
+    }
+
+    fn class_names(&self) -> Result<Vec<String>, ProviderError> {
+        (**self).class_names()
+    }
+}
+
+/// The classes a provider has already looked up. Classes which weren't
+/// found are remembered too so that classpaths don't search for them again.
+/// Errors aren't cached
+#[derive(Debug, Default)]
+pub struct ClassCache {
+    classes: RwLock<HashMap<String, Option<Arc<Class>>>>,
+}
+
+impl ClassCache {
+    pub fn new() -> ClassCache { ClassCache::default() }
+
+    /// Gets the cached class or loads it. Threads looking up the same class
+    /// at the same time may both load it but only the first is kept
+    pub fn get_or_load<F>(&self, internal_name: &str, load: F) -> Result<Option<Arc<Class>>, ProviderError>
+        where F: FnOnce() -> Result<Option<Class>, ProviderError> {
+        if let Some(class) = self.classes.read().unwrap_or_else(|err| err.into_inner()).get(internal_name) {
+            return Ok(class.clone());
+        }
+        let class = load()?.map(Arc::new);
+        let mut classes = self.classes.write().unwrap_or_else(|err| err.into_inner());
+        Ok(classes.entry(internal_name.to_string()).or_insert(class).clone())
+    }
+
+    pub fn clear(&self) {
+        self.classes.write().unwrap_or_else(|err| err.into_inner()).clear()
+    }
+}
+
+/// Classes which have already been read, keyed by their internal name
+#[derive(Debug, Default)]
+pub struct MemoryProvider {
+    classes: HashMap<String, Arc<Class>>,
+}
+
+impl MemoryProvider {
+    pub fn new() -> MemoryProvider { MemoryProvider::default() }
+
+    /// Adds the class replacing any class with the same name
+    pub fn insert(&mut self, class: Class) {
+        self.classes.insert(class.class_path.internal_path(), Arc::new(class));
+    }
+}
+
+impl From<Class> for MemoryProvider {
+    fn from(class: Class) -> Self {
+        let mut provider = MemoryProvider::new();
+        provider.insert(class);
+        provider
+    }
+}
+
+impl ClassProvider for MemoryProvider {
+    fn find(&self, internal_name: &str) -> Result<Option<Arc<Class>>, ProviderError> {
+        Ok(self.classes.get(internal_name).cloned())
+    }
+
+    fn class_names(&self) -> Result<Vec<String>, ProviderError> {
+        let mut names: Vec<String> = self.classes.keys().cloned().collect();
+        names.sort();
+        Ok(names)
+    }
+}
+
+/// Classes in a directory laid out by package (e.g. the output directory
+/// of javac)
+#[derive(Debug)]
+pub struct DirectoryProvider {
+    root: PathBuf,
+    limits: ParseLimits,
+    cache: ClassCache,
+}
+
+impl DirectoryProvider {
+    pub fn new<P: Into<PathBuf>>(root: P) -> DirectoryProvider {
+        DirectoryProvider { root: root.into(), limits: ParseLimits::default(), cache: ClassCache::new() }
+    }
+
+    /// The limits used when parsing the classes
+    pub fn with_limits(mut self, limits: ParseLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // Collects the names of the classes below the directory
+    fn collect_names(&self, directory: &Path, prefix: &str, out: &mut Vec<String>) -> Result<(), ProviderError> {
+        for entry in std::fs::read_dir(directory)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_dir() {
+                self.collect_names(&entry.path(), &format!("{}{}/", prefix, name), out)?;
+            } else if let Some(name) = name.strip_suffix(".class") {
+                out.push(format!("{}{}", prefix, name));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ClassProvider for DirectoryProvider {
+    fn find(&self, internal_name: &str) -> Result<Option<Arc<Class>>, ProviderError> {
+        // Names which could point outside of the directory are never found
+        if internal_name.split('/').any(|segment| segment.is_empty() || segment == "." || segment == "..") {
+            return Ok(None);
+        }
+        self.cache.get_or_load(internal_name, || {
+            let path = self.root.join(format!("{}.class", internal_name));
+            let file = match File::open(path) {
+                Ok(file) => file,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+            Class::read_with_limits(&mut BufReader::new(file), &self.limits)
+                .map(Some)
+                .map_err(|source| ProviderError::InvalidClass { name: internal_name.to_string(), source })
+        })
+    }
+
+    fn class_names(&self) -> Result<Vec<String>, ProviderError> {
+        let mut names = Vec::new();
+        self.collect_names(&self.root, "", &mut names)?;
+        names.sort();
+        Ok(names)
+    }
+}
+
+/// Reports classes an archive has but can't parse with the same error
+/// as the other providers
+fn class_error(internal_name: &str, err: JarError) -> ProviderError {
+    match err {
+        JarError::InvalidClass { source, .. } => ProviderError::InvalidClass { name: internal_name.to_string(), source },
+        err => err.into(),
+    }
+}
+
+/// The classes of a jar. Multi-release jars use the entries for the
+/// release chosen for the `JarReader`
+pub struct JarProvider<R: Read + Seek + Send> {
+    // Reading entries needs exclusive access to the archive
+    jar: Mutex<JarReader<R>>,
+    cache: ClassCache,
+}
+
+impl JarProvider<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ProviderError> {
+        Ok(JarProvider::new(JarReader::open(path)?))
+    }
+}
+
+impl<R: Read + Seek + Send> JarProvider<R> {
+    pub fn new(jar: JarReader<R>) -> JarProvider<R> {
+        JarProvider { jar: Mutex::new(jar), cache: ClassCache::new() }
+    }
+
+    /// The limits used when parsing the classes
+    pub fn with_limits(self, limits: ParseLimits) -> Self {
+        let jar = self.jar.into_inner().unwrap_or_else(|err| err.into_inner());
+        JarProvider { jar: Mutex::new(jar.with_limits(limits)), cache: self.cache }
+    }
+}
+
+impl<R: Read + Seek + Send> ClassProvider for JarProvider<R> {
+    fn find(&self, internal_name: &str) -> Result<Option<Arc<Class>>, ProviderError> {
+        self.cache.get_or_load(internal_name, || {
+            let mut jar = self.jar.lock().unwrap_or_else(|err| err.into_inner());
+            let entry = jar.entry(&format!("{}.class", internal_name));
+            if entry.is_none_or(|entry| !entry.is_class()) {
+                return Ok(None);
+            }
+            jar.read_class(internal_name)
+                .map(Some)
+                .map_err(|err| class_error(internal_name, err))
+        })
+    }
+
+    fn class_names(&self) -> Result<Vec<String>, ProviderError> {
+        let jar = self.jar.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(jar.class_names().map(str::to_string).collect())
+    }
+}
+
+/// The classes of a jmod file (e.g. the modules of the JDK in its jmods
+/// directory). These are stored under classes/ in the archive
+pub struct JmodProvider<R: Read + Seek + Send> {
+    jmod: Mutex<JarReader<R>>,
+    cache: ClassCache,
+}
+
+impl JmodProvider<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ProviderError> {
+        JmodProvider::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek + Send> JmodProvider<R> {
+    /// Checks the header of the jmod before opening the archive after it
+    pub fn new(mut reader: R) -> Result<JmodProvider<R>, ProviderError> {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header)?;
+        if header != JMOD_HEADER {
+            return Err(ProviderError::InvalidJmodHeader { found: header, expected: JMOD_HEADER });
+        }
+        Ok(JmodProvider { jmod: Mutex::new(JarReader::new(reader)?), cache: ClassCache::new() })
+    }
+
+    /// The limits used when parsing the classes
+    pub fn with_limits(self, limits: ParseLimits) -> Self {
+        let jmod = self.jmod.into_inner().unwrap_or_else(|err| err.into_inner());
+        JmodProvider { jmod: Mutex::new(jmod.with_limits(limits)), cache: self.cache }
+    }
+}
+
+impl<R: Read + Seek + Send> ClassProvider for JmodProvider<R> {
+    fn find(&self, internal_name: &str) -> Result<Option<Arc<Class>>, ProviderError> {
+        self.cache.get_or_load(internal_name, || {
+            let mut jmod = self.jmod.lock().unwrap_or_else(|err| err.into_inner());
+            let class_name = format!("{}{}", JMOD_CLASSES, internal_name);
+            if jmod.entry(&format!("{}.class", class_name)).is_none() {
+                return Ok(None);
+            }
+            jmod.read_class(&class_name)
+                .map(Some)
+                .map_err(|err| class_error(internal_name, err))
+        })
+    }
+
+    fn class_names(&self) -> Result<Vec<String>, ProviderError> {
+        let jmod = self.jmod.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(jmod.entries()
+            .filter_map(|entry| entry.name.strip_prefix(JMOD_CLASSES)?.strip_suffix(".class"))
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+/// A classpath made of other providers. Classes are looked up in the order
+/// the providers were added so earlier providers shadow later ones
+#[derive(Default)]
+pub struct CompositeProvider {
+    providers: Vec<Box<dyn ClassProvider>>,
+}
+
+impl CompositeProvider {
+    pub fn new() -> CompositeProvider { CompositeProvider::default() }
+
+    /// Adds the provider after the existing ones
+    pub fn with<P: ClassProvider + 'static>(mut self, provider: P) -> Self {
+        self.push(provider);
+        self
+    }
+
+    pub fn push<P: ClassProvider + 'static>(&mut self, provider: P) {
+        self.providers.push(Box::new(provider))
+    }
+}
+
+impl ClassProvider for CompositeProvider {
+    /// The class from the first provider which has it. A class which can't
+    /// be read is an error rather than falling through to later providers
+    fn find(&self, internal_name: &str) -> Result<Option<Arc<Class>>, ProviderError> {
+        for provider in &self.providers {
+            if let Some(class) = provider.find(internal_name)? {
+                return Ok(Some(class));
+            }
+        }
+        Ok(None)
+    }
+
+    fn class_names(&self) -> Result<Vec<String>, ProviderError> {
+        let mut names = BTreeSet::new();
+        for provider in &self.providers {
+            names.extend(provider.class_names()?);
+        }
+        Ok(names.into_iter().collect())
+    }
+}
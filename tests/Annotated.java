@@ -0,0 +1,54 @@
+import java.lang.annotation.ElementType;
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+
+@Annotated.Values(name = "class", kind = ElementType.TYPE, type = String[].class, tags = {"a", "b"}, nested = @Annotated.Tag("inner"))
+@Deprecated
+public class Annotated {
+
+    @Retention(RetentionPolicy.RUNTIME)
+    public @interface Values {
+        String name();
+
+        byte b() default 1;
+
+        char c() default 'c';
+
+        double d() default 1.5;
+
+        float f() default 2.5f;
+
+        int i() default 3;
+
+        long j() default 4L;
+
+        short s() default 5;
+
+        boolean z() default true;
+
+        ElementType kind() default ElementType.FIELD;
+
+        Class<?> type() default void.class;
+
+        String[] tags() default {};
+
+        Tag nested() default @Tag("default");
+    }
+
+    @Retention(RetentionPolicy.RUNTIME)
+    public @interface Tag {
+        String value();
+    }
+
+    // Class retention so stored in the invisible attributes
+    public @interface Marker {
+    }
+
+    @Marker
+    @Deprecated
+    public int field;
+
+    @Tag("method")
+    public void method(@Marker String a, @Tag("b") int b) {
+    }
+}
@@ -0,0 +1,16 @@
+import base.Base;
+
+public class Derived extends Base implements Runnable {
+    public String name() {
+        return "derived";
+    }
+
+    public void run() {
+    }
+
+    void hidden() {
+    }
+
+    public static void main(String[] args) {
+    }
+}
@@ -0,0 +1,65 @@
+import java.util.ArrayList;
+import java.util.List;
+
+public class Frames {
+
+    private final int value;
+
+    // The branch happens before the super constructor is called
+    public Frames(boolean flag) {
+        this(flag ? 1 : 2);
+    }
+
+    public Frames(int value) {
+        this.value = value;
+    }
+
+    public static Object uninitialized(boolean flag) {
+        return new StringBuilder(flag ? "a" : "b");
+    }
+
+    public static double wide(long a, double b, int[] values) {
+        double total = 0;
+        for (int value : values) {
+            if (value > a) {
+                total += value;
+            }
+        }
+        return total + b;
+    }
+
+    public static String nulls(Object value) {
+        String result = null;
+        if (value instanceof String) {
+            result = (String) value;
+        }
+        return result;
+    }
+
+    public static int extended(int value) {
+        if (value > 0) {
+            value = value * 31 + value / 7 - value % 3 + value * 31 + value / 7 - value % 3
+                    + value * 31 + value / 7 - value % 3 + value * 31 + value / 7 - value % 3
+                    + value * 31 + value / 7 - value % 3 + value * 31 + value / 7 - value % 3;
+        }
+        return value;
+    }
+
+    public static List<String> full(int count) {
+        List<String> out = new ArrayList<>();
+        for (int i = 0; i < count; i++) {
+            long wide = i;
+            if (wide > 3) {
+                String text = "x" + wide;
+                out.add(text);
+                continue;
+            }
+            try {
+                out.add(String.valueOf(i));
+            } catch (RuntimeException e) {
+                break;
+            }
+        }
+        return out;
+    }
+}
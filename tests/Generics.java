@@ -0,0 +1,35 @@
+import java.io.Serializable;
+import java.util.AbstractMap;
+import java.util.List;
+import java.util.Map;
+import java.util.Set;
+import java.util.function.Supplier;
+
+public class Generics<K extends Comparable<K>, V> extends AbstractMap<K, V> implements Serializable {
+
+    public class Node<T extends Runnable & Serializable> {
+        public Generics<K, V>.Node<T> next;
+        public T value;
+    }
+
+    public Map<String, ? extends List<K>> index;
+    public List<? super V>[] buckets;
+    public Generics<K, V>.Node<?> head;
+    public Supplier<?> supplier;
+
+    public Set<Map.Entry<K, V>> entrySet() {
+        return null;
+    }
+
+    public static <T, E extends Exception> T attempt(Supplier<T> supplier, E error) throws E {
+        if (supplier == null) {
+            throw error;
+        }
+        return supplier.get();
+    }
+
+    @SafeVarargs
+    public final <T> int count(List<? extends T>... values) {
+        return values.length;
+    }
+}
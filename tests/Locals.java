@@ -0,0 +1,30 @@
+import java.util.List;
+import java.util.Map;
+
+// Compiled with javac -g so the local variable tables are included
+public class Locals<T> {
+
+    public int sum(List<Integer> values) {
+        int total = 0;
+        for (Integer value : values) {
+            total += value;
+        }
+        return total;
+    }
+
+    public T first(Map<String, ? extends T> map, String[] keys) {
+        for (String key : keys) {
+            T found = map.get(key);
+            if (found != null) {
+                return found;
+            }
+        }
+        List<T>[] empty = null;
+        return empty == null ? null : empty[0].get(0);
+    }
+
+    // The value is in slot 2 as the long before it takes two slots
+    public static int pick(long skip, int value) {
+        return value;
+    }
+}
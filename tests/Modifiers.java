@@ -0,0 +1,29 @@
+// javac --release 16 Modifiers.java (strictfp only sets ACC_STRICT before 17)
+public abstract class Modifiers {
+    protected transient int cached;
+    private volatile boolean running;
+
+    public abstract void run();
+
+    public static native long address();
+
+    public synchronized void stop() {
+        return;
+    }
+
+    public strictfp double half(double value) {
+        return value;
+    }
+}
+
+interface Defaults {
+    void required();
+
+    default int optional() {
+        return 1;
+    }
+
+    static Defaults none() {
+        return null;
+    }
+}
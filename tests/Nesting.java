@@ -0,0 +1,23 @@
+public class Nesting {
+    Member member;
+    Top$Level top;
+    java.util.List<Top$Level> tops;
+
+    class Member {
+    }
+
+    Object anonymous() {
+        return new Object() {
+        };
+    }
+
+    Object local() {
+        class Local {
+        }
+        return new Local();
+    }
+}
+
+class Top$Level {
+    Nesting.Member member;
+}
@@ -0,0 +1,9 @@
+// javac --release 17 --enable-preview Patterns.java
+public class Patterns {
+    static String describe(Object value) {
+        return switch (value) {
+            case Integer i -> "int";
+            default -> "other";
+        };
+    }
+}
@@ -0,0 +1,31 @@
+import java.util.List;
+
+public class Shapes {
+
+    public sealed interface Shape permits Circle, Square, Group {
+    }
+
+    public record Circle(double radius) implements Shape {
+    }
+
+    public static final class Square implements Shape {
+        private final int side;
+
+        public Square(int side) {
+            this.side = side;
+        }
+    }
+
+    // Components can be annotated and generic
+    public record Group(@Deprecated String name, List<Shape> shapes) implements Shape {
+        public Group {
+            shapes = List.copyOf(shapes);
+        }
+    }
+
+    private int secret;
+
+    public static int area(Square square) {
+        return square.side * square.side;
+    }
+}
@@ -0,0 +1,65 @@
+import java.io.IOException;
+import java.io.StringReader;
+import java.lang.annotation.ElementType;
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.lang.annotation.Target;
+import java.util.ArrayList;
+import java.util.List;
+import java.util.function.Supplier;
+
+public class TypeAnnotated<@NonNull T extends @Nullable Object>
+        extends @NonNull Object
+        implements @Nullable Runnable {
+
+    public @NonNull String name;
+
+    public String @NonNull [] @Nullable [] values;
+
+    public List<@NonNull String> list;
+
+    @Both
+    public String both;
+
+    public @NonNull String method(@NonNull TypeAnnotated<T> this, @Nullable String value) throws @NonNull IOException {
+        return value;
+    }
+
+    public String body(String value) throws IOException {
+        @NonNull Object local = value;
+        try (@NonNull StringReader reader = new StringReader(value)) {
+            if (local instanceof @NonNull String) {
+                return (@NonNull String) local;
+            }
+        } catch (@Nullable IllegalStateException e) {
+            return null;
+        }
+        List<String> list = new @NonNull ArrayList<>();
+        Supplier<List<String>> supplier = @NonNull ArrayList::new;
+        return this.<@NonNull String>generic(list);
+    }
+
+    public <@Nullable U extends @NonNull Comparable<U>> U generic(Object value) {
+        return null;
+    }
+
+    @Override
+    public void run() {
+    }
+}
+
+@Retention(RetentionPolicy.RUNTIME)
+@Target({ElementType.TYPE_USE, ElementType.TYPE_PARAMETER})
+@interface NonNull {
+}
+
+// Class retention so stored in the invisible attributes
+@Target({ElementType.TYPE_USE, ElementType.TYPE_PARAMETER})
+@interface Nullable {
+}
+
+// Applies to both the declaration and the type
+@Retention(RetentionPolicy.RUNTIME)
+@Target({ElementType.TYPE_USE, ElementType.FIELD})
+@interface Both {
+}
@@ -0,0 +1,14 @@
+// Compiled along with com.example.app.Main, com.example.app.Other and
+// com.example.app.spi.Service using --module-version 1.2 then packaged
+// with jar --main-class com.example.app.Main to add ModuleMainClass
+@Deprecated
+module com.example.app {
+    requires transitive java.logging;
+    requires static java.sql;
+    requires java.net.http;
+    exports com.example.app;
+    exports com.example.app.spi to java.logging, java.sql;
+    opens com.example.app.spi to java.logging;
+    uses com.example.app.spi.Service;
+    provides com.example.app.spi.Service with com.example.app.Main, com.example.app.Other;
+}